@@ -10,6 +10,7 @@ use xshell::{cmd, Shell};
 pub(crate) const INTEGRATION_TEST_LABEL: &str = "bcvk.integration-test=1";
 
 mod tests {
+    pub mod ephemeral_test;
     pub mod libvirt_upload_disk;
     pub mod libvirt_verb;
     pub mod mount_feature;
@@ -132,14 +133,86 @@ fn main() {
             tests::run_ephemeral::test_run_ephemeral_execute();
             Ok(())
         }),
+        Trial::test("run_ephemeral_execute_script", || {
+            tests::run_ephemeral::test_run_ephemeral_execute_script();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_execute_result", || {
+            tests::run_ephemeral::test_run_ephemeral_execute_result();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_artifacts_dir", || {
+            tests::run_ephemeral::test_run_ephemeral_artifacts_dir();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_execute_timeout", || {
+            tests::run_ephemeral::test_run_ephemeral_execute_timeout();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_credential", || {
+            tests::run_ephemeral::test_run_ephemeral_credential();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_systemd_units_tree", || {
+            tests::run_ephemeral::test_run_ephemeral_systemd_units_tree();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_copy_in", || {
+            tests::run_ephemeral::test_run_ephemeral_copy_in();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_state_disk", || {
+            tests::run_ephemeral::test_run_ephemeral_state_disk();
+            Ok(())
+        }),
         Trial::test("run_ephemeral_container_ssh_access", || {
             tests::run_ephemeral::test_run_ephemeral_container_ssh_access();
             Ok(())
         }),
+        Trial::test("run_ephemeral_exec", || {
+            tests::run_ephemeral::test_run_ephemeral_exec();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_logs", || {
+            tests::run_ephemeral::test_run_ephemeral_logs();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_console", || {
+            tests::run_ephemeral::test_run_ephemeral_console();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_lifecycle", || {
+            tests::run_ephemeral::test_run_ephemeral_lifecycle();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_wait", || {
+            tests::run_ephemeral::test_run_ephemeral_wait();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_boot_report", || {
+            tests::run_ephemeral::test_run_ephemeral_boot_report();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_inspect", || {
+            tests::run_ephemeral::test_run_ephemeral_inspect();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_ps", || {
+            tests::run_ephemeral::test_run_ephemeral_ps();
+            Ok(())
+        }),
+        Trial::test("run_ephemeral_cp", || {
+            tests::run_ephemeral::test_run_ephemeral_cp();
+            Ok(())
+        }),
         Trial::test("run_ephemeral_ssh_command", || {
             tests::run_ephemeral_ssh::test_run_ephemeral_ssh_command();
             Ok(())
         }),
+        Trial::test("run_ephemeral_ssh_user", || {
+            tests::run_ephemeral_ssh::test_run_ephemeral_ssh_user();
+            Ok(())
+        }),
         Trial::test("run_ephemeral_ssh_cleanup", || {
             tests::run_ephemeral_ssh::test_run_ephemeral_ssh_cleanup();
             Ok(())
@@ -152,10 +225,18 @@ fn main() {
             tests::run_ephemeral_ssh::test_run_ephemeral_ssh_exit_code();
             Ok(())
         }),
+        Trial::test("run_ephemeral_ssh_boot_failure", || {
+            tests::run_ephemeral_ssh::test_run_ephemeral_ssh_boot_failure();
+            Ok(())
+        }),
         Trial::test("run_ephemeral_ssh_cross_distro_compatibility", || {
             tests::run_ephemeral_ssh::test_run_ephemeral_ssh_cross_distro_compatibility();
             Ok(())
         }),
+        Trial::test("ephemeral_test_reports", || {
+            tests::ephemeral_test::test_ephemeral_test_reports();
+            Ok(())
+        }),
         Trial::test("mount_feature_bind", || {
             tests::mount_feature::test_mount_feature_bind();
             Ok(())
@@ -164,6 +245,10 @@ fn main() {
             tests::mount_feature::test_mount_feature_ro_bind();
             Ok(())
         }),
+        Trial::test("mount_feature_bind_guest_path", || {
+            tests::mount_feature::test_mount_feature_bind_guest_path();
+            Ok(())
+        }),
         Trial::test("to_disk", || {
             tests::to_disk::test_to_disk();
             Ok(())
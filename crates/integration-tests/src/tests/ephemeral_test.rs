@@ -0,0 +1,64 @@
+//! Integration tests for `bcvk ephemeral test`
+
+use std::os::unix::fs::PermissionsExt;
+use std::process::Command;
+
+use crate::{get_bck_command, get_test_image, INTEGRATION_TEST_LABEL};
+
+pub fn test_ephemeral_test_reports() {
+    let bck = get_bck_command().unwrap();
+    let td = tempfile::tempdir().unwrap();
+    let testdir = td.path().join("tests");
+    std::fs::create_dir(&testdir).unwrap();
+    // Not executable, so it runs with /bin/sh
+    std::fs::write(testdir.join("01-pass.sh"), "test -d /usr\necho all good\n").unwrap();
+    let failing = testdir.join("02-fail");
+    std::fs::write(&failing, "#!/bin/sh\necho broken >&2\nexit 2\n").unwrap();
+    std::fs::set_permissions(&failing, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(testdir.join("README.md"), "not a test\n").unwrap();
+    let junit = td.path().join("junit.xml");
+
+    let output = Command::new("timeout")
+        .args([
+            "300s",
+            &bck,
+            "ephemeral",
+            "test",
+            "--label",
+            INTEGRATION_TEST_LABEL,
+            "--test-timeout",
+            "60s",
+            "--junit",
+            junit.to_str().unwrap(),
+            &get_test_image(),
+            testdir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral test");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("ephemeral test stdout: {}", stdout);
+    eprintln!("ephemeral test stderr: {}", stderr);
+
+    assert_eq!(
+        output.status.code(),
+        Some(1),
+        "expected failure exit for a failing test: {stderr}"
+    );
+    assert!(stdout.contains("1..2\n"), "wrong test count: {stdout}");
+    assert!(stdout.contains("ok 1 - 01-pass.sh"), "TAP: {stdout}");
+    assert!(stdout.contains("not ok 2 - 02-fail"), "TAP: {stdout}");
+    assert!(stdout.contains("exit_code: 2"), "TAP: {stdout}");
+    assert!(stdout.contains("broken"), "stderr not captured: {stdout}");
+
+    let junit = std::fs::read_to_string(&junit).unwrap();
+    assert!(
+        junit.contains(r#"tests="2" failures="1" errors="0""#),
+        "JUnit: {junit}"
+    );
+    assert!(
+        junit.contains("<system-out>all good"),
+        "stdout not captured: {junit}"
+    );
+}
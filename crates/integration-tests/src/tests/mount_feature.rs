@@ -181,3 +181,57 @@ pub fn test_mount_feature_ro_bind() {
     let stdout = String::from_utf8_lossy(&output.stdout);
     assert!(stdout.contains("ok mount verify"));
 }
+
+pub fn test_mount_feature_bind_guest_path() {
+    let bck = get_bck_command().unwrap();
+
+    let temp_dir = TempDir::new().expect("Failed to create temp directory");
+    let temp_dir_path = Utf8Path::from_path(temp_dir.path()).expect("temp dir path is not utf8");
+    fs::write(temp_dir_path.join("test.txt"), "guest path content")
+        .expect("Failed to write test file");
+
+    // Mount at an arbitrary guest path, read-only with a non-default cache mode
+    let output = Command::new("timeout")
+        .args([
+            "120s",
+            &bck,
+            "ephemeral",
+            "run",
+            "--rm",
+            "--label",
+            INTEGRATION_TEST_LABEL,
+            "--bind",
+            &format!("{}:/var/lib/bcvk-test:ro,cache=auto", temp_dir_path),
+            "--execute",
+            "/bin/sh -c 'cat /var/lib/bcvk-test/test.txt; echo; findmnt -no FSTYPE,OPTIONS /var/lib/bcvk-test'",
+            &get_test_image(),
+        ])
+        .output()
+        .expect("Failed to run bcvk with bind mount at guest path");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "ephemeral run with --bind to guest path failed: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("guest path content"),
+        "Mounted file not found: {}",
+        stdout
+    );
+    let mount_line = stdout
+        .lines()
+        .find(|l| l.starts_with("virtiofs"))
+        .unwrap_or_else(|| panic!("No virtiofs mount at /var/lib/bcvk-test: {}", stdout));
+    assert!(
+        mount_line
+            .split_whitespace()
+            .nth(1)
+            .unwrap()
+            .starts_with("ro"),
+        "Mount is not read-only: {}",
+        mount_line
+    );
+}
@@ -43,6 +43,49 @@ pub fn get_container_kernel_version(image: &str) -> String {
     String::from_utf8_lossy(&output.stdout).trim().to_string()
 }
 
+/// A VM started with `ephemeral run --detach`; the container is removed on
+/// drop, so a failed assertion does not leave the VM running.
+struct DetachedVm {
+    name: String,
+}
+
+impl DetachedVm {
+    /// Start the test image as `<prefix>-test-<pid>`, passing `extra_args` to `ephemeral run`.
+    fn start(prefix: &str, extra_args: &[&str]) -> Self {
+        let vm = Self {
+            name: format!("{prefix}-test-{}", std::process::id()),
+        };
+        let output = Command::new(get_bck_command().unwrap())
+            .args([
+                "ephemeral",
+                "run",
+                "--rm",
+                "--label",
+                INTEGRATION_TEST_LABEL,
+                "--detach",
+                "--name",
+                &vm.name,
+            ])
+            .args(extra_args)
+            .arg(get_test_image())
+            .output()
+            .expect("Failed to start detached VM");
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            panic!("Failed to start detached VM: {}", stderr);
+        }
+        vm
+    }
+}
+
+impl Drop for DetachedVm {
+    fn drop(&mut self) {
+        let _ = Command::new("podman")
+            .args(["rm", "-f", &self.name])
+            .output();
+    }
+}
+
 pub fn test_run_ephemeral_correct_kernel() {
     let image = get_test_image();
     let bck = get_bck_command().unwrap();
@@ -235,53 +278,422 @@ pub fn test_run_ephemeral_execute() {
     eprintln!("Execute test passed: script output captured successfully");
 }
 
-pub fn test_run_ephemeral_container_ssh_access() {
-    let image = get_test_image();
+pub fn test_run_ephemeral_execute_script() {
     let bck = get_bck_command().unwrap();
+    let td = tempfile::tempdir().unwrap();
+    let script_path = td.path().join("test.sh");
+    // No #! line, so this runs with /bin/sh; the pipe and quoting would
+    // need hand-escaping with --execute
+    std::fs::write(
+        &script_path,
+        "echo \"$GREETING from $1\" | tr a-z A-Z\nprintf 'args=%s\\n' \"$#\"\n",
+    )
+    .unwrap();
+    let script_spec = format!("{} 'the vm' second", script_path.display());
 
-    eprintln!("Testing container-based SSH access");
+    let output = Command::new("timeout")
+        .args([
+            "120s",
+            &bck,
+            "ephemeral",
+            "run",
+            "--rm",
+            "--label",
+            INTEGRATION_TEST_LABEL,
+            "--execute-env",
+            "GREETING=hello",
+            "--execute-script",
+            &script_spec,
+            &get_test_image(),
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral run with --execute-script");
 
-    // Generate a unique container name
-    let container_name = format!(
-        "ssh-test-{}",
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("execute script test stdout: {}", stdout);
+    eprintln!("execute script test stderr: {}", stderr);
+
+    assert!(
+        output.status.success(),
+        "ephemeral run with --execute-script failed: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("HELLO FROM THE VM"),
+        "script output not found in stdout: {stdout}"
     );
+    assert!(stdout.contains("args=2"), "wrong argument count: {stdout}");
+}
 
-    eprintln!(
-        "Starting detached VM with container name: {}",
-        container_name
+pub fn test_run_ephemeral_execute_result() {
+    let bck = get_bck_command().unwrap();
+    let td = tempfile::tempdir().unwrap();
+    let result_path = td.path().join("result.json");
+
+    let output = Command::new("timeout")
+        .args([
+            "120s",
+            &bck,
+            "ephemeral",
+            "run",
+            "--rm",
+            "--label",
+            INTEGRATION_TEST_LABEL,
+            "--execute-result",
+            result_path.to_str().unwrap(),
+            "--execute",
+            "/bin/echo first",
+            "--execute",
+            "/bin/sh -c 'echo to-stdout; echo to-stderr >&2; exit 3'",
+            "--execute",
+            "/bin/echo unreached",
+            &get_test_image(),
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral run with --execute-result");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("execute result test stdout: {}", stdout);
+    eprintln!("execute result test stderr: {}", stderr);
+
+    assert!(!output.status.success(), "failing command did not fail");
+    assert!(stdout.contains("to-stdout"), "stdout missing: {stdout}");
+    assert!(stderr.contains("to-stderr"), "stderr missing: {stderr}");
+    assert!(
+        !stdout.contains("to-stderr"),
+        "stderr ended up on stdout: {stdout}"
     );
+    assert!(!stdout.contains("unreached"), "later command ran: {stdout}");
+
+    let result: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&result_path).unwrap()).unwrap();
+    eprintln!("execute result: {result:#}");
+    assert_eq!(result["result"], "exit-code");
+    assert_eq!(result["exit_code"], 3);
+    assert!(result["start_timestamp"].is_string());
+    let commands = result["commands"].as_array().unwrap();
+    assert_eq!(commands.len(), 3);
+    assert_eq!(commands[0]["exit_code"], 0);
+    assert_eq!(commands[1]["exit_code"], 3);
+    assert_eq!(commands[2]["ran"], false);
+}
+
+pub fn test_run_ephemeral_artifacts_dir() {
+    let bck = get_bck_command().unwrap();
+    let td = tempfile::tempdir().unwrap();
+    let artifacts = td.path().join("artifacts");
 
-    // Start VM with SSH in detached mode
-    let output = Command::new(&bck)
+    let output = Command::new("timeout")
         .args([
+            "120s",
+            &bck,
             "ephemeral",
             "run",
-            "--ssh-keygen",
+            "--rm",
             "--label",
             INTEGRATION_TEST_LABEL,
-            "--detach",
-            "--name",
-            &container_name,
-            &image,
+            "--artifacts-dir",
+            artifacts.to_str().unwrap(),
+            "--execute",
+            "/bin/sh -c 'echo artifacts-marker; exit 1'",
+            &get_test_image(),
         ])
         .output()
-        .expect("Failed to start detached VM with SSH");
+        .expect("Failed to run bcvk ephemeral run with --artifacts-dir");
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        panic!("Failed to start detached VM: {}", stderr);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("artifacts test stderr: {}", stderr);
+    assert!(!output.status.success(), "failing command did not fail");
+
+    for name in [
+        "journal.txt",
+        "systemctl-failed.txt",
+        "execute-status.txt",
+        "console.log",
+    ] {
+        let path = artifacts.join(name);
+        assert!(path.exists(), "missing artifact {name}");
     }
+    let status = std::fs::read_to_string(artifacts.join("execute-status.txt")).unwrap();
+    assert!(
+        status.contains("bootc-execute.service"),
+        "unexpected execute status: {status}"
+    );
+}
+
+pub fn test_run_ephemeral_execute_timeout() {
+    let bck = get_bck_command().unwrap();
+
+    let output = Command::new("timeout")
+        .args([
+            "240s",
+            &bck,
+            "ephemeral",
+            "run",
+            "--rm",
+            "--label",
+            INTEGRATION_TEST_LABEL,
+            "--timeout",
+            "90s",
+            "--execute",
+            "/bin/sh -c 'echo started; sleep 1000'",
+            &get_test_image(),
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral run with --timeout");
 
     let stdout = String::from_utf8_lossy(&output.stdout);
-    eprintln!(
-        "Detached VM started:\nstdout: {}\nstderr: {}",
-        stdout,
-        String::from_utf8_lossy(&output.stderr)
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("execute timeout test stdout: {}", stdout);
+    eprintln!("execute timeout test stderr: {}", stderr);
+
+    assert_eq!(
+        output.status.code(),
+        Some(124),
+        "expected the --timeout exit code: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("started"),
+        "command did not start: {stdout}"
     );
+    assert!(
+        stderr.contains("timed out"),
+        "timeout not reported: {stderr}"
+    );
+    assert!(
+        stderr.contains("lines of the console"),
+        "console tail not shown: {stderr}"
+    );
+}
+
+pub fn test_run_ephemeral_credential() {
+    let bck = get_bck_command().unwrap();
+
+    // One text credential and one from a file with binary content
+    let td = tempfile::tempdir().unwrap();
+    let cred_path = td.path().join("bin");
+    std::fs::write(&cred_path, b"line1\nline2,with comma\n").unwrap();
+
+    let output = Command::new("timeout")
+        .args([
+            "120s",
+            &bck,
+            "ephemeral",
+            "run",
+            "--rm",
+            "--label",
+            INTEGRATION_TEST_LABEL,
+            "--credential",
+            "bcvk.text=hello credential",
+            "--credential",
+            &format!("bcvk.bin=@{}", cred_path.display()),
+            "--execute",
+            "/bin/sh -c 'cat /run/credentials/@system/bcvk.text; echo; cat /run/credentials/@system/bcvk.bin'",
+            &get_test_image(),
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral run with --credential");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "ephemeral run with --credential failed: {}",
+        stderr
+    );
+    assert!(
+        stdout.contains("hello credential"),
+        "Text credential not found in stdout: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("line1\nline2,with comma"),
+        "File credential not found in stdout: {}",
+        stdout
+    );
+}
+
+pub fn test_run_ephemeral_systemd_units_tree() {
+    let bck = get_bck_command().unwrap();
+
+    // A timer enabled via timers.target.wants, with a drop-in on its service
+    let units_dir = tempfile::tempdir().unwrap();
+    let system = units_dir.path().join("system");
+    std::fs::create_dir_all(system.join("bcvk-test.service.d")).unwrap();
+    std::fs::create_dir_all(system.join("timers.target.wants")).unwrap();
+    std::fs::write(
+        system.join("bcvk-test.service"),
+        "[Service]\nType=oneshot\nExecStart=/bin/true\n",
+    )
+    .unwrap();
+    std::fs::write(
+        system.join("bcvk-test.service.d/10-env.conf"),
+        "[Service]\nEnvironment=MSG=from-dropin\n",
+    )
+    .unwrap();
+    std::fs::write(system.join("bcvk-test.timer"), "[Timer]\nOnActiveSec=1h\n").unwrap();
+    std::os::unix::fs::symlink(
+        "../bcvk-test.timer",
+        system.join("timers.target.wants/bcvk-test.timer"),
+    )
+    .unwrap();
+
+    let output = Command::new("timeout")
+        .args([
+            "120s",
+            &bck,
+            "ephemeral",
+            "run",
+            "--rm",
+            "--label",
+            INTEGRATION_TEST_LABEL,
+            "--systemd-units",
+            units_dir.path().to_str().unwrap(),
+            "--execute",
+            "/bin/sh -c 'systemctl show -P ActiveState bcvk-test.timer; systemctl show -P Environment bcvk-test.service'",
+            &get_test_image(),
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral run with --systemd-units");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "ephemeral run with --systemd-units failed: {}",
+        stderr
+    );
+    assert!(
+        stdout.lines().any(|l| l.trim() == "active"),
+        "Timer not active: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("MSG=from-dropin"),
+        "Drop-in not applied: {}",
+        stdout
+    );
+}
+
+pub fn test_run_ephemeral_copy_in() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let bck = get_bck_command().unwrap();
+
+    let td = tempfile::tempdir().unwrap();
+    let script = td.path().join("bcvk-hello");
+    std::fs::write(&script, "#!/bin/sh\necho hello from copy-in\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o750)).unwrap();
+
+    let output = Command::new("timeout")
+        .args([
+            "120s",
+            &bck,
+            "ephemeral",
+            "run",
+            "--rm",
+            "--label",
+            INTEGRATION_TEST_LABEL,
+            "--copy-in",
+            &format!("{}:/usr/local/bin/", script.display()),
+            "--execute",
+            "/bin/sh -c 'stat -c %a /usr/local/bin/bcvk-hello; /usr/local/bin/bcvk-hello'",
+            &get_test_image(),
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral run with --copy-in");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "ephemeral run with --copy-in failed: {}",
+        stderr
+    );
+    assert!(
+        stdout.lines().any(|l| l.trim() == "750"),
+        "Mode not preserved: {}",
+        stdout
+    );
+    assert!(
+        stdout.contains("hello from copy-in"),
+        "Copied script did not run: {}",
+        stdout
+    );
+}
+
+pub fn test_run_ephemeral_state_disk() {
+    let bck = get_bck_command().unwrap();
+    let td = tempfile::tempdir().unwrap();
+    let disk = td.path().join("state.img");
+    let disk_arg = disk.to_str().unwrap();
+
+    let run = |script: &str| {
+        let output = Command::new("timeout")
+            .args([
+                "180s",
+                &bck,
+                "ephemeral",
+                "run",
+                "--rm",
+                "--label",
+                INTEGRATION_TEST_LABEL,
+                "--state-disk",
+                disk_arg,
+                "--execute",
+                &format!("/bin/sh -c '{script}'"),
+                &get_test_image(),
+            ])
+            .output()
+            .expect("Failed to run bcvk ephemeral run with --state-disk");
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            output.status.success(),
+            "ephemeral run with --state-disk failed: {}",
+            stderr
+        );
+        stdout
+    };
+
+    // Enable a unit and write to /var in the first run
+    run(concat!(
+        "printf \"[Unit]\\nBefore=bootc-execute.service\\n",
+        "[Service]\\nType=oneshot\\nRemainAfterExit=yes\\nExecStart=/bin/true\\n\" ",
+        "> /etc/systemd/system/bcvk-state-test.service && ",
+        "mkdir -p /etc/systemd/system/multi-user.target.wants && ",
+        "ln -s ../bcvk-state-test.service /etc/systemd/system/multi-user.target.wants/ && ",
+        "echo persisted > /var/bcvk-state-test"
+    ));
+
+    // The overlays are in place before systemd loads units, so the unit
+    // enabled in the previous run is started at boot
+    let stdout = run(concat!(
+        "cat /var/bcvk-state-test; ",
+        "systemctl is-active bcvk-state-test.service; true"
+    ));
+    assert!(
+        stdout.lines().any(|l| l.trim() == "persisted"),
+        "/var was not persisted: {}",
+        stdout
+    );
+    assert!(
+        stdout.lines().any(|l| l.trim() == "active"),
+        "Unit enabled in the previous run did not start: {}",
+        stdout
+    );
+}
+
+pub fn test_run_ephemeral_container_ssh_access() {
+    let bck = get_bck_command().unwrap();
+
+    eprintln!("Testing container-based SSH access");
+
+    let vm = DetachedVm::start("ssh", &["--ssh-keygen"]);
+    let container_name = vm.name.as_str();
 
     // Try to SSH into the VM via container (with a simple command)
     eprintln!("Attempting SSH connection via container...");
@@ -291,7 +703,7 @@ pub fn test_run_ephemeral_container_ssh_access() {
             &bck,
             "ephemeral",
             "ssh",
-            &container_name,
+            container_name,
             "echo",
             "SSH_TEST_SUCCESS",
         ])
@@ -305,19 +717,580 @@ pub fn test_run_ephemeral_container_ssh_access() {
     eprintln!("SSH stdout: {}", ssh_stdout);
     eprintln!("SSH stderr: {}", ssh_stderr);
 
-    // Cleanup: stop the container
-    let cleanup_output = Command::new("podman")
-        .args(["stop", &container_name])
-        .output();
+    // Check if SSH worked
+    assert!(ssh_output.status.success());
+    assert!(ssh_stdout.contains("SSH_TEST_SUCCESS"));
+}
+
+/// Test executing commands in a running VM over vsock, without SSH
+pub fn test_run_ephemeral_exec() {
+    let bck = get_bck_command().unwrap();
+
+    // Start VM without any SSH setup
+    let vm = DetachedVm::start("exec", &[]);
+    let container_name = vm.name.as_str();
+
+    let exec_output = Command::new("timeout")
+        .args([
+            "180s",
+            &bck,
+            "ephemeral",
+            "exec",
+            container_name,
+            "--",
+            "sh",
+            "-c",
+            "echo EXEC_STDOUT; echo EXEC_STDERR >&2; exit 42",
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral exec");
+
+    // Stdin is forwarded to the command
+    let mut stdin_child = Command::new("timeout")
+        .args(["60s", &bck, "ephemeral", "exec", container_name, "cat"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn bcvk ephemeral exec");
+    {
+        use std::io::Write;
+        let mut stdin = stdin_child.stdin.take().unwrap();
+        stdin.write_all(b"EXEC_STDIN\n").unwrap();
+    }
+    let stdin_output = stdin_child.wait_with_output().unwrap();
+
+    let stdout = String::from_utf8_lossy(&exec_output.stdout);
+    let stderr = String::from_utf8_lossy(&exec_output.stderr);
+    eprintln!("exec stdout: {}", stdout);
+    eprintln!("exec stderr: {}", stderr);
+
+    assert_eq!(exec_output.status.code(), Some(42));
+    assert_eq!(stdout, "EXEC_STDOUT\n");
+    assert!(stderr.contains("EXEC_STDERR"));
+
+    assert!(stdin_output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&stdin_output.stdout),
+        "EXEC_STDIN\n"
+    );
+}
+
+/// Test copying a directory into a running VM and back out, preserving modes
+pub fn test_run_ephemeral_cp() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let bck = get_bck_command().unwrap();
+
+    let td = tempfile::tempdir().unwrap();
+    let src = td.path().join("fixtures");
+    std::fs::create_dir_all(src.join("sub")).unwrap();
+    let script = src.join("sub/script.sh");
+    std::fs::write(&script, "#!/bin/sh\necho CP_FIXTURE\n").unwrap();
+    std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o750)).unwrap();
+
+    let vm = DetachedVm::start("cp", &[]);
+    let container_name = vm.name.as_str();
+
+    let copy_in = Command::new("timeout")
+        .args(["180s", &bck, "ephemeral", "cp"])
+        .arg(&src)
+        .arg(format!("{container_name}:/var/tmp"))
+        .output()
+        .expect("Failed to run bcvk ephemeral cp");
+    let run_script = Command::new("timeout")
+        .args([
+            "60s",
+            &bck,
+            "ephemeral",
+            "exec",
+            container_name,
+            "/var/tmp/fixtures/sub/script.sh",
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral exec");
+    let dest = td.path().join("copied");
+    let copy_out = Command::new("timeout")
+        .args(["60s", &bck, "ephemeral", "cp"])
+        .arg(format!("{container_name}:/var/tmp/fixtures"))
+        .arg(&dest)
+        .output()
+        .expect("Failed to run bcvk ephemeral cp");
+
+    assert!(
+        copy_in.status.success(),
+        "copy into VM failed: {}",
+        String::from_utf8_lossy(&copy_in.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&run_script.stdout),
+        "CP_FIXTURE\n",
+        "copied script did not run: {}",
+        String::from_utf8_lossy(&run_script.stderr)
+    );
+    assert!(
+        copy_out.status.success(),
+        "copy out of VM failed: {}",
+        String::from_utf8_lossy(&copy_out.stderr)
+    );
+    let mode = std::fs::metadata(dest.join("sub/script.sh"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o750);
+}
+
+pub fn test_run_ephemeral_logs() {
+    let bck = get_bck_command().unwrap();
+
+    let vm = DetachedVm::start("logs", &[]);
+    let container_name = vm.name.as_str();
 
-    if let Ok(cleanup) = cleanup_output {
-        eprintln!(
-            "Container cleanup: {}",
-            String::from_utf8_lossy(&cleanup.stdout)
+    // Waits for boot via the exec agent
+    let exec_output = Command::new("timeout")
+        .args([
+            "180s",
+            &bck,
+            "ephemeral",
+            "exec",
+            container_name,
+            "logger",
+            "-t",
+            "bcvk-logs-test",
+            "LOGS_MARKER",
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral exec");
+
+    let logs = |extra: &[&str]| {
+        let output = Command::new("timeout")
+            .args(["60s", &bck, "ephemeral", "logs"])
+            .args(extra)
+            .arg(container_name)
+            .output()
+            .expect("Failed to run bcvk ephemeral logs");
+        assert!(
+            output.status.success(),
+            "logs failed: {}",
+            String::from_utf8_lossy(&output.stderr)
         );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    // The journal is forwarded asynchronously
+    let mut all = String::new();
+    for _ in 0..30 {
+        all = logs(&[]);
+        if all.contains("LOGS_MARKER") {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_secs(1));
     }
+    let journald = logs(&["-u", "systemd-journald"]);
+    let recent = logs(&["--since", "1h"]);
 
-    // Check if SSH worked
-    assert!(ssh_output.status.success());
-    assert!(ssh_stdout.contains("SSH_TEST_SUCCESS"));
+    assert!(exec_output.status.success(), "logger failed in the VM");
+    assert!(
+        all.contains("bcvk-logs-test") && all.contains("LOGS_MARKER"),
+        "marker missing from journal"
+    );
+    assert!(
+        !journald.is_empty() && !journald.contains("LOGS_MARKER"),
+        "unit filter did not apply: {journald}"
+    );
+    assert!(
+        recent.contains("LOGS_MARKER"),
+        "--since dropped new entries"
+    );
+}
+
+pub fn test_run_ephemeral_console() {
+    let bck = get_bck_command().unwrap();
+
+    let vm = DetachedVm::start("console", &[]);
+    let container_name = vm.name.as_str();
+
+    // The kernel logs to the serial console from early boot
+    let mut log = String::new();
+    for _ in 0..60 {
+        let output = Command::new("timeout")
+            .args(["30s", &bck, "ephemeral", "console", "--log", container_name])
+            .output()
+            .expect("Failed to run bcvk ephemeral console --log");
+        log = String::from_utf8_lossy(&output.stdout).into_owned();
+        if log.contains("Linux version") {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
+
+    // Attach, send a newline, then detach with Ctrl-]
+    let mut child = Command::new("timeout")
+        .args(["60s", &bck, "ephemeral", "console", container_name])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn bcvk ephemeral console");
+    {
+        use std::io::Write;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(b"\n").unwrap();
+        std::thread::sleep(std::time::Duration::from_secs(2));
+        stdin.write_all(b"\x1d").unwrap();
+    }
+    let attach_output = child.wait_with_output().unwrap();
+
+    assert!(
+        log.contains("Linux version"),
+        "kernel messages missing from console log: {log}"
+    );
+    let stderr = String::from_utf8_lossy(&attach_output.stderr);
+    assert!(
+        attach_output.status.success(),
+        "console attach failed: {stderr}"
+    );
+    assert!(stderr.contains("Detached from console"), "{stderr}");
+}
+
+pub fn test_run_ephemeral_lifecycle() {
+    let bck = get_bck_command().unwrap();
+
+    let vm = DetachedVm::start("lifecycle", &[]);
+    let container_name = vm.name.as_str();
+
+    let boot_id = |timeout: &str| {
+        let output = Command::new("timeout")
+            .args([
+                timeout,
+                &bck,
+                "ephemeral",
+                "exec",
+                container_name,
+                "cat",
+                "/proc/sys/kernel/random/boot_id",
+            ])
+            .output()
+            .expect("Failed to run bcvk ephemeral exec");
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    };
+    let control = |args: &[&str]| {
+        let output = Command::new("timeout")
+            .args(["120s", &bck, "ephemeral"])
+            .args(args)
+            .arg(container_name)
+            .output()
+            .expect("Failed to run bcvk ephemeral lifecycle command");
+        assert!(
+            output.status.success(),
+            "{args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    };
+
+    let first_boot = boot_id("180s").expect("VM did not boot");
+
+    // A paused guest does not respond
+    control(&["pause"]);
+    let while_paused = boot_id("10s");
+    control(&["resume"]);
+    let after_resume = boot_id("60s");
+
+    control(&["reset"]);
+    let mut after_reset = None;
+    for _ in 0..6 {
+        after_reset = boot_id("60s").filter(|id| *id != first_boot);
+        if after_reset.is_some() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_secs(5));
+    }
+
+    let start = std::time::Instant::now();
+    control(&["stop", "--timeout", "90s"]);
+    let stop_duration = start.elapsed();
+    let exists = Command::new("podman")
+        .args(["container", "exists", container_name])
+        .status()
+        .unwrap();
+
+    assert_eq!(while_paused, None, "paused VM responded");
+    assert_eq!(after_resume.as_deref(), Some(first_boot.as_str()));
+    assert!(after_reset.is_some(), "VM did not come back after reset");
+    assert!(!exists.success(), "container still exists after stop");
+    // A clean poweroff does not need the full timeout
+    assert!(
+        stop_duration < std::time::Duration::from_secs(90),
+        "guest did not power off cleanly"
+    );
+}
+
+/// Test waiting for boot milestones and exit of a detached VM
+pub fn test_run_ephemeral_wait() {
+    let bck = get_bck_command().unwrap();
+
+    let vm = DetachedVm::start("wait", &[]);
+    let container_name = vm.name.as_str();
+
+    let wait = |condition: &str, timeout: &str| {
+        Command::new(&bck)
+            .args(["ephemeral", "wait", container_name, "--for", condition])
+            .args(["--timeout", timeout])
+            .output()
+            .expect("Failed to run bcvk ephemeral wait")
+    };
+
+    let target = wait("target=multi-user.target", "3m");
+    let unit = wait("unit=systemd-journald", "2m");
+    // An inactive unit never becomes active on its own
+    let timed_out = wait("unit=bcvk-does-not-exist", "5s");
+
+    let mut exited = Command::new(&bck)
+        .args(["ephemeral", "wait", container_name, "--for", "exited"])
+        .args(["--timeout", "2m"])
+        .spawn()
+        .expect("Failed to run bcvk ephemeral wait");
+    let stop = Command::new(&bck)
+        .args(["ephemeral", "stop", container_name])
+        .output()
+        .expect("Failed to run bcvk ephemeral stop");
+    let exited = exited
+        .wait()
+        .expect("Failed to wait for bcvk ephemeral wait");
+
+    assert!(
+        target.status.success(),
+        "waiting for multi-user.target failed: {}",
+        String::from_utf8_lossy(&target.stderr)
+    );
+    assert!(
+        unit.status.success(),
+        "waiting for systemd-journald failed: {}",
+        String::from_utf8_lossy(&unit.stderr)
+    );
+    assert_eq!(timed_out.status.code(), Some(124));
+    assert!(stop.status.success());
+    assert!(exited.success(), "waiting for exit failed");
+
+    // A mistyped container name is an error, not an exited VM
+    let missing = Command::new(&bck)
+        .args([
+            "ephemeral",
+            "wait",
+            "bcvk-no-such-container",
+            "--for",
+            "exited",
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral wait");
+    assert!(!missing.status.success());
+    assert!(
+        String::from_utf8_lossy(&missing.stderr).contains("No such container"),
+        "unexpected error: {}",
+        String::from_utf8_lossy(&missing.stderr)
+    );
+}
+
+/// Test the boot timing report of a detached VM
+pub fn test_run_ephemeral_boot_report() {
+    let bck = get_bck_command().unwrap();
+
+    let td = tempfile::tempdir().unwrap();
+    let report_path = td.path().join("boot-report.json");
+
+    let vm = DetachedVm::start(
+        "boot-report",
+        &["--boot-report", report_path.to_str().unwrap()],
+    );
+    let container_name = vm.name.as_str();
+
+    let wait = Command::new(&bck)
+        .args(["ephemeral", "wait", container_name])
+        .args(["--for", "target=multi-user.target", "--timeout", "3m"])
+        .output()
+        .expect("Failed to run bcvk ephemeral wait");
+    let report = Command::new(&bck)
+        .args(["ephemeral", "boot-report", "--json", container_name])
+        .output()
+        .expect("Failed to run bcvk ephemeral boot-report");
+    let table = Command::new(&bck)
+        .args(["ephemeral", "boot-report", container_name])
+        .output()
+        .expect("Failed to run bcvk ephemeral boot-report");
+
+    assert!(
+        wait.status.success(),
+        "VM did not boot: {}",
+        String::from_utf8_lossy(&wait.stderr)
+    );
+    assert!(
+        report.status.success(),
+        "boot-report failed: {}",
+        String::from_utf8_lossy(&report.stderr)
+    );
+    let report: serde_json::Value = serde_json::from_slice(&report.stdout).unwrap();
+    let events = report["events"].as_array().unwrap();
+    let names: Vec<_> = events.iter().map(|e| e["name"].as_str().unwrap()).collect();
+    debug!("Boot events: {names:?}");
+    let position = |name: &str| {
+        names
+            .iter()
+            .position(|n| *n == name)
+            .unwrap_or_else(|| panic!("{name} missing from boot report: {names:?}"))
+    };
+    let phases = [
+        "podman-run",
+        "container-start",
+        "virtiofsd-ready",
+        "qemu-spawn",
+        "first-notify",
+        "multi-user.target",
+    ];
+    let positions: Vec<_> = phases.iter().map(|p| position(p)).collect();
+    assert!(
+        positions.windows(2).all(|w| w[0] < w[1]),
+        "boot phases out of order: {names:?}"
+    );
+    let elapsed: Vec<_> = events
+        .iter()
+        .map(|e| e["elapsed_ms"].as_u64().unwrap())
+        .collect();
+    assert!(elapsed.windows(2).all(|w| w[0] <= w[1]));
+
+    assert!(table.status.success());
+    assert!(String::from_utf8_lossy(&table.stdout).contains("multi-user.target"));
+
+    // The host file was kept up to date while the VM ran
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+    assert_eq!(saved["started"], report["started"]);
+    assert!(saved["events"].as_array().unwrap().len() >= events.len());
+}
+
+/// Test inspecting the configuration and state of a detached VM
+pub fn test_run_ephemeral_inspect() {
+    let bck = get_bck_command().unwrap();
+
+    let td = tempfile::tempdir().unwrap();
+    let mount_dir = td.path().to_str().unwrap();
+
+    let vm = DetachedVm::start(
+        "inspect",
+        &[
+            "--memory",
+            "2048",
+            "--vcpus",
+            "2",
+            "--ro-bind",
+            &format!("{mount_dir}:inspectdata"),
+        ],
+    );
+    let container_name = vm.name.as_str();
+
+    let wait = Command::new(&bck)
+        .args(["ephemeral", "wait", container_name])
+        .args(["--for", "target=multi-user.target", "--timeout", "3m"])
+        .output()
+        .expect("Failed to run bcvk ephemeral wait");
+    let inspect = |format: &str| {
+        Command::new(&bck)
+            .args(["ephemeral", "inspect", "--format", format, container_name])
+            .output()
+            .expect("Failed to run bcvk ephemeral inspect")
+    };
+    let json = inspect("json");
+    let yaml = inspect("yaml");
+
+    assert!(
+        wait.status.success(),
+        "VM did not boot: {}",
+        String::from_utf8_lossy(&wait.stderr)
+    );
+    assert!(
+        json.status.success(),
+        "inspect failed: {}",
+        String::from_utf8_lossy(&json.stderr)
+    );
+    let info: serde_json::Value = serde_json::from_slice(&json.stdout).unwrap();
+    debug!("Inspect output: {info}");
+    assert_eq!(info["name"], container_name);
+    assert_eq!(info["running"], true);
+    assert_eq!(info["memory_mb"], 2048);
+    assert_eq!(info["vcpus"], 2);
+    assert!(info["qemu_pid"].as_u64().unwrap() > 0);
+    assert!(!info["kernel_version"].as_str().unwrap().is_empty());
+    assert!(info["started"].is_string());
+    assert!(info["last_target"].is_string());
+    let mounts = info["mounts"].as_array().unwrap();
+    assert!(
+        mounts
+            .iter()
+            .any(|m| m["target"] == "/run/virtiofs-mnt-inspectdata" && m["readonly"] == true),
+        "mount missing: {mounts:?}"
+    );
+
+    assert!(yaml.status.success());
+    let yaml = String::from_utf8_lossy(&yaml.stdout);
+    assert!(yaml.contains(&format!("name: {container_name}")));
+    assert!(yaml.contains("memory_mb: 2048"));
+}
+
+/// Test the VM columns of ephemeral ps
+pub fn test_run_ephemeral_ps() {
+    let bck = get_bck_command().unwrap();
+
+    let vm = DetachedVm::start("ps", &["--memory", "2048", "--vcpus", "2"]);
+    let container_name = vm.name.as_str();
+
+    let wait = Command::new(&bck)
+        .args(["ephemeral", "wait", container_name])
+        .args(["--for", "target=multi-user.target", "--timeout", "3m"])
+        .output()
+        .expect("Failed to run bcvk ephemeral wait");
+    let ps = Command::new(&bck)
+        .args(["ephemeral", "ps", "--json"])
+        .output()
+        .expect("Failed to run bcvk ephemeral ps");
+    let watch = Command::new("timeout")
+        .args(["5s", &bck, "ephemeral", "ps", "--watch", "--interval", "1s"])
+        .output()
+        .expect("Failed to run bcvk ephemeral ps --watch");
+
+    assert!(
+        wait.status.success(),
+        "VM did not boot: {}",
+        String::from_utf8_lossy(&wait.stderr)
+    );
+    assert!(
+        ps.status.success(),
+        "ps failed: {}",
+        String::from_utf8_lossy(&ps.stderr)
+    );
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(&ps.stdout).unwrap();
+    let entry = entries
+        .iter()
+        .find(|e| {
+            e["Names"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .any(|n| n == container_name)
+        })
+        .unwrap_or_else(|| panic!("{container_name} not listed: {entries:?}"));
+    debug!("ps entry: {entry}");
+    assert!(
+        ["booting", "ready"].contains(&entry["BootState"].as_str().unwrap()),
+        "unexpected boot state: {entry}"
+    );
+    assert!(entry["SshReady"].is_boolean());
+    assert_eq!(entry["Vcpus"], 2);
+    assert_eq!(entry["MemoryMb"], 2048);
+    assert!(entry["RssBytes"].as_u64().unwrap() > 0);
+    assert!(entry["CpuPercent"].as_f64().is_some());
+
+    // Killed by timeout, but it refreshed in the meantime
+    let watch = String::from_utf8_lossy(&watch.stdout);
+    assert!(watch.matches("bcvk ephemeral ps").count() >= 2, "{watch}");
+    assert!(watch.contains(container_name));
 }
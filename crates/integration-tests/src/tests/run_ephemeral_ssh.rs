@@ -66,6 +66,48 @@ pub fn test_run_ephemeral_ssh_command() {
     eprintln!("Successfully executed command via SSH and received output");
 }
 
+/// Test that --user creates a non-root user with sudo, and SSH connects as it
+pub fn test_run_ephemeral_ssh_user() {
+    let bck = get_bck_command().unwrap();
+
+    let output = Command::new("timeout")
+        .args([
+            "60s",
+            &bck,
+            "ephemeral",
+            "run-ssh",
+            "--label",
+            INTEGRATION_TEST_LABEL,
+            "--user",
+            "testuser:wheel",
+            &get_test_image(),
+            "--",
+            "sh",
+            "-c",
+            "id -un; id -Gn; sudo -n id -un",
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral run-ssh --user");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        output.status.success(),
+        "ephemeral run-ssh --user failed: {}",
+        stderr
+    );
+
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "Unexpected output: {}", stdout);
+    assert_eq!(lines[0], "testuser");
+    assert!(
+        lines[1].split_whitespace().any(|g| g == "wheel"),
+        "testuser is not in wheel: {}",
+        lines[1]
+    );
+    assert_eq!(lines[2], "root");
+}
+
 /// Test that the container is cleaned up when SSH exits
 pub fn test_run_ephemeral_ssh_cleanup() {
     let bck = get_bck_command().unwrap();
@@ -195,6 +237,48 @@ pub fn test_run_ephemeral_ssh_exit_code() {
     eprintln!("Exit code was properly forwarded");
 }
 
+/// Test that run-ssh fails fast with the reason when the guest boots into emergency.target
+pub fn test_run_ephemeral_ssh_boot_failure() {
+    let bck = get_bck_command().unwrap();
+
+    let start = std::time::Instant::now();
+    let output = Command::new("timeout")
+        .args([
+            "120s",
+            &bck,
+            "ephemeral",
+            "run-ssh",
+            "--label",
+            INTEGRATION_TEST_LABEL,
+            "--karg",
+            "systemd.unit=emergency.target",
+            &get_test_image(),
+            "--",
+            "true",
+        ])
+        .output()
+        .expect("Failed to run bcvk ephemeral run-ssh");
+    let elapsed = start.elapsed();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("boot failure stderr: {stderr}");
+    assert!(
+        !output.status.success(),
+        "run-ssh succeeded in emergency mode"
+    );
+    assert_ne!(output.status.code(), Some(124), "run-ssh did not fail fast");
+    assert!(
+        stderr.contains("Guest entered emergency.target"),
+        "missing failure reason: {stderr}"
+    );
+    assert!(
+        stderr.contains("lines of the console"),
+        "missing console excerpt: {stderr}"
+    );
+    // Well before the SSH timeout
+    assert!(elapsed < Duration::from_secs(60), "took {elapsed:?}");
+}
+
 /// Test SSH functionality across different bootc images (Fedora and CentOS)
 /// This test verifies that our systemd version compatibility fix works correctly
 /// with both newer systemd (Fedora) and older systemd (CentOS Stream 9)
@@ -50,6 +50,14 @@ fi
 # Check systemd version from the container image (not host)
 export SYSTEMD_VERSION=$(systemctl --version 2>/dev/null)
 
+# bash points stdin of background jobs at /dev/null; keep it for
+# commands that stream it into the VM
+if [[ "${1:-}" == exec || "${1:-}" == console ]]; then
+    exec 3<&0
+else
+    exec 3</dev/null
+fi
+
 # Execute with proper environment passing
 # Set up signal handlers that will cleanly exit on INT or TERM
 trap 'kill -TERM $BWRAP_PID 2>/dev/null; exit 0' INT TERM
@@ -57,7 +65,7 @@ trap 'kill -TERM $BWRAP_PID 2>/dev/null; exit 0' INT TERM
 # Run bwrap in background so we can handle signals; xref
 # https://github.com/containers/bubblewrap/pull/586
 # But probably really we should switch to systemd
-bwrap --as-pid-1 --unshare-pid "${BWRAP_ARGS[@]}" --bind /run /run -- ${SELFEXE} container-entrypoint "$@" &
+bwrap --as-pid-1 --unshare-pid "${BWRAP_ARGS[@]}" --bind /run /run -- ${SELFEXE} container-entrypoint "$@" <&3 3<&- &
 BWRAP_PID=$!
 
 # Wait for bwrap to complete
@@ -2,7 +2,10 @@ use color_eyre::Result;
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use std::{fs::File, io::BufRead, time::Duration};
 
-use crate::supervisor_status::{StatusWriter, SupervisorState, SupervisorStatus};
+use crate::boot_report::{BootRecorder, EventSource};
+use crate::supervisor_status::{
+    BootFailure, BootFailureKind, StatusWriter, SupervisorState, SupervisorStatus,
+};
 
 const SSH_ACCESS: &str = "ssh-access.target";
 
@@ -20,17 +23,26 @@ pub fn create_boot_progress_bar() -> ProgressBar {
     pb
 }
 
-/// Monitor systemd boot progress and update progress bar
-pub async fn monitor_boot_progress(piper: File, status_writer: StatusWriter) -> Result<()> {
+/// Monitor systemd boot progress, updating the status and recording boot timing
+pub async fn monitor_boot_progress(
+    piper: File,
+    status_writer: StatusWriter,
+    recorder: BootRecorder,
+) -> Result<()> {
     // Update status to indicate we're waiting for systemd
     status_writer.update_state(SupervisorState::WaitingForSystemd)?;
 
     let bufr = std::io::BufReader::new(piper);
 
     let mut ssh_access = false;
+    let mut reached_targets = Vec::new();
+    let mut first = true;
     for line in bufr.lines() {
         let line = line?;
         let line = line.trim();
+        if std::mem::take(&mut first) {
+            recorder.record(EventSource::Host, "first-notify");
+        }
 
         let Some((k, v)) = line.split_once('=') else {
             tracing::trace!("Unhandled status line: {line}");
@@ -39,14 +51,29 @@ pub async fn monitor_boot_progress(piper: File, status_writer: StatusWriter) ->
         tracing::debug!("Got systemd notification: {k}={v}");
         match k {
             "READY" => {
+                recorder.record(EventSource::Guest, line);
                 let state = SupervisorState::ReachedTarget(v.to_owned());
                 status_writer.update(SupervisorStatus {
                     state: Some(state),
                     ssh_access,
                     running: true,
+                    reached_targets: reached_targets.clone(),
+                    ..Default::default()
                 })?;
             }
             "X_SYSTEMD_UNIT_ACTIVE" => {
+                reached_targets.push(v.to_owned());
+                recorder.record(EventSource::Guest, v);
+                let failure = match v {
+                    "emergency.target" => Some(BootFailureKind::EmergencyTarget),
+                    "rescue.target" => Some(BootFailureKind::RescueTarget),
+                    _ => None,
+                };
+                if let Some(kind) = failure {
+                    let failure = BootFailure::new(kind, format!("Guest entered {v}"));
+                    status_writer.fail(failure, true)?;
+                    continue;
+                }
                 let state = SupervisorState::ReachedTarget(v.to_owned());
                 if v == SSH_ACCESS {
                     ssh_access = true;
@@ -55,6 +82,8 @@ pub async fn monitor_boot_progress(piper: File, status_writer: StatusWriter) ->
                     state: Some(state),
                     ssh_access,
                     running: true,
+                    reached_targets: reached_targets.clone(),
+                    ..Default::default()
                 })?;
             }
             _ => {
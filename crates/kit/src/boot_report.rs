@@ -0,0 +1,260 @@
+//! Boot timing of ephemeral VMs.
+//!
+//! The container records when each boot phase happened into
+//! [`BOOT_REPORT_PATH`]: host-side phases (podman run, container start,
+//! virtiofsd ready, QEMU spawn, first systemd notification) and every unit
+//! the guest systemd reports as active over the vmm.notify_socket. Times are
+//! relative to when `bcvk` invoked podman, so the report covers the whole
+//! startup as seen by a user, which makes it suitable for tracking boot-time
+//! regressions of an image in CI.
+
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use comfy_table::{presets::UTF8_FULL, Table};
+use serde::{Deserialize, Serialize};
+
+/// Where the container keeps the boot report; `--boot-report` is mounted here
+pub const BOOT_REPORT_PATH: &str = "/run/bcvk-boot-report.json";
+
+/// Environment variable carrying the time (Unix milliseconds) podman was invoked
+pub(crate) const BOOT_START_ENV: &str = "BCVK_BOOT_START_MS";
+
+/// Where a boot event was observed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventSource {
+    /// bcvk on the host or in the container
+    Host,
+    /// A systemd notification from the guest
+    Guest,
+}
+
+impl std::fmt::Display for EventSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventSource::Host => f.write_str("host"),
+            EventSource::Guest => f.write_str("guest"),
+        }
+    }
+}
+
+/// A boot phase and when it was reached.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootEvent {
+    /// Milliseconds since the report started
+    pub elapsed_ms: u64,
+    pub source: EventSource,
+    /// A host phase like `qemu-spawn`, or the unit the guest reported
+    pub name: String,
+}
+
+/// Timestamps of the boot phases of a VM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootReport {
+    /// When podman was invoked, or the container started if unknown
+    pub started: DateTime<Utc>,
+    /// Events ordered by time
+    pub events: Vec<BootEvent>,
+}
+
+impl BootReport {
+    pub fn new(started: DateTime<Utc>) -> Self {
+        Self {
+            started,
+            events: Vec::new(),
+        }
+    }
+
+    /// Add an event, keeping events ordered by time.
+    pub fn push(&mut self, source: EventSource, name: impl Into<String>, at: DateTime<Utc>) {
+        let elapsed_ms = (at - self.started).num_milliseconds().max(0) as u64;
+        let idx = self.events.partition_point(|e| e.elapsed_ms <= elapsed_ms);
+        self.events.insert(
+            idx,
+            BootEvent {
+                elapsed_ms,
+                source,
+                name: name.into(),
+            },
+        );
+    }
+
+    /// A table of events with the time since start and since the previous event.
+    pub fn to_table(&self) -> Table {
+        let mut table = Table::new();
+        table
+            .load_preset(UTF8_FULL)
+            .set_header(vec!["ELAPSED", "DELTA", "SOURCE", "EVENT"]);
+        let mut previous = 0;
+        for event in &self.events {
+            table.add_row(vec![
+                format_ms(event.elapsed_ms),
+                format!("+{}", format_ms(event.elapsed_ms - previous)),
+                event.source.to_string(),
+                event.name.clone(),
+            ]);
+            previous = event.elapsed_ms;
+        }
+        table
+    }
+}
+
+fn format_ms(ms: u64) -> String {
+    format!("{}.{:03}s", ms / 1000, ms % 1000)
+}
+
+/// Records boot events into the report file; clones share the same report.
+///
+/// The report is diagnostic, so failing to write it is logged rather than
+/// interrupting the VM.
+#[derive(Debug, Clone)]
+pub struct BootRecorder {
+    path: String,
+    report: Arc<Mutex<BootReport>>,
+}
+
+impl BootRecorder {
+    /// Start a report at the time in [`BOOT_START_ENV`], recording the container start.
+    pub fn from_env(path: impl Into<String>) -> Self {
+        let now = Utc::now();
+        let podman_run = std::env::var(BOOT_START_ENV)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .and_then(DateTime::from_timestamp_millis);
+        let mut report = BootReport::new(podman_run.unwrap_or(now));
+        if podman_run.is_some() {
+            report.push(EventSource::Host, "podman-run", report.started);
+        }
+        report.push(EventSource::Host, "container-start", now);
+        let recorder = Self {
+            path: path.into(),
+            report: Arc::new(Mutex::new(report)),
+        };
+        recorder.write(&recorder.report.lock().unwrap());
+        recorder
+    }
+
+    /// Record an event that happened now.
+    pub fn record(&self, source: EventSource, name: &str) {
+        self.record_at(source, name, Utc::now())
+    }
+
+    /// Record an event that happened at the given time.
+    pub fn record_at(&self, source: EventSource, name: &str, at: DateTime<Utc>) {
+        let mut report = self.report.lock().unwrap();
+        report.push(source, name, at);
+        self.write(&report);
+    }
+
+    fn write(&self, report: &BootReport) {
+        let r = serde_json::to_string_pretty(report)
+            .map_err(Into::into)
+            // Written in place, as this may be a file bind mounted from the host
+            .and_then(|json| {
+                std::fs::write(&self.path, json).with_context(|| format!("Writing {}", self.path))
+            });
+        if let Err(e) = r {
+            tracing::warn!("Failed to update the boot report: {e:#}");
+        }
+    }
+}
+
+/// Options for showing the boot report of a running VM.
+#[derive(clap::Parser, Debug)]
+pub struct BootReportOpts {
+    /// Name or ID of the container running the target VM
+    pub container_name: String,
+
+    /// Output as structured JSON instead of table format
+    #[clap(long)]
+    pub json: bool,
+}
+
+/// Read the boot report from the given container.
+fn read_report(container_name: &str) -> Result<BootReport> {
+    // The report is rewritten in place, so a read can catch it half written
+    for _ in 0..5 {
+        let output = Command::new("podman")
+            .args(["exec", container_name, "cat", BOOT_REPORT_PATH])
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(|e| eyre!("Failed to run podman exec: {}", e))?;
+        if !output.status.success() {
+            return Err(eyre!("No boot report available for {container_name}"));
+        }
+        if let Ok(report) = serde_json::from_slice(&output.stdout) {
+            return Ok(report);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Err(eyre!("Failed to parse the boot report of {container_name}"))
+}
+
+/// Show the boot report of the VM in the given container.
+pub fn run(opts: BootReportOpts) -> Result<()> {
+    let report = read_report(&opts.container_name)?;
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report.to_table());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boot_report() {
+        let started = DateTime::from_timestamp_millis(1_700_000_000_000).unwrap();
+        let at = |ms| started + chrono::Duration::milliseconds(ms);
+        let mut report = BootReport::new(started);
+        report.push(EventSource::Host, "podman-run", started);
+        report.push(EventSource::Host, "qemu-spawn", at(900));
+        // Recorded after the fact
+        report.push(EventSource::Host, "virtiofsd-ready", at(750));
+        report.push(EventSource::Guest, "multi-user.target", at(4321));
+        let names: Vec<_> = report.events.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "podman-run",
+                "virtiofsd-ready",
+                "qemu-spawn",
+                "multi-user.target"
+            ]
+        );
+
+        let table = report.to_table().to_string();
+        assert!(table.contains("4.321s"));
+        assert!(table.contains("+3.421s"));
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains(r#""source":"guest""#));
+        let parsed: BootReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, report);
+    }
+
+    #[test]
+    fn test_recorder_best_effort() {
+        let td = tempfile::tempdir().unwrap();
+        // A write failure must not stop recording
+        let missing = td.path().join("missing/report.json");
+        let recorder = BootRecorder::from_env(missing.to_str().unwrap());
+        recorder.record(EventSource::Host, "qemu-spawn");
+        assert_eq!(recorder.report.lock().unwrap().events.len(), 2);
+
+        let path = td.path().join("report.json");
+        let recorder = BootRecorder::from_env(path.to_str().unwrap());
+        recorder.record(EventSource::Host, "qemu-spawn");
+        let written: BootReport =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written, *recorder.report.lock().unwrap());
+    }
+}
@@ -22,3 +22,61 @@ impl fmt::Display for MemoryOpts {
         write!(f, "{}", self.memory)
     }
 }
+
+/// systemd credentials to pass to the VM via SMBIOS
+#[derive(Parser, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CredentialOpts {
+    #[clap(
+        long = "credential",
+        value_name = "NAME=VALUE|NAME=@FILE",
+        help = "Pass a systemd credential to the VM (binary values are base64 encoded automatically)"
+    )]
+    pub credential: Vec<String>,
+
+    #[clap(
+        long = "credential-file",
+        value_name = "PATH",
+        help = "Pass a file, or each file in a directory, as a systemd credential named after the file"
+    )]
+    pub credential_file: Vec<String>,
+}
+
+impl CredentialOpts {
+    /// Resolve all credentials into SMBIOS type 11 strings
+    ///
+    /// Files are read here, so this must run on the host.
+    pub fn smbios_credentials(&self) -> color_eyre::Result<Vec<String>> {
+        use color_eyre::eyre::{eyre, Context};
+
+        let mut r = Vec::new();
+        for spec in &self.credential {
+            let (name, value) = crate::sshcred::parse_credential(spec)?;
+            r.push(crate::sshcred::smbios_cred(&name, &value));
+        }
+        for path in &self.credential_file {
+            let path = camino::Utf8Path::new(path);
+            let files = if path.is_dir() {
+                let mut files = path
+                    .read_dir_utf8()
+                    .with_context(|| format!("Reading {path}"))?
+                    .map(|e| e.map(|e| e.into_path()))
+                    .collect::<std::io::Result<Vec<_>>>()?;
+                files.retain(|p| p.is_file());
+                files.sort();
+                files
+            } else {
+                vec![path.to_owned()]
+            };
+            for file in files {
+                let name = file
+                    .file_name()
+                    .ok_or_else(|| eyre!("Cannot determine credential name for {file}"))?;
+                let value =
+                    std::fs::read(&file).with_context(|| format!("Reading credential {file}"))?;
+                crate::sshcred::validate_credential_name(name)?;
+                r.push(crate::sshcred::smbios_cred(name, &value));
+            }
+        }
+        Ok(r)
+    }
+}
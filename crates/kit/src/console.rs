@@ -0,0 +1,435 @@
+//! Serial console of ephemeral VMs that are not attached to a terminal.
+//!
+//! Unless `--console` is used, QEMU connects the guest serial port to a unix
+//! socket served by the container (see [`ConsoleMux`]). The container keeps the
+//! most recent output in [`CONSOLE_LOG_PATH`], used for `--timeout` diagnostics
+//! and `--artifacts-dir`, and relays the console to clients of
+//! [`CONSOLE_SOCKET_PATH`]. `bcvk ephemeral console` attaches to it through
+//! `podman exec`; typing [`ESCAPE_CHAR`] (Ctrl-]) detaches.
+
+use std::fs::File;
+use std::io::{IsTerminal, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::process::Command;
+
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+use tokio::sync::{broadcast, mpsc};
+use tracing::debug;
+
+use crate::supervisor_status::{BootFailure, BootFailureKind, StatusWriter};
+
+/// Where the serial console output is logged inside the container
+pub(crate) const CONSOLE_LOG_PATH: &str = "/run/console.log";
+
+/// Socket that console clients connect to inside the container
+const CONSOLE_SOCKET_PATH: &str = "/run/console.sock";
+
+/// Socket that QEMU connects the serial port to
+pub(crate) const SERIAL_SOCKET_PATH: &str = "/run/qemu-serial.sock";
+
+/// Detach from the console (Ctrl-])
+const ESCAPE_CHAR: u8 = 0x1d;
+
+/// The console log is trimmed to this size once it grows to twice it
+const CONSOLE_LOG_LIMIT: usize = 1024 * 1024;
+
+/// Marks a kernel panic in the console output
+const KERNEL_PANIC_MARKER: &[u8] = b"Kernel panic - not syncing";
+
+/// The last `n` lines of the console log; empty if it is not being logged.
+pub(crate) fn console_tail(n: usize) -> Vec<String> {
+    let Ok(log) = std::fs::read(CONSOLE_LOG_PATH) else {
+        return Vec::new();
+    };
+    let log = String::from_utf8_lossy(&log);
+    let lines: Vec<_> = log.lines().map(|l| l.trim_end_matches('\r')).collect();
+    lines[lines.len().saturating_sub(n)..]
+        .iter()
+        .map(|l| l.to_string())
+        .collect()
+}
+
+/// Options for attaching to the serial console of a running VM.
+#[derive(clap::Parser, Debug)]
+pub struct ConsoleOpts {
+    /// Name or ID of the container running the target VM
+    pub container_name: String,
+
+    /// Print the recent console output instead of attaching
+    #[clap(long)]
+    pub log: bool,
+}
+
+/// A log file that keeps only recent output: once it grows to twice `limit`,
+/// it is trimmed to the last `limit` bytes.
+pub(crate) struct RingLog {
+    path: &'static str,
+    file: File,
+    len: usize,
+    limit: usize,
+}
+
+impl RingLog {
+    pub(crate) fn create(path: &'static str, limit: usize) -> Result<Self> {
+        let file = File::create(path).with_context(|| format!("Creating {path}"))?;
+        Ok(Self {
+            path,
+            file,
+            len: 0,
+            limit,
+        })
+    }
+
+    pub(crate) fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.file.write_all(buf)?;
+        self.len += buf.len();
+        if self.len > 2 * self.limit {
+            self.trim()?;
+        }
+        Ok(())
+    }
+
+    /// Keep the last `limit` bytes, starting at a line boundary.
+    /// The file is replaced so readers never see a partial rewrite.
+    fn trim(&mut self) -> Result<()> {
+        let contents = std::fs::read(self.path)?;
+        let tail = trim_to_limit(&contents, self.limit);
+        let tmp = format!("{}.tmp", self.path);
+        std::fs::write(&tmp, tail)?;
+        std::fs::rename(&tmp, self.path)?;
+        self.file = std::fs::OpenOptions::new().append(true).open(self.path)?;
+        self.len = tail.len();
+        Ok(())
+    }
+}
+
+/// The last `limit` bytes of `buf`, without a leading partial line.
+fn trim_to_limit(buf: &[u8], limit: usize) -> &[u8] {
+    if buf.len() <= limit {
+        return buf;
+    }
+    let tail = &buf[buf.len() - limit..];
+    match tail.iter().position(|&b| b == b'\n') {
+        Some(i) => &tail[i + 1..],
+        None => tail,
+    }
+}
+
+/// Relays the serial console between QEMU, the console log and attached clients.
+pub(crate) struct ConsoleMux {
+    serial: UnixListener,
+    clients: UnixListener,
+    log: RingLog,
+    status_writer: StatusWriter,
+}
+
+impl ConsoleMux {
+    /// Listen on the console sockets. This must happen before QEMU is spawned,
+    /// since it connects to [`SERIAL_SOCKET_PATH`] on startup.
+    pub(crate) fn bind(status_writer: StatusWriter) -> Result<Self> {
+        let serial = UnixListener::bind(SERIAL_SOCKET_PATH)
+            .with_context(|| format!("Binding {SERIAL_SOCKET_PATH}"))?;
+        let clients = UnixListener::bind(CONSOLE_SOCKET_PATH)
+            .with_context(|| format!("Binding {CONSOLE_SOCKET_PATH}"))?;
+        let log = RingLog::create(CONSOLE_LOG_PATH, CONSOLE_LOG_LIMIT)?;
+        Ok(Self {
+            serial,
+            clients,
+            log,
+            status_writer,
+        })
+    }
+
+    /// Relay the console until QEMU closes it.
+    pub(crate) async fn run(mut self) -> Result<()> {
+        let (serial, _) = self.serial.accept().await?;
+        let (mut serial_rx, mut serial_tx) = serial.into_split();
+        debug!("QEMU connected to the serial console");
+
+        let (output_tx, _) = broadcast::channel::<Vec<u8>>(256);
+        let (input_tx, mut input_rx) = mpsc::channel::<Vec<u8>>(16);
+        let mut buf = vec![0u8; 8192];
+        let mut panic_scanner = MarkerScanner::new(KERNEL_PANIC_MARKER);
+        loop {
+            tokio::select! {
+                n = serial_rx.read(&mut buf) => {
+                    let n = n?;
+                    if n == 0 {
+                        debug!("Serial console closed");
+                        return Ok(());
+                    }
+                    self.log.write(&buf[..n])?;
+                    if panic_scanner.scan(&buf[..n]) {
+                        let failure = BootFailure::new(BootFailureKind::KernelPanic, "Guest kernel panicked");
+                        // Keep relaying, so the console still shows the panic
+                        if let Err(e) = self.status_writer.fail(failure, true) {
+                            tracing::warn!("Failed to record the kernel panic: {e:#}");
+                        }
+                    }
+                    // No receivers just means nobody is attached
+                    let _ = output_tx.send(buf[..n].to_vec());
+                }
+                Some(input) = input_rx.recv() => {
+                    serial_tx.write_all(&input).await?;
+                }
+                client = self.clients.accept() => {
+                    let (client, _) = client?;
+                    debug!("Console client attached");
+                    tokio::spawn(relay_client(client, output_tx.subscribe(), input_tx.clone()));
+                }
+            }
+        }
+    }
+}
+
+/// Finds the first occurrence of a marker in a stream of chunks.
+struct MarkerScanner {
+    marker: &'static [u8],
+    /// The end of the previous chunk, in case the marker spans chunks
+    carry: Vec<u8>,
+    found: bool,
+}
+
+impl MarkerScanner {
+    fn new(marker: &'static [u8]) -> Self {
+        Self {
+            marker,
+            carry: Vec::new(),
+            found: false,
+        }
+    }
+
+    /// Returns true the first time the marker is seen.
+    fn scan(&mut self, chunk: &[u8]) -> bool {
+        if self.found {
+            return false;
+        }
+        self.carry.extend_from_slice(chunk);
+        if self
+            .carry
+            .windows(self.marker.len())
+            .any(|w| w == self.marker)
+        {
+            self.found = true;
+            return true;
+        }
+        let keep = self.marker.len() - 1;
+        let start = self.carry.len().saturating_sub(keep);
+        self.carry.drain(..start);
+        false
+    }
+}
+
+/// Relay the console between one client and the mux.
+async fn relay_client(
+    client: tokio::net::UnixStream,
+    mut output: broadcast::Receiver<Vec<u8>>,
+    input: mpsc::Sender<Vec<u8>>,
+) {
+    let (mut client_rx, mut client_tx) = client.into_split();
+    let to_client = async move {
+        loop {
+            match output.recv().await {
+                Ok(data) => {
+                    if client_tx.write_all(&data).await.is_err() {
+                        break;
+                    }
+                }
+                // A slow client misses some output rather than stalling the VM
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    };
+    let from_client = async move {
+        let mut buf = vec![0u8; 1024];
+        loop {
+            match client_rx.read(&mut buf).await {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if input.send(buf[..n].to_vec()).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    };
+    tokio::select! {
+        _ = to_client => {}
+        _ = from_client => {}
+    }
+    debug!("Console client detached");
+}
+
+/// Puts a terminal in raw mode, restoring it when dropped.
+struct RawTerminal {
+    fd: i32,
+    saved: libc::termios,
+}
+
+impl RawTerminal {
+    fn enable(f: &impl AsRawFd) -> Result<Self> {
+        let fd = f.as_raw_fd();
+        // SAFETY: termios is plain data, and fd is a terminal we have open
+        unsafe {
+            let mut termios: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(std::io::Error::last_os_error()).context("tcgetattr");
+            }
+            let saved = termios;
+            libc::cfmakeraw(&mut termios);
+            if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+                return Err(std::io::Error::last_os_error()).context("tcsetattr");
+            }
+            Ok(Self { fd, saved })
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        // SAFETY: restores the attributes saved in enable()
+        unsafe {
+            libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+/// Attach stdio to the console; runs inside the container.
+pub(crate) fn attach() -> Result<()> {
+    let stream = UnixStream::connect(CONSOLE_SOCKET_PATH).map_err(|e| {
+        eyre!("Console is not available (VM not started, or started with --console): {e}")
+    })?;
+    let stdin = std::io::stdin();
+    let raw = if stdin.is_terminal() {
+        Some(RawTerminal::enable(&stdin)?)
+    } else {
+        None
+    };
+
+    // Either side ending ends the session
+    let (done_tx, done_rx) = std::sync::mpsc::channel::<&'static str>();
+    {
+        let mut stream = stream.try_clone()?;
+        let done_tx = done_tx.clone();
+        std::thread::spawn(move || {
+            let mut stdout = std::io::stdout();
+            let mut buf = vec![0u8; 8192];
+            while let Ok(n) = stream.read(&mut buf) {
+                if n == 0 || stdout.write_all(&buf[..n]).is_err() || stdout.flush().is_err() {
+                    break;
+                }
+            }
+            let _ = done_tx.send("Console closed");
+        });
+    }
+    {
+        let mut stream = stream.try_clone()?;
+        std::thread::spawn(move || {
+            let mut stdin = std::io::stdin().lock();
+            let mut buf = vec![0u8; 1024];
+            let reason = loop {
+                let n = match stdin.read(&mut buf) {
+                    Ok(0) | Err(_) => break "Input closed",
+                    Ok(n) => n,
+                };
+                let (input, detach) = split_at_escape(&buf[..n]);
+                if stream.write_all(input).is_err() {
+                    break "Console closed";
+                }
+                if detach {
+                    break "Detached from console";
+                }
+            };
+            let _ = done_tx.send(reason);
+        });
+    }
+
+    let reason = done_rx.recv()?;
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+    drop(raw);
+    eprintln!("\n{reason}");
+    Ok(())
+}
+
+/// Split input at [`ESCAPE_CHAR`], returning the input before it and whether it was found.
+fn split_at_escape(buf: &[u8]) -> (&[u8], bool) {
+    match buf.iter().position(|&b| b == ESCAPE_CHAR) {
+        Some(i) => (&buf[..i], true),
+        None => (buf, false),
+    }
+}
+
+/// Print the console log; runs inside the container.
+pub(crate) fn print_log() -> Result<()> {
+    let mut f = File::open(CONSOLE_LOG_PATH).map_err(|e| {
+        eyre!("Console log is not available (VM not started, or started with --console): {e}")
+    })?;
+    std::io::copy(&mut f, &mut std::io::stdout().lock())?;
+    Ok(())
+}
+
+/// Attach to the console of the VM in the given container.
+pub fn run(opts: ConsoleOpts) -> Result<()> {
+    let mut cmd = Command::new("podman");
+    cmd.args(["exec", "-i"]);
+    let interactive = !opts.log && std::io::stdin().is_terminal();
+    if interactive {
+        cmd.arg("-t");
+    }
+    cmd.args([
+        &opts.container_name,
+        crate::run_ephemeral::ENTRYPOINT,
+        "console",
+    ]);
+    if opts.log {
+        cmd.arg("--log");
+    } else if interactive {
+        eprintln!(
+            "Connected to the console of {}; press Ctrl-] to detach",
+            opts.container_name
+        );
+    }
+    let status = cmd
+        .status()
+        .map_err(|e| eyre!("Failed to run podman exec: {}", e))?;
+    if !status.success() {
+        return Err(eyre!(
+            "Failed to attach to the console of {}",
+            opts.container_name
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_to_limit() {
+        assert_eq!(trim_to_limit(b"short\n", 10), b"short\n");
+        assert_eq!(trim_to_limit(b"line one\nline two\n", 12), b"line two\n");
+        assert_eq!(trim_to_limit(b"0123456789", 4), b"6789");
+    }
+
+    #[test]
+    fn test_marker_scanner() {
+        let mut s = MarkerScanner::new(KERNEL_PANIC_MARKER);
+        assert!(!s.scan(b"[    1.0] Kernel pan"));
+        assert!(s.scan(b"ic - not syncing: VFS: Unable to mount root fs\n"));
+        // Only reported once
+        assert!(!s.scan(b"Kernel panic - not syncing"));
+    }
+
+    #[test]
+    fn test_split_at_escape() {
+        assert_eq!(split_at_escape(b"ls\r"), (&b"ls\r"[..], false));
+        assert_eq!(split_at_escape(b"ab\x1dcd"), (&b"ab"[..], true));
+        assert_eq!(split_at_escape(b"\x1d"), (&b""[..], true));
+    }
+}
@@ -22,6 +22,18 @@ pub enum ContainerCommands {
 
     /// Monitor VM status file using inotify
     MonitorStatus(MonitorStatusOpts),
+
+    /// Execute a command in the VM over vsock
+    Exec(ExecOpts),
+
+    /// Stream the forwarded VM journal
+    Logs(LogsOpts),
+
+    /// Attach to the VM serial console
+    Console(ConsoleOpts),
+
+    /// Stop, pause, resume or reset the VM over QMP
+    Control(ControlOpts),
 }
 
 #[derive(Parser)]
@@ -34,6 +46,38 @@ pub struct SshOpts {
 #[derive(Parser)]
 pub struct MonitorStatusOpts {}
 
+#[derive(Parser)]
+pub struct ExecOpts {
+    /// Command and arguments to execute
+    #[clap(required = true, allow_hyphen_values = true)]
+    pub args: Vec<String>,
+}
+
+#[derive(Parser)]
+pub struct LogsOpts {
+    /// Keep streaming new entries
+    #[clap(long)]
+    pub follow: bool,
+}
+
+#[derive(Parser)]
+pub struct ConsoleOpts {
+    /// Print the console log instead of attaching
+    #[clap(long)]
+    pub log: bool,
+}
+
+#[derive(Parser)]
+pub struct ControlOpts {
+    /// The operation to perform
+    #[clap(value_enum)]
+    pub action: crate::ephemeral_lifecycle::VmAction,
+
+    /// How long to wait for the guest to power off when stopping
+    #[clap(long, default_value = "60s")]
+    pub timeout: String,
+}
+
 /// Configuration passed via BCK_CONFIG environment variable
 #[derive(Serialize, Deserialize)]
 pub struct ContainerConfig {
@@ -84,6 +128,11 @@ pub fn ssh_to_vm(opts: SshOpts) -> Result<()> {
     std::process::exit(status.code().unwrap_or(1));
 }
 
+pub fn exec_in_vm(opts: ExecOpts) -> Result<()> {
+    let code = crate::vsock_exec::exec_in_vm(&opts.args)?;
+    std::process::exit(code);
+}
+
 pub fn monitor_status(_opts: MonitorStatusOpts) -> Result<()> {
     crate::status_monitor::monitor_and_stream_status()
 }
@@ -113,6 +162,32 @@ pub async fn run(opts: ContainerEntrypointOpts) -> Result<()> {
                 ContainerCommands::MonitorStatus(monitor_opts) => {
                     tokio::task::spawn_blocking(move || monitor_status(monitor_opts)).await?
                 }
+                ContainerCommands::Exec(exec_opts) => {
+                    tokio::task::spawn_blocking(move || exec_in_vm(exec_opts)).await?
+                }
+                ContainerCommands::Console(console_opts) => {
+                    tokio::task::spawn_blocking(move || {
+                        if console_opts.log {
+                            crate::console::print_log()
+                        } else {
+                            crate::console::attach()
+                        }
+                    })
+                    .await?
+                }
+                ContainerCommands::Control(control_opts) => {
+                    tokio::task::spawn_blocking(move || {
+                        let timeout = crate::utils::parse_duration(&control_opts.timeout)?;
+                        crate::ephemeral_lifecycle::control(control_opts.action, timeout)
+                    })
+                    .await?
+                }
+                ContainerCommands::Logs(logs_opts) => {
+                    tokio::task::spawn_blocking(move || {
+                        crate::ephemeral_logs::stream_journal(logs_opts.follow)
+                    })
+                    .await?
+                }
             }
         } => r
     }
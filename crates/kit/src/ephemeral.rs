@@ -6,14 +6,23 @@
 
 use clap::Subcommand;
 use color_eyre::{eyre::eyre, Result};
-use comfy_table::{presets::UTF8_FULL, Table};
 use serde::{Deserialize, Serialize};
 
 // Re-export the existing implementations
+use crate::boot_report;
+use crate::console;
+use crate::ephemeral_cp;
+use crate::ephemeral_inspect;
+use crate::ephemeral_lifecycle::{self, VmAction};
+use crate::ephemeral_logs;
+use crate::ephemeral_ps;
+use crate::ephemeral_test;
+use crate::ephemeral_wait;
 use crate::hostexec;
 use crate::run_ephemeral;
 use crate::run_ephemeral_ssh;
 use crate::ssh;
+use crate::vsock_exec;
 
 /// Label used to identify bcvk ephemeral containers
 const EPHEMERAL_LABEL: &str = "bcvk.ephemeral=1";
@@ -30,6 +39,10 @@ pub struct SshOpts {
     /// used when starting the ephemeral VM.
     pub container_name: String,
 
+    /// User to connect as (default: the VM's --user, or root)
+    #[clap(long)]
+    pub user: Option<String>,
+
     /// Additional SSH client arguments to pass through
     ///
     /// Standard ssh arguments like -v for verbose output, -L for
@@ -38,6 +51,19 @@ pub struct SshOpts {
     pub args: Vec<String>,
 }
 
+/// Options for executing a command in a running VM over vsock.
+///
+/// Unlike `ssh`, this does not require sshd in the image.
+#[derive(clap::Parser, Debug)]
+pub struct ExecOpts {
+    /// Name or ID of the container running the target VM
+    pub container_name: String,
+
+    /// Command and arguments to execute in the VM
+    #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    pub command: Vec<String>,
+}
+
 /// Container list entry for ephemeral VMs
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
@@ -59,6 +85,44 @@ pub struct ContainerListEntry {
 
     /// Container command
     pub command: Vec<String>,
+
+    /// Ports published from the host to the VM
+    #[serde(default)]
+    pub ports: Option<Vec<ContainerPort>>,
+
+    /// Host PID of the container's init process, 0 if it is not running
+    #[serde(default)]
+    pub pid: u32,
+}
+
+/// A port mapping as reported by `podman ps`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContainerPort {
+    /// Host address the port is bound to (empty for all addresses)
+    #[serde(default)]
+    pub host_ip: String,
+
+    /// Port inside the container, which is also the guest port
+    pub container_port: u16,
+
+    /// Port on the host
+    pub host_port: u16,
+
+    /// Transport protocol (tcp or udp)
+    pub protocol: String,
+}
+
+impl std::fmt::Display for ContainerPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.host_ip.is_empty() {
+            write!(f, "{}:", self.host_ip)?;
+        }
+        write!(
+            f,
+            "{}->{}/{}",
+            self.host_port, self.container_port, self.protocol
+        )
+    }
 }
 
 /// Ephemeral VM operations
@@ -76,13 +140,57 @@ pub enum EphemeralCommands {
     #[clap(name = "ssh")]
     Ssh(SshOpts),
 
-    /// List ephemeral VM containers
+    /// Execute a command in a running VM over vsock
+    #[clap(name = "exec")]
+    Exec(ExecOpts),
+
+    /// Copy files between the host and a running VM
+    #[clap(name = "cp")]
+    Cp(ephemeral_cp::CpOpts),
+
+    /// Shut down a running VM cleanly, stopping it if the guest does not power off in time
+    #[clap(name = "stop")]
+    Stop(ephemeral_lifecycle::StopOpts),
+
+    /// Pause the vCPUs of a running VM
+    #[clap(name = "pause")]
+    Pause(ephemeral_lifecycle::VmRefOpts),
+
+    /// Resume a paused VM
+    #[clap(name = "resume")]
+    Resume(ephemeral_lifecycle::VmRefOpts),
+
+    /// Hard reset a running VM
+    #[clap(name = "reset")]
+    Reset(ephemeral_lifecycle::VmRefOpts),
+
+    /// Attach to the serial console of a running VM
+    #[clap(name = "console")]
+    Console(console::ConsoleOpts),
+
+    /// Show the journal of a running VM
+    #[clap(name = "logs")]
+    Logs(ephemeral_logs::LogsOpts),
+
+    /// Wait until a running VM reaches a target, a unit is active, SSH is up or it exits
+    #[clap(name = "wait")]
+    Wait(ephemeral_wait::WaitOpts),
+
+    /// Show when a VM reached each boot phase
+    #[clap(name = "boot-report")]
+    BootReport(boot_report::BootReportOpts),
+
+    /// Show detailed information about a running VM
+    #[clap(name = "inspect")]
+    Inspect(ephemeral_inspect::EphemeralInspectOpts),
+
+    /// Run a directory of test scripts in ephemeral VMs, producing TAP and JUnit reports
+    #[clap(name = "test")]
+    Test(ephemeral_test::TestOpts),
+
+    /// List ephemeral VM containers with their boot state and resource usage
     #[clap(name = "ps")]
-    Ps {
-        /// Output as structured JSON instead of table format
-        #[clap(long)]
-        json: bool,
-    },
+    Ps(ephemeral_ps::PsOpts),
 
     /// Remove all ephemeral VM containers
     #[clap(name = "rm-all")]
@@ -109,59 +217,39 @@ impl EphemeralCommands {
                     progress_bar,
                 )?;
 
-                ssh::connect_via_container(&opts.container_name, opts.args)
+                ssh::connect_via_container(&opts.container_name, opts.user.as_deref(), opts.args)
+            }
+            EphemeralCommands::Exec(opts) => {
+                let status = vsock_exec::exec_via_container(&opts.container_name, &opts.command)?;
+                std::process::exit(status.code().unwrap_or(1));
+            }
+            EphemeralCommands::Cp(opts) => ephemeral_cp::run(opts),
+            EphemeralCommands::Stop(opts) => {
+                ephemeral_lifecycle::run(&opts.container_name, VmAction::Stop, Some(&opts.timeout))
+            }
+            EphemeralCommands::Pause(opts) => {
+                ephemeral_lifecycle::run(&opts.container_name, VmAction::Pause, None)
+            }
+            EphemeralCommands::Resume(opts) => {
+                ephemeral_lifecycle::run(&opts.container_name, VmAction::Resume, None)
             }
-            EphemeralCommands::Ps { json } => {
-                let containers = list_ephemeral_containers()?;
-
-                if json {
-                    let json_output = serde_json::to_string_pretty(&containers)?;
-                    println!("{}", json_output);
-                } else {
-                    // Create a table using comfy_table
-                    let mut table = Table::new();
-                    table.load_preset(UTF8_FULL).set_header(vec![
-                        "CONTAINER ID",
-                        "IMAGE",
-                        "CREATED",
-                        "STATUS",
-                        "NAMES",
-                    ]);
-
-                    for container in containers {
-                        let id = if container.id.len() > 12 {
-                            &container.id[..12]
-                        } else {
-                            &container.id
-                        };
-
-                        let names = container.names.join(", ");
-                        let image = if container.image.len() > 30 {
-                            format!("{}...", &container.image[..30])
-                        } else {
-                            container.image.clone()
-                        };
-
-                        table.add_row(vec![
-                            id.to_string(),
-                            image,
-                            container.created_at,
-                            container.state,
-                            names,
-                        ]);
-                    }
-
-                    println!("{}", table);
-                }
-                Ok(())
+            EphemeralCommands::Reset(opts) => {
+                ephemeral_lifecycle::run(&opts.container_name, VmAction::Reset, None)
             }
+            EphemeralCommands::Console(opts) => console::run(opts),
+            EphemeralCommands::Logs(opts) => ephemeral_logs::run(opts),
+            EphemeralCommands::Wait(opts) => ephemeral_wait::run(opts),
+            EphemeralCommands::BootReport(opts) => boot_report::run(opts),
+            EphemeralCommands::Inspect(opts) => ephemeral_inspect::run(opts),
+            EphemeralCommands::Test(opts) => ephemeral_test::run(opts),
+            EphemeralCommands::Ps(opts) => ephemeral_ps::run(opts),
             EphemeralCommands::RmAll { force } => remove_all_ephemeral_containers(force),
         }
     }
 }
 
 /// List ephemeral VM containers with bcvk.ephemeral=1 label
-fn list_ephemeral_containers() -> Result<Vec<ContainerListEntry>> {
+pub(crate) fn list_ephemeral_containers() -> Result<Vec<ContainerListEntry>> {
     use bootc_utils::CommandRunExt;
 
     let containers: Vec<ContainerListEntry> = hostexec::command("podman", None)?
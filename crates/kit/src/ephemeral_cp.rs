@@ -0,0 +1,183 @@
+//! Copying files between the host and a running ephemeral VM.
+//!
+//! Files are streamed as a tar archive through the vsock exec agent (see
+//! [`crate::vsock_exec`]), so this works on images without sshd and preserves
+//! directory structure and file modes.
+
+use std::process::{Command, Stdio};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::{eyre::eyre, Result};
+use tracing::debug;
+
+use crate::vsock_exec;
+
+/// Archive `$1` to stdout, relative to its parent directory.
+const CREATE_SCRIPT: &str = r#"exec tar -c -f - -C "$(dirname "$1")" -- "$(basename "$1")""#;
+
+/// Extract an archive of a single entry named `$2` from stdin. If `$1` is an
+/// existing directory the entry is extracted into it, otherwise it is
+/// extracted as `$1`. Remaining arguments are passed to tar.
+const EXTRACT_SCRIPT: &str = r#"set -eu
+dst=$1
+name=$2
+shift 2
+if [ -d "$dst" ]; then
+    exec tar -x -p -f - -C "$dst" "$@"
+fi
+tmp=$(mktemp -d "$(dirname "$dst")/.bcvk-cp.XXXXXX")
+trap 'rm -rf "$tmp"' EXIT
+tar -x -p -f - -C "$tmp" "$@"
+mv "$tmp/$name" "$dst"
+"#;
+
+/// Options for copying files between the host and a running VM.
+#[derive(clap::Parser, Debug)]
+pub struct CpOpts {
+    /// Source, either a host path or NAME:PATH in the VM of container NAME
+    pub source: String,
+
+    /// Destination, either a host path or NAME:PATH in the VM of container NAME
+    ///
+    /// If the destination is an existing directory, the source is copied into it.
+    pub destination: String,
+}
+
+/// One side of a copy.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CpLocation {
+    /// A path on the host
+    Host(Utf8PathBuf),
+    /// A path in the VM running in the given container
+    Vm {
+        container_name: String,
+        path: Utf8PathBuf,
+    },
+}
+
+impl CpLocation {
+    /// Parse `[NAME:]PATH`. A prefix containing `/` is part of a host path, so
+    /// host paths with colons can be given as e.g. `./a:b`.
+    pub(crate) fn parse(spec: &str) -> Self {
+        match spec.split_once(':') {
+            Some((name, path)) if !name.is_empty() && !name.contains('/') => Self::Vm {
+                container_name: name.to_owned(),
+                path: path.into(),
+            },
+            _ => Self::Host(spec.into()),
+        }
+    }
+}
+
+/// Build the arguments for running one of our scripts with `sh -c`.
+fn sh_args(script: &str, args: &[&str]) -> Vec<String> {
+    ["sh", "-c", script, "sh"]
+        .iter()
+        .chain(args)
+        .map(|s| s.to_string())
+        .collect()
+}
+
+fn host_command(args: Vec<String>) -> Command {
+    let mut cmd = Command::new(&args[0]);
+    cmd.args(&args[1..]);
+    cmd
+}
+
+fn file_name(path: &Utf8Path) -> Result<&str> {
+    path.file_name()
+        .ok_or_else(|| eyre!("Cannot determine the file name to copy from: {path}"))
+}
+
+/// Copy files between the host and a running VM.
+pub fn run(opts: CpOpts) -> Result<()> {
+    let source = CpLocation::parse(&opts.source);
+    let destination = CpLocation::parse(&opts.destination);
+
+    let (mut producer, mut consumer) = match (&source, &destination) {
+        (
+            CpLocation::Host(src),
+            CpLocation::Vm {
+                container_name,
+                path,
+            },
+        ) => {
+            let name = file_name(src)?;
+            let producer = host_command(sh_args(CREATE_SCRIPT, &[src.as_str()]));
+            // Files copied into the VM are owned by root there
+            let extract = sh_args(EXTRACT_SCRIPT, &[path.as_str(), name, "--no-same-owner"]);
+            (producer, vsock_exec::exec_command(container_name, &extract))
+        }
+        (
+            CpLocation::Vm {
+                container_name,
+                path,
+            },
+            CpLocation::Host(dst),
+        ) => {
+            let name = file_name(path)?;
+            let create = sh_args(CREATE_SCRIPT, &[path.as_str()]);
+            let consumer = host_command(sh_args(EXTRACT_SCRIPT, &[dst.as_str(), name]));
+            (vsock_exec::exec_command(container_name, &create), consumer)
+        }
+        (CpLocation::Host(_), CpLocation::Host(_)) => {
+            return Err(eyre!(
+                "One of source or destination must be in a VM (NAME:PATH)"
+            ));
+        }
+        (CpLocation::Vm { .. }, CpLocation::Vm { .. }) => {
+            return Err(eyre!("Copying directly between VMs is not supported"));
+        }
+    };
+    debug!("Copying {source:?} to {destination:?}");
+
+    let mut producer = producer
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| eyre!("Failed to start archiving {}: {}", opts.source, e))?;
+    let archive = producer.stdout.take().unwrap();
+    let consumer_status = consumer
+        .stdin(archive)
+        .status()
+        .map_err(|e| eyre!("Failed to start extracting to {}: {}", opts.destination, e))?;
+    let producer_status = producer.wait()?;
+
+    if !producer_status.success() {
+        return Err(eyre!(
+            "Failed to archive {} (exit code: {:?})",
+            opts.source,
+            producer_status.code()
+        ));
+    }
+    if !consumer_status.success() {
+        return Err(eyre!(
+            "Failed to extract to {} (exit code: {:?})",
+            opts.destination,
+            consumer_status.code()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cp_location() {
+        assert_eq!(
+            CpLocation::parse("myvm:/var/log"),
+            CpLocation::Vm {
+                container_name: "myvm".into(),
+                path: "/var/log".into()
+            }
+        );
+        assert_eq!(
+            CpLocation::parse("/tmp/fixtures"),
+            CpLocation::Host("/tmp/fixtures".into())
+        );
+        assert_eq!(CpLocation::parse("./a:b"), CpLocation::Host("./a:b".into()));
+        assert_eq!(CpLocation::parse(":foo"), CpLocation::Host(":foo".into()));
+    }
+}
@@ -0,0 +1,154 @@
+//! ephemeral inspect command - show detailed information about an ephemeral VM
+//!
+//! This is the ephemeral counterpart to `libvirt inspect`. The information
+//! comes from the supervisor status the container keeps updated, read with
+//! `podman exec`.
+
+use clap::Parser;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use serde::Serialize;
+use yaml_rust2::{YamlEmitter, YamlLoader};
+
+use crate::run_ephemeral_ssh::read_supervisor_status;
+use crate::supervisor_status::{SupervisorState, SupervisorStatus, VmInfo};
+
+/// Options for inspecting an ephemeral VM
+#[derive(Debug, Parser)]
+pub struct EphemeralInspectOpts {
+    /// Name or ID of the container running the VM
+    pub container_name: String,
+
+    /// Output format (yaml or json)
+    #[clap(long, default_value = "yaml")]
+    pub format: String,
+
+    /// Output as JSON; the same as --format=json
+    #[clap(long, conflicts_with = "format")]
+    pub json: bool,
+}
+
+/// What `ephemeral inspect` shows
+#[derive(Debug, Serialize)]
+struct VmInspect {
+    name: String,
+    /// e.g. `booting` or `ready`
+    state: String,
+    running: bool,
+    ssh_access: bool,
+    last_target: Option<String>,
+    /// Why the VM failed, if it did
+    failure: Option<String>,
+    #[serde(flatten)]
+    vm: Option<VmInfo>,
+}
+
+impl VmInspect {
+    fn new(name: &str, status: SupervisorStatus) -> Self {
+        let failure = match &status.state {
+            Some(SupervisorState::Failed(failure)) => Some(failure.message.clone()),
+            _ => None,
+        };
+        Self {
+            name: name.to_owned(),
+            state: status.boot_state().to_owned(),
+            running: status.running,
+            ssh_access: status.ssh_access,
+            last_target: status.last_target().map(ToOwned::to_owned),
+            failure,
+            vm: status.vm,
+        }
+    }
+}
+
+fn to_yaml(inspect: &VmInspect) -> Result<String> {
+    // JSON is YAML; loading it rather than going through serde_json::Value
+    // keeps the fields in declaration order
+    let json = serde_json::to_string(inspect)?;
+    let docs = YamlLoader::load_from_str(&json).map_err(|e| eyre!("Failed to parse JSON: {e}"))?;
+    let mut out = String::new();
+    YamlEmitter::new(&mut out)
+        .dump(&docs[0])
+        .map_err(|e| eyre!("Failed to format YAML: {e}"))?;
+    // Drop the document start marker
+    let out = out.strip_prefix("---\n").unwrap_or(&out);
+    Ok(format!("{out}\n"))
+}
+
+/// Execute the ephemeral inspect command
+pub fn run(opts: EphemeralInspectOpts) -> Result<()> {
+    let status = read_supervisor_status(&opts.container_name).ok_or_else(|| {
+        eyre!(
+            "No status available for '{}'; is it a running ephemeral VM?",
+            opts.container_name
+        )
+    })?;
+    let inspect = VmInspect::new(&opts.container_name, status);
+
+    let format = if opts.json { "json" } else { &opts.format };
+    match format {
+        "yaml" => print!("{}", to_yaml(&inspect)?),
+        "json" => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&inspect)
+                    .with_context(|| "Failed to serialize VM as JSON")?
+            );
+        }
+        _ => return Err(eyre!("Unsupported format: {}", opts.format)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supervisor_status::MountInfo;
+
+    #[test]
+    fn test_inspect_yaml() -> Result<()> {
+        let status = SupervisorStatus {
+            state: Some(SupervisorState::ReachedTarget("multi-user.target".into())),
+            ssh_access: true,
+            running: true,
+            reached_targets: vec!["multi-user.target".into()],
+            vm: Some(VmInfo {
+                qemu_pid: 42,
+                vsock_cid: None,
+                memory_mb: 4096,
+                vcpus: 2,
+                published_ports: vec!["8080:80/tcp".into()],
+                mounts: vec![MountInfo {
+                    source: "/srv/data".into(),
+                    target: "/run/virtiofs-mnt-data".into(),
+                    readonly: true,
+                }],
+                kernel_version: Some("6.12.0".into()),
+                started: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            }),
+        };
+        let yaml = to_yaml(&VmInspect::new("myvm", status))?;
+        let expected = indoc::indoc! {r#"
+            name: myvm
+            state: ready
+            running: true
+            ssh_access: true
+            last_target: multi-user.target
+            failure: ~
+            qemu_pid: 42
+            vsock_cid: ~
+            memory_mb: 4096
+            vcpus: 2
+            published_ports:
+              - "8080:80/tcp"
+            mounts:
+              - source: /srv/data
+                target: /run/virtiofs-mnt-data
+                readonly: true
+            kernel_version: 6.12.0
+            started: "2023-11-14T22:13:20Z"
+        "#};
+        assert_eq!(yaml, expected);
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+//! Lifecycle control of running ephemeral VMs over QMP.
+//!
+//! QEMU listens for QMP on [`qemu::QMP_SOCKET_PATH`] inside the container.
+//! The host commands run `entrypoint control ACTION` through `podman exec`,
+//! which connects to that socket. Unlike `podman stop`, which kills QEMU
+//! (the container uses `--stop-signal=SIGKILL`), `bcvk ephemeral stop`
+//! requests an ACPI shutdown so the guest can stop its services cleanly.
+
+use std::process::Command;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use color_eyre::eyre::eyre;
+use color_eyre::Result;
+use tracing::debug;
+
+use crate::qemu::{self, QmpClient};
+use crate::{podman, utils};
+
+/// A VM lifecycle operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum VmAction {
+    /// ACPI shutdown, then stop QEMU if the guest does not power off in time
+    Stop,
+    /// Suspend the vCPUs
+    Pause,
+    /// Resume the vCPUs after pause
+    Resume,
+    /// Hard reset, like pressing the reset button
+    Reset,
+}
+
+impl VmAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            VmAction::Stop => "stop",
+            VmAction::Pause => "pause",
+            VmAction::Resume => "resume",
+            VmAction::Reset => "reset",
+        }
+    }
+}
+
+/// Options for stopping a running VM.
+#[derive(clap::Parser, Debug)]
+pub struct StopOpts {
+    /// Name or ID of the container running the target VM
+    pub container_name: String,
+
+    #[clap(
+        long,
+        value_name = "DURATION",
+        default_value = "60s",
+        help = "How long to wait for the guest to power off before stopping QEMU"
+    )]
+    pub timeout: String,
+}
+
+/// Options for pausing, resuming or resetting a running VM.
+#[derive(clap::Parser, Debug)]
+pub struct VmRefOpts {
+    /// Name or ID of the container running the target VM
+    pub container_name: String,
+}
+
+/// Perform a lifecycle action on the VM in this container; runs inside the container.
+pub(crate) fn control(action: VmAction, timeout: Duration) -> Result<()> {
+    let mut qmp = QmpClient::connect(qemu::QMP_SOCKET_PATH)?;
+    match action {
+        VmAction::Stop => {
+            // A paused guest cannot react to the power button
+            if qmp.status()? == "paused" {
+                qmp.execute("cont")?;
+            }
+            qmp.execute("system_powerdown")?;
+            if !qmp.wait_closed(timeout)? {
+                eprintln!(
+                    "Guest did not power off within {}s, stopping QEMU",
+                    timeout.as_secs()
+                );
+                qmp.execute("quit")?;
+                qmp.wait_closed(Duration::from_secs(10))?;
+            }
+        }
+        VmAction::Pause => {
+            qmp.execute("stop")?;
+        }
+        VmAction::Resume => {
+            qmp.execute("cont")?;
+        }
+        VmAction::Reset => {
+            qmp.execute("system_reset")?;
+        }
+    }
+    Ok(())
+}
+
+/// Run a lifecycle action on the VM of the given container.
+pub fn run(container_name: &str, action: VmAction, timeout: Option<&str>) -> Result<()> {
+    let mut cmd = Command::new("podman");
+    cmd.args([
+        "exec",
+        container_name,
+        crate::run_ephemeral::ENTRYPOINT,
+        "control",
+        action.as_str(),
+    ]);
+    if let Some(timeout) = timeout {
+        // Validate here for a better error than from inside the container
+        utils::parse_duration(timeout)?;
+        cmd.args(["--timeout", timeout]);
+    }
+    debug!("Running {} on VM of {container_name}", action.as_str());
+    let status = cmd
+        .status()
+        .map_err(|e| eyre!("Failed to run podman exec: {}", e))?;
+
+    if action != VmAction::Stop {
+        if !status.success() {
+            return Err(eyre!(
+                "Failed to {} the VM of {container_name}",
+                action.as_str()
+            ));
+        }
+        return Ok(());
+    }
+
+    // The exec session ends with the container, so that can look like a failure;
+    // a container already removed with --rm is not running either
+    if !status.success() && podman::container_running(container_name).unwrap_or(false) {
+        // e.g. QEMU is unresponsive; fall back to what podman stop would do
+        eprintln!("Clean shutdown failed, killing {container_name}");
+        let status = Command::new("podman")
+            .args(["kill", container_name])
+            .status()
+            .map_err(|e| eyre!("Failed to run podman kill: {}", e))?;
+        if !status.success() {
+            return Err(eyre!("Failed to stop {container_name}"));
+        }
+    }
+    // The container exits once QEMU has; it may already be removed with --rm
+    let _ = Command::new("podman")
+        .args(["wait", container_name])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status();
+    Ok(())
+}
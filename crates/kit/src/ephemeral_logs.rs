@@ -0,0 +1,470 @@
+//! Streaming the journal of a running ephemeral VM to the host.
+//!
+//! This provides `bcvk ephemeral logs`, which works on images without sshd
+//! and from early boot, before the vsock exec agent is listening. A guest
+//! unit enabled in `sysinit.target` runs `journalctl --follow --output=json`
+//! with its output connected to the [`JOURNAL_PORT`] virtio-serial port.
+//! The container keeps the most recent entries in [`JOURNAL_LOG_PATH`] (see
+//! [`record_journal`]), so a long running VM does not fill up `/run`.
+//!
+//! The container side only copies that file; filtering and formatting happen
+//! on the host, so timestamps are shown in the host's time zone.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, Write};
+use std::os::unix::fs::MetadataExt;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use camino::Utf8Path;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use serde_json::{Map, Value};
+use tracing::debug;
+
+use crate::console::RingLog;
+use crate::utils;
+
+/// Name of the virtio-serial port carrying the guest journal
+pub(crate) const JOURNAL_PORT: &str = "journal";
+
+/// Where the guest journal is kept inside the container
+pub(crate) const JOURNAL_LOG_PATH: &str = "/run/bcvk-journal.json";
+
+/// The journal log is trimmed to this size once it grows to twice it
+const JOURNAL_LOG_LIMIT: usize = 8 * 1024 * 1024;
+
+/// Name of the guest unit forwarding the journal
+const FORWARDER_UNIT: &str = "bcvk-journal.service";
+
+/// How often to check for new journal entries when following
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Options for showing the journal of a running VM.
+#[derive(clap::Parser, Debug)]
+pub struct LogsOpts {
+    /// Name or ID of the container running the target VM
+    pub container_name: String,
+
+    /// Keep printing new entries as they are logged
+    #[clap(short, long)]
+    pub follow: bool,
+
+    #[clap(
+        long,
+        value_name = "TIME",
+        help = "Only show entries since TIME, either a duration ago (e.g. 10m) or a timestamp like \"2025-10-16 10:00:00\""
+    )]
+    pub since: Option<String>,
+
+    #[clap(
+        short = 'u',
+        long = "unit",
+        value_name = "UNIT",
+        help = "Only show entries of UNIT; may be given multiple times"
+    )]
+    pub units: Vec<String>,
+}
+
+/// Install the journal forwarder unit, enabled in sysinit.target.
+pub(crate) fn write_guest_forwarder(target_unitdir: &Utf8Path) -> Result<()> {
+    // No default dependencies, so this starts early and keeps forwarding
+    // through shutdown. The cursor file avoids duplicating entries if
+    // journalctl is restarted.
+    let service = format!(
+        r#"[Unit]
+Description=Forward the journal to the host for bcvk ephemeral logs
+DefaultDependencies=no
+After=systemd-journald.service dev-virtio\x2dports-{JOURNAL_PORT}.device
+Requires=dev-virtio\x2dports-{JOURNAL_PORT}.device
+
+[Service]
+ExecStart=journalctl --boot --follow --no-tail --output=json --cursor-file=/run/bcvk-journal.cursor
+StandardOutput=file:/dev/virtio-ports/{JOURNAL_PORT}
+Restart=on-failure
+"#
+    );
+    std::fs::write(target_unitdir.join(FORWARDER_UNIT), service)?;
+    let wants_dir = target_unitdir.join("sysinit.target.wants");
+    std::fs::create_dir_all(&wants_dir)?;
+    std::os::unix::fs::symlink(
+        format!("../{FORWARDER_UNIT}"),
+        wants_dir.join(FORWARDER_UNIT),
+    )?;
+    debug!("Installed {FORWARDER_UNIT}");
+    Ok(())
+}
+
+/// Copy the journal forwarded over `port` (the read end of the
+/// [`JOURNAL_PORT`] pipe) into [`JOURNAL_LOG_PATH`], keeping only the most
+/// recent [`JOURNAL_LOG_LIMIT`] bytes. Runs in the background until QEMU
+/// closes the port.
+pub(crate) fn record_journal(mut port: File) -> Result<()> {
+    let mut log = RingLog::create(JOURNAL_LOG_PATH, JOURNAL_LOG_LIMIT)?;
+    std::thread::spawn(move || {
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let r = port
+                .read(&mut buf)
+                .map_err(Into::into)
+                .and_then(|n| log.write(&buf[..n]).map(|_| n));
+            match r {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    tracing::warn!("Failed to record the guest journal: {e}");
+                    break;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Whether the log `f` was opened from has been trimmed and replaced since.
+fn journal_replaced(f: &File) -> Result<bool> {
+    match std::fs::metadata(JOURNAL_LOG_PATH) {
+        Ok(m) => Ok(m.ino() != f.metadata()?.ino()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Position `reader` after the line `last`, or at the start if it is not
+/// found. A trimmed log keeps a suffix of the previous one, so this is where
+/// a follower continues after switching to it.
+fn skip_past(reader: &mut (impl BufRead + Seek), last: &[u8]) -> Result<()> {
+    if last.is_empty() {
+        return Ok(());
+    }
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            reader.rewind()?;
+            return Ok(());
+        }
+        if line == last {
+            return Ok(());
+        }
+    }
+}
+
+/// Copy the forwarded journal to stdout; runs inside the container.
+pub(crate) fn stream_journal(follow: bool) -> Result<()> {
+    let open = || -> Result<Option<BufReader<File>>> {
+        match File::open(JOURNAL_LOG_PATH) {
+            Ok(f) => Ok(Some(BufReader::new(f))),
+            Err(e) if e.kind() == ErrorKind::NotFound && follow => Ok(None),
+            Err(e) => Err(eyre!("VM journal is not available (VM not started?): {e}")),
+        }
+    };
+    let mut reader = loop {
+        match open()? {
+            Some(r) => break r,
+            None => std::thread::sleep(FOLLOW_INTERVAL),
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    let mut line = Vec::new();
+    // The last complete entry, to continue from when the log is trimmed
+    let mut last = Vec::new();
+    loop {
+        // Checked first: once replaced, the old file is complete, so reading
+        // it to the end below leaves nothing behind
+        let replaced = follow && journal_replaced(reader.get_ref())?;
+        // Only complete entries are copied; a partial one is kept for later
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            if line.ends_with(b"\n") {
+                stdout.write_all(&line)?;
+                std::mem::swap(&mut last, &mut line);
+                line.clear();
+            }
+        }
+        if !follow {
+            break;
+        }
+        if replaced {
+            if let Some(mut r) = open()? {
+                skip_past(&mut r, &last)?;
+                reader = r;
+                line.clear();
+                continue;
+            }
+        }
+        stdout.flush()?;
+        std::thread::sleep(FOLLOW_INTERVAL);
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Which journal entries to show.
+#[derive(Debug, Default)]
+struct JournalFilter {
+    /// Earliest realtime timestamp, in microseconds since the epoch
+    since_usec: Option<u64>,
+    /// Units to show, with a type suffix
+    units: Vec<String>,
+}
+
+impl JournalFilter {
+    fn new(since: Option<&str>, units: &[String]) -> Result<Self> {
+        let since_usec = since
+            .map(|s| parse_since(s, Local::now()))
+            .transpose()?
+            .map(|t| t.timestamp_micros().max(0) as u64);
+        let units = units.iter().map(|u| normalize_unit(u)).collect();
+        Ok(Self { since_usec, units })
+    }
+
+    fn matches(&self, entry: &Map<String, Value>) -> bool {
+        if let Some(since) = self.since_usec {
+            match field(entry, "__REALTIME_TIMESTAMP").and_then(|t| t.parse::<u64>().ok()) {
+                Some(t) if t >= since => {}
+                _ => return false,
+            }
+        }
+        if !self.units.is_empty() {
+            // Like journalctl -u: messages from the unit's processes, and
+            // messages about the unit from systemd
+            let matched = ["_SYSTEMD_UNIT", "UNIT", "OBJECT_SYSTEMD_UNIT"]
+                .iter()
+                .filter_map(|k| field(entry, k))
+                .any(|u| self.units.iter().any(|want| *want == u));
+            if !matched {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Add `.service` to a unit name without a type suffix, as journalctl does.
+pub(crate) fn normalize_unit(unit: &str) -> String {
+    const SUFFIXES: &[&str] = &[
+        ".service",
+        ".socket",
+        ".target",
+        ".mount",
+        ".automount",
+        ".swap",
+        ".timer",
+        ".path",
+        ".slice",
+        ".scope",
+        ".device",
+    ];
+    if SUFFIXES.iter().any(|s| unit.ends_with(s)) {
+        unit.to_owned()
+    } else {
+        format!("{unit}.service")
+    }
+}
+
+/// Parse `--since`: a duration before `now`, an RFC 3339 timestamp, or a
+/// local `YYYY-MM-DD[ HH:MM[:SS]]`.
+fn parse_since(s: &str, now: DateTime<Local>) -> Result<DateTime<Local>> {
+    let s = s.trim();
+    if let Ok(ago) = utils::parse_duration(s) {
+        return Ok(now - chrono::Duration::from_std(ago)?);
+    }
+    if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+        return Ok(t.with_timezone(&Local));
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|d| d.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| eyre!("Invalid --since value: {s}"))?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .ok_or_else(|| eyre!("Invalid local time: {s}"))
+}
+
+/// Get a journal field as text. Fields that are not valid UTF-8 are
+/// serialized as byte arrays, and repeated fields as arrays of values.
+fn field<'a>(entry: &'a Map<String, Value>, key: &str) -> Option<Cow<'a, str>> {
+    match entry.get(key)? {
+        Value::String(s) => Some(Cow::Borrowed(s)),
+        Value::Array(items) if items.iter().all(Value::is_u64) => {
+            let bytes: Vec<u8> = items
+                .iter()
+                .filter_map(|v| v.as_u64().map(|b| b as u8))
+                .collect();
+            Some(Cow::Owned(String::from_utf8_lossy(&bytes).into_owned()))
+        }
+        Value::Array(items) => items.first().and_then(Value::as_str).map(Cow::Borrowed),
+        _ => None,
+    }
+}
+
+/// Format an entry like `journalctl --output=short`.
+fn format_entry(entry: &Map<String, Value>) -> String {
+    let time = field(entry, "__REALTIME_TIMESTAMP")
+        .and_then(|t| t.parse::<i64>().ok())
+        .and_then(DateTime::from_timestamp_micros)
+        .map(|t| t.with_timezone(&Local).format("%b %d %H:%M:%S").to_string())
+        .unwrap_or_default();
+    let host = field(entry, "_HOSTNAME").unwrap_or_default();
+    let ident = field(entry, "SYSLOG_IDENTIFIER")
+        .or_else(|| field(entry, "_COMM"))
+        .unwrap_or(Cow::Borrowed("unknown"));
+    let pid = field(entry, "SYSLOG_PID")
+        .or_else(|| field(entry, "_PID"))
+        .map(|p| format!("[{p}]"))
+        .unwrap_or_default();
+    let message = field(entry, "MESSAGE").unwrap_or_default();
+    format!("{time} {host} {ident}{pid}: {message}")
+}
+
+/// Show the journal of the VM in the given container.
+pub fn run(opts: LogsOpts) -> Result<()> {
+    let filter = JournalFilter::new(opts.since.as_deref(), &opts.units)?;
+
+    let mut cmd = Command::new("podman");
+    cmd.args([
+        "exec",
+        &opts.container_name,
+        crate::run_ephemeral::ENTRYPOINT,
+        "logs",
+    ]);
+    if opts.follow {
+        cmd.arg("--follow");
+    }
+    debug!("Reading journal of container {}", opts.container_name);
+    let mut child = cmd
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| eyre!("Failed to run podman exec: {}", e))?;
+    let reader = BufReader::new(child.stdout.take().unwrap());
+
+    let mut stdout = std::io::stdout().lock();
+    for line in reader.lines() {
+        let line = line.context("Reading journal")?;
+        let Ok(entry) = serde_json::from_str::<Map<String, Value>>(&line) else {
+            // A partial entry from a restarted forwarder
+            debug!("Skipping unparseable journal line: {line}");
+            continue;
+        };
+        if !filter.matches(&entry) {
+            continue;
+        }
+        let r = writeln!(stdout, "{}", format_entry(&entry)).and_then(|_| {
+            if opts.follow {
+                stdout.flush()
+            } else {
+                Ok(())
+            }
+        });
+        match r {
+            Ok(()) => {}
+            // e.g. piped into head
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                let _ = child.kill();
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let status = child.wait()?;
+    if !status.success() && status.code().is_some() {
+        return Err(eyre!(
+            "Failed to read the journal of {}",
+            opts.container_name
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(json: &str) -> Map<String, Value> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_journal_filter() -> Result<()> {
+        let sshd = entry(
+            r#"{"__REALTIME_TIMESTAMP":"2000000","_SYSTEMD_UNIT":"sshd.service","SYSLOG_IDENTIFIER":"sshd","_PID":"42","_HOSTNAME":"vm","MESSAGE":"Server listening"}"#,
+        );
+        let pid1 = entry(
+            r#"{"__REALTIME_TIMESTAMP":"1000000","_SYSTEMD_UNIT":"init.scope","UNIT":"sshd.service","MESSAGE":"Started sshd.service"}"#,
+        );
+        let other = entry(r#"{"__REALTIME_TIMESTAMP":"3000000","MESSAGE":[104,105]}"#);
+
+        let all = JournalFilter::default();
+        assert!([&sshd, &pid1, &other].iter().all(|e| all.matches(e)));
+
+        let f = JournalFilter::new(None, &["sshd".to_owned()])?;
+        assert!(f.matches(&sshd));
+        assert!(f.matches(&pid1));
+        assert!(!f.matches(&other));
+
+        let f = JournalFilter {
+            since_usec: Some(2_000_000),
+            ..Default::default()
+        };
+        assert!(f.matches(&sshd));
+        assert!(!f.matches(&pid1));
+        assert!(f.matches(&other));
+
+        assert_eq!(field(&other, "MESSAGE").as_deref(), Some("hi"));
+        assert!(format_entry(&sshd).ends_with(" vm sshd[42]: Server listening"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_past() -> Result<()> {
+        let trimmed = || std::io::Cursor::new(b"{\"n\":2}\n{\"n\":3}\n{\"n\":4}\n".to_vec());
+        let mut r = trimmed();
+        skip_past(&mut r, b"{\"n\":3}\n")?;
+        let mut rest = String::new();
+        r.read_to_string(&mut rest)?;
+        assert_eq!(rest, "{\"n\":4}\n");
+
+        // Nothing was copied before, or the last entry was trimmed away
+        for last in [&b""[..], b"{\"n\":1}\n"] {
+            let mut r = trimmed();
+            skip_past(&mut r, last)?;
+            assert_eq!(r.position(), 0);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_since() -> Result<()> {
+        let now = Local.with_ymd_and_hms(2025, 10, 16, 12, 0, 0).unwrap();
+        assert_eq!(
+            parse_since("90m", now)?,
+            Local.with_ymd_and_hms(2025, 10, 16, 10, 30, 0).unwrap()
+        );
+        assert_eq!(
+            parse_since("2025-10-16 10:00", now)?,
+            Local.with_ymd_and_hms(2025, 10, 16, 10, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_since("2025-10-16", now)?,
+            Local.with_ymd_and_hms(2025, 10, 16, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            parse_since("2025-10-16T10:00:00Z", now)?.timestamp(),
+            1760608800
+        );
+        assert!(parse_since("yesterday-ish", now).is_err());
+
+        assert_eq!(normalize_unit("sshd"), "sshd.service");
+        assert_eq!(normalize_unit("local-fs.target"), "local-fs.target");
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+//! Listing ephemeral VMs with their boot state and resource usage.
+//!
+//! Beyond what `podman ps` reports, this reads the supervisor status of each
+//! running container (boot state, SSH readiness, vCPUs and memory) and the
+//! CPU time and RSS of the QEMU process, found through the container's
+//! cgroup. CPU usage is measured between two samples: over a short interval
+//! for a single listing, or between refreshes with `--watch`.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use color_eyre::Result;
+use comfy_table::{presets::UTF8_FULL, Table};
+use indicatif::HumanBytes;
+use serde::Serialize;
+
+use crate::ephemeral::{list_ephemeral_containers, ContainerListEntry};
+use crate::run_ephemeral_ssh::read_supervisor_status;
+use crate::supervisor_status::SupervisorStatus;
+use crate::utils;
+
+/// How long CPU usage is measured for without `--watch`
+const CPU_SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Options for listing ephemeral VMs
+#[derive(clap::Parser, Debug)]
+pub struct PsOpts {
+    /// Output as structured JSON instead of table format
+    #[clap(long)]
+    pub json: bool,
+
+    /// Keep refreshing the list, like top
+    #[clap(long, short = 'w', conflicts_with = "json")]
+    pub watch: bool,
+
+    #[clap(
+        long,
+        value_name = "DURATION",
+        default_value = "2s",
+        requires = "watch",
+        help = "How often to refresh with --watch"
+    )]
+    pub interval: String,
+}
+
+/// CPU time and memory of a QEMU process at one point in time
+#[derive(Debug, Clone, Copy)]
+struct QemuSample {
+    pid: u32,
+    /// utime + stime, in clock ticks
+    cpu_ticks: u64,
+    rss_bytes: u64,
+    at: Instant,
+}
+
+impl QemuSample {
+    fn read(pid: u32) -> Option<Self> {
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+        let statm = std::fs::read_to_string(format!("/proc/{pid}/statm")).ok()?;
+        let resident: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+        Some(Self {
+            pid,
+            cpu_ticks: parse_cpu_ticks(&stat)?,
+            rss_bytes: resident * sysconf(libc::_SC_PAGESIZE),
+            at: Instant::now(),
+        })
+    }
+
+    /// CPU usage since `previous`, where 100% is one CPU
+    fn cpu_percent(&self, previous: &QemuSample) -> Option<f64> {
+        let elapsed = self.at.duration_since(previous.at).as_secs_f64();
+        if self.pid != previous.pid || elapsed <= 0.0 {
+            return None;
+        }
+        let ticks = self.cpu_ticks.saturating_sub(previous.cpu_ticks) as f64;
+        Some(ticks / sysconf(libc::_SC_CLK_TCK) as f64 / elapsed * 100.0)
+    }
+}
+
+fn sysconf(name: libc::c_int) -> u64 {
+    // SAFETY: sysconf has no preconditions
+    unsafe { libc::sysconf(name) }.max(1) as u64
+}
+
+/// The sum of utime and stime from `/proc/PID/stat`.
+fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    // The command name may contain spaces and parentheses
+    let (_, fields) = stat.rsplit_once(')')?;
+    let fields: Vec<&str> = fields.split_whitespace().collect();
+    // utime and stime are fields 14 and 15 in proc_pid_stat(5); this starts at field 3
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// The cgroup v2 path from `/proc/PID/cgroup`.
+fn unified_cgroup_path(contents: &str) -> Option<&str> {
+    contents.lines().find_map(|l| l.strip_prefix("0::"))
+}
+
+/// All processes in a cgroup and its descendants.
+fn cgroup_pids(dir: &Path) -> Vec<u32> {
+    let mut pids: Vec<u32> = std::fs::read_to_string(dir.join("cgroup.procs"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.parse().ok())
+        .collect();
+    for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+        if entry.file_type().is_ok_and(|t| t.is_dir()) {
+            pids.extend(cgroup_pids(&entry.path()));
+        }
+    }
+    pids
+}
+
+/// Find QEMU in the cgroup of the container whose init process is `container_pid`.
+fn find_qemu(container_pid: u32) -> Option<u32> {
+    let cgroup = std::fs::read_to_string(format!("/proc/{container_pid}/cgroup")).ok()?;
+    let path = unified_cgroup_path(&cgroup)?;
+    let dir = Path::new("/sys/fs/cgroup").join(path.trim_start_matches('/'));
+    cgroup_pids(&dir).into_iter().find(|pid| {
+        std::fs::read_to_string(format!("/proc/{pid}/comm")).is_ok_and(|c| c.starts_with("qemu"))
+    })
+}
+
+/// A container with what could be read about its VM
+struct VmRow {
+    container: ContainerListEntry,
+    status: Option<SupervisorStatus>,
+    qemu: Option<QemuSample>,
+}
+
+/// List the containers, reading the status of running VMs in parallel.
+fn collect_rows() -> Result<Vec<VmRow>> {
+    let containers = list_ephemeral_containers()?;
+    let rows = std::thread::scope(|s| {
+        let handles: Vec<_> = containers
+            .into_iter()
+            .map(|container| {
+                s.spawn(move || {
+                    if container.state != "running" {
+                        return VmRow {
+                            container,
+                            status: None,
+                            qemu: None,
+                        };
+                    }
+                    let status = read_supervisor_status(&container.id);
+                    let qemu = find_qemu(container.pid).and_then(QemuSample::read);
+                    VmRow {
+                        container,
+                        status,
+                        qemu,
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    Ok(rows)
+}
+
+/// A line of `ephemeral ps` output
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PsEntry {
+    #[serde(flatten)]
+    container: ContainerListEntry,
+    /// e.g. `booting` or `ready`, for running VMs
+    boot_state: Option<String>,
+    ssh_ready: Option<bool>,
+    vcpus: Option<u32>,
+    memory_mb: Option<u32>,
+    /// QEMU CPU usage, where 100 is one CPU
+    cpu_percent: Option<f64>,
+    rss_bytes: Option<u64>,
+}
+
+impl PsEntry {
+    fn new(row: VmRow, previous: Option<&QemuSample>) -> Self {
+        let vm = row.status.as_ref().and_then(|s| s.vm.as_ref());
+        Self {
+            boot_state: row.status.as_ref().map(|s| s.boot_state().to_owned()),
+            ssh_ready: row.status.as_ref().map(|s| s.ssh_access),
+            vcpus: vm.map(|vm| vm.vcpus),
+            memory_mb: vm.map(|vm| vm.memory_mb),
+            cpu_percent: row
+                .qemu
+                .zip(previous)
+                .and_then(|(current, previous)| current.cpu_percent(previous)),
+            rss_bytes: row.qemu.map(|q| q.rss_bytes),
+            container: row.container,
+        }
+    }
+}
+
+fn to_table(entries: &[PsEntry]) -> Table {
+    let mut table = Table::new();
+    table.load_preset(UTF8_FULL).set_header(vec![
+        "CONTAINER ID",
+        "IMAGE",
+        "CREATED",
+        "STATUS",
+        "BOOT",
+        "SSH",
+        "VCPUS",
+        "MEMORY",
+        "CPU",
+        "RSS",
+        "PORTS",
+        "NAMES",
+    ]);
+
+    let or_dash = |v: Option<String>| v.unwrap_or_else(|| "-".to_owned());
+    for entry in entries {
+        let container = &entry.container;
+        let id = if container.id.len() > 12 {
+            &container.id[..12]
+        } else {
+            &container.id
+        };
+
+        let names = container.names.join(", ");
+        let ports = container
+            .ports
+            .iter()
+            .flatten()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let image = if container.image.len() > 30 {
+            format!("{}...", &container.image[..30])
+        } else {
+            container.image.clone()
+        };
+
+        table.add_row(vec![
+            id.to_string(),
+            image,
+            container.created_at.clone(),
+            container.state.clone(),
+            or_dash(entry.boot_state.clone()),
+            or_dash(
+                entry
+                    .ssh_ready
+                    .map(|r| if r { "yes" } else { "no" }.to_owned()),
+            ),
+            or_dash(entry.vcpus.map(|v| v.to_string())),
+            or_dash(
+                entry
+                    .memory_mb
+                    .map(|mb| HumanBytes(u64::from(mb) * 1024 * 1024).to_string()),
+            ),
+            or_dash(entry.cpu_percent.map(|p| format!("{p:.1}%"))),
+            or_dash(entry.rss_bytes.map(|b| HumanBytes(b).to_string())),
+            ports,
+            names,
+        ]);
+    }
+    table
+}
+
+/// Refresh the list every interval until interrupted.
+fn watch(interval: Duration) -> Result<()> {
+    let mut previous: HashMap<String, QemuSample> = HashMap::new();
+    loop {
+        let rows = collect_rows()?;
+        let samples = rows
+            .iter()
+            .filter_map(|r| Some((r.container.id.clone(), r.qemu?)))
+            .collect();
+        let entries: Vec<_> = rows
+            .into_iter()
+            .map(|row| {
+                let prev = previous.get(&row.container.id);
+                PsEntry::new(row, prev)
+            })
+            .collect();
+        previous = samples;
+
+        // Clear the screen and move to the top left
+        print!("\x1b[H\x1b[2J");
+        println!(
+            "Every {}s: bcvk ephemeral ps    {}",
+            interval.as_secs_f64(),
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+        );
+        println!("{}", to_table(&entries));
+        std::thread::sleep(interval);
+    }
+}
+
+/// Execute the ephemeral ps command
+pub fn run(opts: PsOpts) -> Result<()> {
+    if opts.watch {
+        return watch(utils::parse_duration(&opts.interval)?);
+    }
+
+    let rows = collect_rows()?;
+    // CPU usage needs a second sample
+    let previous: HashMap<_, _> = rows
+        .iter()
+        .filter_map(|r| Some((r.container.id.clone(), r.qemu?)))
+        .collect();
+    if !previous.is_empty() {
+        std::thread::sleep(CPU_SAMPLE_INTERVAL);
+    }
+    let entries: Vec<_> = rows
+        .into_iter()
+        .map(|mut row| {
+            row.qemu = row.qemu.and_then(|q| QemuSample::read(q.pid));
+            let prev = previous.get(&row.container.id);
+            PsEntry::new(row, prev)
+        })
+        .collect();
+
+    if opts.json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        println!("{}", to_table(&entries));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc() {
+        let stat = "4242 (qemu-kvm (x)) S 1 4242 4242 0 -1 4194560 100 0 0 0 1500 250 0 0 20 0 9 0 12345 0 0";
+        assert_eq!(parse_cpu_ticks(stat), Some(1750));
+        assert_eq!(parse_cpu_ticks("4242 (qemu"), None);
+
+        let cgroup = "0::/machine.slice/libpod-0123abcd.scope/container\n";
+        assert_eq!(
+            unified_cgroup_path(cgroup),
+            Some("/machine.slice/libpod-0123abcd.scope/container")
+        );
+        assert_eq!(unified_cgroup_path("1:name=systemd:/init.scope\n"), None);
+    }
+
+    #[test]
+    fn test_cpu_percent() {
+        let previous = QemuSample {
+            pid: 10,
+            cpu_ticks: 1000,
+            rss_bytes: 0,
+            at: Instant::now(),
+        };
+        let ticks = sysconf(libc::_SC_CLK_TCK);
+        let current = QemuSample {
+            cpu_ticks: 1000 + ticks * 3 / 2,
+            at: previous.at + Duration::from_secs(1),
+            ..previous
+        };
+        let percent = current.cpu_percent(&previous).unwrap();
+        assert!((percent - 150.0).abs() < 1.0, "{percent}");
+        // A restarted VM has a new QEMU process
+        let restarted = QemuSample { pid: 11, ..current };
+        assert_eq!(restarted.cpu_percent(&previous), None);
+    }
+}
@@ -0,0 +1,526 @@
+//! Running a directory of test scripts in ephemeral VMs (`bcvk ephemeral test`).
+//!
+//! The tests are shared into the VM read-only and run one after another by a
+//! small runner installed with `--execute-script`. For each test the runner
+//! records stdout, stderr, the exit code and the duration in a writable share,
+//! which is turned into TAP and JUnit XML reports on the host.
+//!
+//! By default all tests run in a single VM; `--isolate` boots a fresh VM per test.
+
+use std::fmt::Write as _;
+use std::os::unix::fs::PermissionsExt;
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::debug;
+
+use crate::run_ephemeral::{
+    run_synchronous, CommonPodmanOptions, CommonVmOpts, ExecuteTimedOut, RunEphemeralOpts,
+    TIMEOUT_EXIT_CODE,
+};
+use crate::utils;
+
+/// Where the test directory is mounted in the guest
+const GUEST_TESTS_DIR: &str = "/run/bcvk-tests";
+/// Where the runner records results in the guest
+const GUEST_RESULTS_DIR: &str = "/run/bcvk-test-results";
+
+/// Extra time allowed for booting and shutting down each VM
+const VM_OVERHEAD: Duration = Duration::from_secs(5 * 60);
+
+/// Runs the tests named in the arguments after the per-test timeout.
+const RUNNER_SCRIPT: &str = r#"#!/bin/sh
+# bcvk test runner: runs each named test, recording its output, exit code
+# and duration under the results directory
+set -u
+timeout=$1
+shift
+cd /run/bcvk-tests
+for name in "$@"; do
+    out=/run/bcvk-test-results/$name
+    mkdir -p "$out"
+    echo "bcvk-test: running $name" >&2
+    if [ -x "$name" ]; then interp=; else interp=sh; fi
+    start=$(date +%s%N)
+    rc=0
+    timeout -k 10 "$timeout" $interp "./$name" >"$out/stdout" 2>"$out/stderr" </dev/null || rc=$?
+    end=$(date +%s%N)
+    echo $(( (end - start) / 1000000 )) >"$out/duration_ms"
+    echo "$rc" >"$out/exit_code"
+done
+"#;
+
+/// Options for running a directory of tests in ephemeral VMs.
+#[derive(clap::Parser, Debug)]
+pub struct TestOpts {
+    /// Container image to boot
+    pub image: String,
+
+    /// Directory of tests; each executable file or `*.sh` script is one test
+    pub testdir: Utf8PathBuf,
+
+    #[clap(flatten)]
+    pub common: CommonVmOpts,
+
+    /// Boot a fresh VM for each test instead of running all tests in one VM
+    #[clap(long)]
+    pub isolate: bool,
+
+    /// Time limit for each test (e.g. 90s, 10m)
+    #[clap(long, value_name = "DURATION", default_value = "10m")]
+    pub test_timeout: String,
+
+    /// Write a JUnit XML report to FILE
+    #[clap(long, value_name = "FILE")]
+    pub junit: Option<Utf8PathBuf>,
+
+    /// Write the TAP report to FILE instead of stdout
+    #[clap(long, value_name = "FILE")]
+    pub tap: Option<Utf8PathBuf>,
+
+    #[clap(
+        long = "label",
+        help = "Add metadata to the container in key=value form"
+    )]
+    pub label: Vec<String>,
+}
+
+/// Outcome of a single test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    Passed,
+    /// The test exited with a non-zero code
+    Failed(i32),
+    /// The test exceeded `--test-timeout`
+    TimedOut,
+    /// The test did not run, e.g. because the VM failed
+    Error(String),
+}
+
+/// Result of a single test
+#[derive(Debug, Clone)]
+pub(crate) struct TestResult {
+    pub(crate) name: String,
+    pub(crate) outcome: Outcome,
+    pub(crate) duration: Duration,
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+}
+
+impl TestResult {
+    fn passed(&self) -> bool {
+        self.outcome == Outcome::Passed
+    }
+
+    fn message(&self, test_timeout: &str) -> String {
+        match &self.outcome {
+            Outcome::Passed => "passed".to_owned(),
+            Outcome::Failed(code) => format!("exited with code {code}"),
+            Outcome::TimedOut => format!("timed out after {test_timeout}"),
+            Outcome::Error(e) => e.clone(),
+        }
+    }
+
+    /// Read the result the runner recorded for `name`, if the test ran.
+    fn read(results_dir: &Utf8Path, name: &str) -> Result<Option<Self>> {
+        let dir = results_dir.join(name);
+        let read = |file: &str| -> Result<Option<String>> {
+            match std::fs::read(dir.join(file)) {
+                Ok(v) => Ok(Some(String::from_utf8_lossy(&v).into_owned())),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e).with_context(|| format!("Reading {dir}/{file}")),
+            }
+        };
+        let Some(exit_code) = read("exit_code")? else {
+            return Ok(None);
+        };
+        let exit_code: i32 = exit_code
+            .trim()
+            .parse()
+            .with_context(|| format!("Parsing exit code of {name}"))?;
+        let duration_ms = read("duration_ms")?
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or_default();
+        let outcome = match exit_code {
+            0 => Outcome::Passed,
+            // timeout -k reports 137 when the test had to be killed
+            TIMEOUT_EXIT_CODE | 137 => Outcome::TimedOut,
+            code => Outcome::Failed(code),
+        };
+        Ok(Some(Self {
+            name: name.to_owned(),
+            outcome,
+            duration: Duration::from_millis(duration_ms),
+            stdout: read("stdout")?.unwrap_or_default(),
+            stderr: read("stderr")?.unwrap_or_default(),
+        }))
+    }
+}
+
+/// Find the tests in `testdir`: non-hidden regular files that are executable
+/// or end in `.sh`, sorted by name.
+pub(crate) fn discover_tests(testdir: &Utf8Path) -> Result<Vec<String>> {
+    let mut tests = Vec::new();
+    for entry in testdir
+        .read_dir_utf8()
+        .with_context(|| format!("Reading {testdir}"))?
+    {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name.starts_with('.') {
+            continue;
+        }
+        let meta = entry.path().metadata()?;
+        if !meta.is_file() {
+            continue;
+        }
+        if meta.permissions().mode() & 0o111 != 0 || name.ends_with(".sh") {
+            tests.push(name.to_owned());
+        }
+    }
+    tests.sort();
+    Ok(tests)
+}
+
+/// Run `tests` in one VM, returning the error if the VM itself failed.
+fn run_in_vm(
+    opts: &TestOpts,
+    testdir: &Utf8Path,
+    runner: &Utf8Path,
+    results_dir: &Utf8Path,
+    test_timeout: Duration,
+    tests: &[String],
+) -> Result<()> {
+    // timeout(1) takes fractional seconds, so e.g. 500ms is not rounded down to
+    // 0, which would disable the limit
+    let timeout_secs = format!("{}s", test_timeout.as_secs_f64());
+    let script = shlex::try_join(
+        [runner.as_str(), timeout_secs.as_str()]
+            .into_iter()
+            .chain(tests.iter().map(String::as_str)),
+    )
+    .map_err(|e| eyre!("Quoting test names: {e}"))?;
+    // Leave room for the per-test kill grace period as well as booting
+    let vm_timeout = u32::try_from(tests.len())
+        .ok()
+        .and_then(|n| {
+            test_timeout
+                .checked_add(Duration::from_secs(10))?
+                .checked_mul(n)?
+                .checked_add(VM_OVERHEAD)
+        })
+        .ok_or_else(|| eyre!("--test-timeout is too large for {} test(s)", tests.len()))?;
+    let run_opts = RunEphemeralOpts {
+        image: opts.image.clone(),
+        common: opts.common.clone(),
+        podman: CommonPodmanOptions {
+            rm: true,
+            label: opts.label.clone(),
+            ..Default::default()
+        },
+        ro_bind_mounts: vec![format!("{testdir}:{GUEST_TESTS_DIR}")],
+        bind_mounts: vec![format!("{results_dir}:{GUEST_RESULTS_DIR}")],
+        execute_script: Some(script),
+        timeout: Some(format!("{}s", vm_timeout.as_secs())),
+        ..Default::default()
+    };
+    debug!("Running {} test(s) in {}", tests.len(), opts.image);
+    run_synchronous(run_opts)
+}
+
+/// Run the tests and write the reports, failing if any test failed.
+pub fn run(opts: TestOpts) -> Result<()> {
+    let test_timeout = utils::parse_duration(&opts.test_timeout)?;
+    if test_timeout.is_zero() {
+        return Err(eyre!("--test-timeout must be greater than zero"));
+    }
+    let testdir = opts
+        .testdir
+        .canonicalize_utf8()
+        .with_context(|| format!("Opening {}", opts.testdir))?;
+    let tests = discover_tests(&testdir)?;
+    if tests.is_empty() {
+        return Err(eyre!(
+            "No tests found in {testdir} (expected executable files or *.sh scripts)"
+        ));
+    }
+    if let Some(name) = tests.iter().find(|t| t.contains(':')) {
+        return Err(eyre!("Test names cannot contain ':': {name}"));
+    }
+
+    let td = tempfile::tempdir()?;
+    let td_path = Utf8Path::from_path(td.path()).ok_or_else(|| eyre!("Non-UTF-8 tempdir"))?;
+    let runner = td_path.join("runner.sh");
+    std::fs::write(&runner, RUNNER_SCRIPT)?;
+    let results_dir = td_path.join("results");
+    std::fs::create_dir(&results_dir)?;
+
+    // Each VM run covers a batch of tests; a VM failure is attributed to the
+    // tests in its batch that did not record a result
+    let batches: Vec<&[String]> = if opts.isolate {
+        tests.chunks(1).collect()
+    } else {
+        vec![&tests]
+    };
+    let mut results = Vec::new();
+    for batch in batches {
+        let vm_error = run_in_vm(&opts, &testdir, &runner, &results_dir, test_timeout, batch)
+            .err()
+            .map(|e| {
+                if e.downcast_ref::<ExecuteTimedOut>().is_some() {
+                    "VM timed out".to_owned()
+                } else {
+                    format!("VM failed: {e}")
+                }
+            });
+        for name in batch {
+            let result = match TestResult::read(&results_dir, name)? {
+                Some(r) => r,
+                None => TestResult {
+                    name: name.clone(),
+                    outcome: Outcome::Error(
+                        vm_error.clone().unwrap_or_else(|| "did not run".to_owned()),
+                    ),
+                    duration: Duration::ZERO,
+                    stdout: String::new(),
+                    stderr: String::new(),
+                },
+            };
+            eprintln!("{}: {}", result.name, result.message(&opts.test_timeout));
+            results.push(result);
+        }
+    }
+
+    let tap = render_tap(&results, &opts.test_timeout);
+    match opts.tap.as_deref() {
+        Some(path) => std::fs::write(path, tap).with_context(|| format!("Writing {path}"))?,
+        None => print!("{tap}"),
+    }
+    if let Some(path) = opts.junit.as_deref() {
+        let junit = render_junit(&opts.image, &results, &opts.test_timeout);
+        std::fs::write(path, junit).with_context(|| format!("Writing {path}"))?;
+    }
+
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    if failed > 0 {
+        return Err(eyre!("{failed} of {} tests failed", results.len()));
+    }
+    Ok(())
+}
+
+/// Render results as TAP version 13, with YAML diagnostics for failures.
+pub(crate) fn render_tap(results: &[TestResult], test_timeout: &str) -> String {
+    let mut out = format!("TAP version 13\n1..{}\n", results.len());
+    for (i, r) in results.iter().enumerate() {
+        let status = if r.passed() { "ok" } else { "not ok" };
+        let _ = writeln!(out, "{status} {} - {}", i + 1, r.name);
+        if r.passed() {
+            continue;
+        }
+        let _ = writeln!(out, "  ---");
+        let _ = writeln!(out, "  message: {:?}", r.message(test_timeout));
+        if let Outcome::Failed(code) = r.outcome {
+            let _ = writeln!(out, "  exit_code: {code}");
+        }
+        let _ = writeln!(out, "  duration_ms: {}", r.duration.as_millis());
+        for (name, text) in [("stdout", &r.stdout), ("stderr", &r.stderr)] {
+            if text.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "  {name}: |");
+            for line in text.lines() {
+                let _ = writeln!(out, "    {line}");
+            }
+        }
+        let _ = writeln!(out, "  ...");
+    }
+    out
+}
+
+/// Render results as a JUnit XML report with a single test suite.
+pub(crate) fn render_junit(suite: &str, results: &[TestResult], test_timeout: &str) -> String {
+    let count = |f: fn(&Outcome) -> bool| results.iter().filter(|r| f(&r.outcome)).count();
+    let failures = count(|o| matches!(o, Outcome::Failed(_) | Outcome::TimedOut));
+    let errors = count(|o| matches!(o, Outcome::Error(_)));
+    let total: Duration = results.iter().map(|r| r.duration).sum();
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+    let _ = writeln!(
+        out,
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{failures}\" errors=\"{errors}\" time=\"{:.3}\">",
+        xml_escape(suite),
+        results.len(),
+        total.as_secs_f64()
+    );
+    for r in results {
+        let _ = writeln!(
+            out,
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">",
+            xml_escape(&r.name),
+            xml_escape(suite),
+            r.duration.as_secs_f64()
+        );
+        let message = xml_escape(&r.message(test_timeout));
+        match r.outcome {
+            Outcome::Passed => {}
+            Outcome::Failed(_) | Outcome::TimedOut => {
+                let _ = writeln!(out, "      <failure message=\"{message}\"/>");
+            }
+            Outcome::Error(_) => {
+                let _ = writeln!(out, "      <error message=\"{message}\"/>");
+            }
+        }
+        for (tag, text) in [("system-out", &r.stdout), ("system-err", &r.stderr)] {
+            if !text.is_empty() {
+                let _ = writeln!(out, "      <{tag}>{}</{tag}>", xml_escape(text));
+            }
+        }
+        let _ = writeln!(out, "    </testcase>");
+    }
+    out.push_str("  </testsuite>\n</testsuites>\n");
+    out
+}
+
+/// Escape text for XML content and attributes, dropping characters XML cannot represent.
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c < ' ' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_results() -> Vec<TestResult> {
+        let result = |name: &str, outcome| TestResult {
+            name: name.to_owned(),
+            outcome,
+            duration: Duration::from_millis(1500),
+            stdout: String::new(),
+            stderr: String::new(),
+        };
+        vec![
+            result("01-boot.sh", Outcome::Passed),
+            TestResult {
+                stdout: "checking <units>\n".to_owned(),
+                stderr: "unit failed\n".to_owned(),
+                ..result("02-units", Outcome::Failed(3))
+            },
+            result("03-slow.sh", Outcome::TimedOut),
+            result("04-never.sh", Outcome::Error("VM timed out".to_owned())),
+        ]
+    }
+
+    #[test]
+    fn test_discover_tests() -> Result<()> {
+        let td = tempfile::tempdir()?;
+        let dir = Utf8Path::from_path(td.path()).unwrap();
+        for (name, mode) in [
+            ("b.sh", 0o644),
+            ("a-exec", 0o755),
+            ("README.md", 0o644),
+            (".hidden.sh", 0o755),
+        ] {
+            std::fs::write(dir.join(name), "")?;
+            std::fs::set_permissions(dir.join(name), std::fs::Permissions::from_mode(mode))?;
+        }
+        std::fs::create_dir(dir.join("lib.sh"))?;
+        assert_eq!(discover_tests(dir)?, ["a-exec", "b.sh"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_result() -> Result<()> {
+        let td = tempfile::tempdir()?;
+        let dir = Utf8Path::from_path(td.path()).unwrap();
+        assert!(TestResult::read(dir, "t.sh")?.is_none());
+        std::fs::create_dir(dir.join("t.sh"))?;
+        std::fs::write(dir.join("t.sh/exit_code"), "124\n")?;
+        std::fs::write(dir.join("t.sh/duration_ms"), "2500\n")?;
+        std::fs::write(dir.join("t.sh/stdout"), "out\n")?;
+        let r = TestResult::read(dir, "t.sh")?.unwrap();
+        assert_eq!(r.outcome, Outcome::TimedOut);
+        assert_eq!(r.duration, Duration::from_millis(2500));
+        assert_eq!(r.stdout, "out\n");
+        assert_eq!(r.stderr, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_tap() {
+        let tap = render_tap(&sample_results(), "10m");
+        similar_asserts::assert_eq!(
+            tap,
+            indoc::indoc! {r#"
+                TAP version 13
+                1..4
+                ok 1 - 01-boot.sh
+                not ok 2 - 02-units
+                  ---
+                  message: "exited with code 3"
+                  exit_code: 3
+                  duration_ms: 1500
+                  stdout: |
+                    checking <units>
+                  stderr: |
+                    unit failed
+                  ...
+                not ok 3 - 03-slow.sh
+                  ---
+                  message: "timed out after 10m"
+                  duration_ms: 1500
+                  ...
+                not ok 4 - 04-never.sh
+                  ---
+                  message: "VM timed out"
+                  duration_ms: 1500
+                  ...
+            "#}
+        );
+    }
+
+    #[test]
+    fn test_render_junit() {
+        let junit = render_junit("quay.io/x/y:latest", &sample_results(), "10m");
+        similar_asserts::assert_eq!(
+            junit,
+            indoc::indoc! {r#"
+                <?xml version="1.0" encoding="UTF-8"?>
+                <testsuites>
+                  <testsuite name="quay.io/x/y:latest" tests="4" failures="2" errors="1" time="6.000">
+                    <testcase name="01-boot.sh" classname="quay.io/x/y:latest" time="1.500">
+                    </testcase>
+                    <testcase name="02-units" classname="quay.io/x/y:latest" time="1.500">
+                      <failure message="exited with code 3"/>
+                      <system-out>checking &lt;units&gt;
+                </system-out>
+                      <system-err>unit failed
+                </system-err>
+                    </testcase>
+                    <testcase name="03-slow.sh" classname="quay.io/x/y:latest" time="1.500">
+                      <failure message="timed out after 10m"/>
+                    </testcase>
+                    <testcase name="04-never.sh" classname="quay.io/x/y:latest" time="1.500">
+                      <error message="VM timed out"/>
+                    </testcase>
+                  </testsuite>
+                </testsuites>
+            "#}
+        );
+        assert_eq!(xml_escape("a\u{1}b'"), "ab&apos;");
+    }
+}
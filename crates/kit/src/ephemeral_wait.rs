@@ -0,0 +1,336 @@
+//! Waiting for a condition in a running ephemeral VM.
+//!
+//! This provides `bcvk ephemeral wait`, for scripts that need to block until
+//! the VM is ready for them without involving SSH. Conditions are evaluated
+//! from the supervisor status stream (`entrypoint monitor-status`), which also
+//! reports boot failures so waiting stops early. Units other than targets are
+//! not reported there and are polled through the vsock exec agent.
+
+use std::io::BufRead;
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::debug;
+
+use crate::supervisor_status::{SupervisorState, SupervisorStatus};
+use crate::{ephemeral_logs, podman, run_ephemeral, run_ephemeral_ssh, utils, vsock_exec};
+
+/// How often to poll the state of a unit
+const UNIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Options for waiting for a condition in a running VM.
+#[derive(clap::Parser, Debug)]
+pub struct WaitOpts {
+    /// Name or ID of the container running the target VM
+    pub container_name: String,
+
+    #[clap(
+        long = "for",
+        value_name = "CONDITION",
+        default_value = "target=multi-user.target",
+        help = "What to wait for: target=TARGET, unit=UNIT (active), ssh or exited"
+    )]
+    pub condition: WaitCondition,
+
+    #[clap(
+        long,
+        value_name = "DURATION",
+        default_value = "5m",
+        help = "Give up after this long and exit with code 124"
+    )]
+    pub timeout: String,
+}
+
+/// A condition to wait for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WaitCondition {
+    /// systemd reached the target
+    Target(String),
+    /// The unit is active
+    Unit(String),
+    /// SSH accepts connections
+    Ssh,
+    /// QEMU exited
+    Exited,
+}
+
+impl FromStr for WaitCondition {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            Some(("target", t)) if !t.is_empty() => Ok(Self::Target(if t.contains('.') {
+                t.to_owned()
+            } else {
+                format!("{t}.target")
+            })),
+            Some(("unit", u)) if !u.is_empty() => Ok(Self::Unit(ephemeral_logs::normalize_unit(u))),
+            None if s == "ssh" => Ok(Self::Ssh),
+            None if s == "exited" => Ok(Self::Exited),
+            _ => Err(eyre!(
+                "Invalid condition {s:?}; expected target=TARGET, unit=UNIT, ssh or exited"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for WaitCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Target(t) => write!(f, "{t}"),
+            Self::Unit(u) => write!(f, "{u} to be active"),
+            Self::Ssh => write!(f, "SSH"),
+            Self::Exited => write!(f, "the VM to exit"),
+        }
+    }
+}
+
+/// Returned when `--timeout` expires.
+#[derive(Debug)]
+pub struct WaitTimedOut {
+    condition: WaitCondition,
+    timeout: Duration,
+}
+
+impl std::fmt::Display for WaitTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Timed out after {}s waiting for {}",
+            self.timeout.as_secs(),
+            self.condition
+        )
+    }
+}
+
+impl std::error::Error for WaitTimedOut {}
+
+/// Updates from `entrypoint monitor-status`; the monitor is killed on drop.
+struct StatusStream {
+    child: Child,
+    rx: Receiver<Result<SupervisorStatus>>,
+}
+
+impl StatusStream {
+    fn spawn(container_name: &str) -> Result<Self> {
+        let mut child = Command::new("podman")
+            .args([
+                "exec",
+                container_name,
+                run_ephemeral::ENTRYPOINT,
+                "monitor-status",
+            ])
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Failed to start status monitor")?;
+        let stdout = child.stdout.take().unwrap();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in std::io::BufReader::new(stdout).lines() {
+                let status = line
+                    .context("Reading monitor output")
+                    .and_then(|l| Ok(serde_json::from_str::<SupervisorStatus>(&l)?));
+                if tx.send(status).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { child, rx })
+    }
+
+    /// The next update, None on timeout, or an error once the stream ended.
+    fn next(&self, timeout: Duration) -> Result<Option<SupervisorStatus>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(status) => status.map(Some),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(eyre!("VM exited")),
+        }
+    }
+}
+
+impl Drop for StatusStream {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Whether the condition holds for this status, or an error if it never will.
+fn check_status(condition: &WaitCondition, status: &SupervisorStatus) -> Result<bool> {
+    if *condition == WaitCondition::Exited {
+        return Ok(!status.running);
+    }
+    if let Some(SupervisorState::Failed(failure)) = &status.state {
+        return Err(eyre!("VM failed: {failure}"));
+    }
+    if !status.running {
+        return Err(eyre!("VM exited while waiting for {condition}"));
+    }
+    Ok(match condition {
+        WaitCondition::Target(t) => {
+            status.reached_targets.contains(t)
+                || status.state == Some(SupervisorState::ReachedTarget(t.clone()))
+        }
+        _ => false,
+    })
+}
+
+/// Query the state of a unit through the exec agent, e.g. `active` or `failed`.
+fn unit_state(container_name: &str, unit: &str, deadline: Instant) -> Result<Option<String>> {
+    let args = ["systemctl", "is-active", unit].map(String::from);
+    let mut child = vsock_exec::exec_command(container_name, &args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| eyre!("Failed to run podman exec: {}", e))?;
+    // The agent is not reachable until the guest has booted far enough
+    while child.try_wait()?.is_none() {
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let output = child.wait_with_output()?;
+    Ok(Some(
+        String::from_utf8_lossy(&output.stdout).trim().to_owned(),
+    ))
+}
+
+/// Wait for the condition, returning an error on failure or [`WaitTimedOut`].
+pub fn wait(container_name: &str, condition: &WaitCondition, timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    let timed_out = || {
+        color_eyre::Report::new(WaitTimedOut {
+            condition: condition.clone(),
+            timeout,
+        })
+    };
+
+    if !podman::container_running(container_name)? {
+        return match condition {
+            WaitCondition::Exited => Ok(()),
+            _ => Err(eyre!("Container {container_name} is not running")),
+        };
+    }
+
+    if *condition == WaitCondition::Ssh {
+        let progress = indicatif::ProgressBar::hidden();
+        return run_ephemeral_ssh::wait_for_ssh_ready(container_name, timeout, progress)
+            .map(|_| ())
+            .map_err(|e| {
+                if Instant::now() >= deadline {
+                    timed_out()
+                } else {
+                    e
+                }
+            });
+    }
+
+    let stream = StatusStream::spawn(container_name)?;
+    // Whether the monitor reported anything, i.e. it is an ephemeral VM
+    let mut started = false;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(timed_out());
+        }
+        let poll = match condition {
+            WaitCondition::Unit(_) => remaining.min(UNIT_POLL_INTERVAL),
+            _ => remaining,
+        };
+        match stream.next(poll) {
+            Ok(Some(status)) => {
+                debug!("Status update: {:?}", status.state);
+                started = true;
+                if check_status(condition, &status)? {
+                    return Ok(());
+                }
+            }
+            Ok(None) => {}
+            // The monitor ends when the VM or the container exits
+            Err(_) if started && *condition == WaitCondition::Exited => return Ok(()),
+            Err(e) => return Err(e.wrap_err(format!("Waiting for {condition}"))),
+        }
+        if let WaitCondition::Unit(unit) = condition {
+            match unit_state(container_name, unit, deadline)?.as_deref() {
+                Some("active") => return Ok(()),
+                Some("failed") => return Err(eyre!("{unit} failed")),
+                state => debug!("{unit}: {state:?}"),
+            }
+        }
+    }
+}
+
+/// Wait for a condition in the VM of the given container.
+pub fn run(opts: WaitOpts) -> Result<()> {
+    let timeout = utils::parse_duration(&opts.timeout)?;
+    match wait(&opts.container_name, &opts.condition, timeout) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            if let Some(t) = e.downcast_ref::<WaitTimedOut>() {
+                eprintln!("error: {t}");
+                std::process::exit(run_ephemeral::TIMEOUT_EXIT_CODE);
+            }
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supervisor_status::{BootFailure, BootFailureKind};
+
+    #[test]
+    fn test_parse_condition() {
+        let parse = |s: &str| s.parse::<WaitCondition>().unwrap();
+        assert_eq!(
+            parse("target=multi-user"),
+            WaitCondition::Target("multi-user.target".into())
+        );
+        assert_eq!(
+            parse("unit=nginx"),
+            WaitCondition::Unit("nginx.service".into())
+        );
+        assert_eq!(
+            parse("unit=podman.socket"),
+            WaitCondition::Unit("podman.socket".into())
+        );
+        assert_eq!(parse("ssh"), WaitCondition::Ssh);
+        assert_eq!(parse("exited"), WaitCondition::Exited);
+        for invalid in ["", "target=", "booted", "ssh=yes"] {
+            assert!(invalid.parse::<WaitCondition>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_check_status() -> Result<()> {
+        let multi_user = WaitCondition::Target("multi-user.target".into());
+        let mut status =
+            SupervisorStatus::new(SupervisorState::ReachedTarget("basic.target".into()));
+        assert!(!check_status(&multi_user, &status)?);
+        status.reached_targets = vec!["multi-user.target".into(), "graphical.target".into()];
+        assert!(check_status(&multi_user, &status)?);
+        assert!(!check_status(&WaitCondition::Exited, &status)?);
+
+        status.running = false;
+        assert!(check_status(&WaitCondition::Exited, &status)?);
+        assert!(check_status(&multi_user, &status).is_err());
+
+        let failed = SupervisorStatus::new(SupervisorState::Failed(BootFailure {
+            kind: BootFailureKind::EmergencyTarget,
+            message: "Guest entered emergency.target".into(),
+            console: Vec::new(),
+        }));
+        let err = check_status(&multi_user, &failed).unwrap_err();
+        assert_eq!(err.to_string(), "VM failed: Guest entered emergency.target");
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+//! Structured result of an `--execute` run.
+//!
+//! The guest reports the state of `bootc-execute.service` by running
+//! `systemctl show` on the `executestatus` virtio-serial port once the service
+//! has finished. This parses that output into an [`ExecuteResult`], which is
+//! used to decide the exit status and can be written as JSON with `--execute-result`.
+
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+
+/// `Result=` used when the run was stopped by `--timeout`
+pub const RESULT_TIMEOUT: &str = "timeout";
+
+/// Outcome of an `--execute` run
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ExecuteResult {
+    /// The systemd `Result=` of the service, e.g. `success`, `exit-code` or `signal`
+    pub result: String,
+    /// Exit code of the last command that ran, if it exited
+    pub exit_code: Option<i32>,
+    /// Signal that killed the last command that ran, e.g. `KILL`
+    pub signal: Option<String>,
+    /// When the first command started
+    pub start_timestamp: Option<String>,
+    /// When the last command exited
+    pub exit_timestamp: Option<String>,
+    /// Time from the start of the first command to the exit of the last one
+    pub duration_usec: Option<u64>,
+    /// Status of each `--execute` command, in order
+    pub commands: Vec<CommandResult>,
+}
+
+/// Status of a single `ExecStart=` command
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct CommandResult {
+    /// The command line
+    pub argv: String,
+    /// False if the command was skipped because an earlier one failed
+    pub ran: bool,
+    /// Guest PID of the command
+    pub pid: Option<u32>,
+    /// Exit code, if the command exited
+    pub exit_code: Option<i32>,
+    /// Signal that killed the command, if any
+    pub signal: Option<String>,
+    pub start_time: Option<String>,
+    pub stop_time: Option<String>,
+}
+
+impl ExecuteResult {
+    /// Parse `systemctl show bootc-execute` output.
+    pub fn parse(status: &str) -> Result<Self> {
+        let mut r = ExecuteResult::default();
+        let mut main_code = None;
+        let mut main_status = None;
+        let mut start_monotonic = None;
+        let mut exit_monotonic = None;
+        for line in status.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let nonempty = || Some(value.to_owned()).filter(|v| !v.is_empty());
+            match key {
+                "Result" => r.result = value.to_owned(),
+                "ExecMainCode" => main_code = Some(value.to_owned()),
+                "ExecMainStatus" => {
+                    main_status = Some(value.parse::<i32>().context("Parsing ExecMainStatus")?)
+                }
+                "ExecMainStartTimestamp" => r.start_timestamp = nonempty(),
+                "ExecMainExitTimestamp" => r.exit_timestamp = nonempty(),
+                "ExecMainStartTimestampMonotonic" => start_monotonic = value.parse::<u64>().ok(),
+                "ExecMainExitTimestampMonotonic" => exit_monotonic = value.parse::<u64>().ok(),
+                "ExecStart" => r.commands.push(CommandResult::parse(value)?),
+                _ => {}
+            }
+        }
+        if r.result.is_empty() {
+            return Err(eyre!("Execute service did not report a result"));
+        }
+
+        r.duration_usec = match (start_monotonic, exit_monotonic) {
+            (Some(start), Some(exit)) if start > 0 && exit >= start => Some(exit - start),
+            _ => None,
+        };
+        if let Some(last) = r.commands.iter().rev().find(|c| c.ran) {
+            r.exit_code = last.exit_code;
+            r.signal = last.signal.clone();
+        } else if main_code.as_deref() == Some("1") {
+            // CLD_EXITED
+            r.exit_code = main_status;
+        }
+        Ok(r)
+    }
+
+    /// The result when `--timeout` stopped the VM before the service finished.
+    pub fn timed_out() -> Self {
+        Self {
+            result: RESULT_TIMEOUT.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        self.result == "success"
+    }
+
+    /// Describe why the run failed.
+    pub fn failure_message(&self) -> String {
+        let failed = self
+            .commands
+            .iter()
+            .rev()
+            .find(|c| c.ran)
+            .map(|c| format!(" ({})", c.argv))
+            .unwrap_or_default();
+        match (self.exit_code, self.signal.as_deref()) {
+            (_, Some(signal)) => format!("Execute command killed by signal {signal}{failed}"),
+            (Some(code), None) if code != 0 => {
+                format!("Execute command failed with exit code: {code}{failed}")
+            }
+            _ => format!("Execute command failed: {}{failed}", self.result),
+        }
+    }
+}
+
+impl CommandResult {
+    /// Parse the value of an `ExecStart=` property, like
+    /// `{ path=/bin/sh ; argv[]=/bin/sh -c true ; ignore_errors=no ; start_time=[...] ; stop_time=[...] ; pid=42 ; code=exited ; status=0 }`
+    fn parse(value: &str) -> Result<Self> {
+        let inner = value
+            .trim()
+            .strip_prefix('{')
+            .and_then(|v| v.strip_suffix('}'))
+            .ok_or_else(|| eyre!("Unexpected ExecStart= format: {value}"))?;
+        let mut r = CommandResult::default();
+        let mut code = None;
+        let mut status = None;
+        for field in inner.split(" ; ") {
+            let Some((key, value)) = field.trim().split_once('=') else {
+                continue;
+            };
+            let timestamp = || {
+                Some(value.trim_start_matches('[').trim_end_matches(']'))
+                    .filter(|v| !v.is_empty() && *v != "n/a")
+                    .map(ToOwned::to_owned)
+            };
+            match key {
+                "argv[]" => r.argv = value.to_owned(),
+                "start_time" => r.start_time = timestamp(),
+                "stop_time" => r.stop_time = timestamp(),
+                "pid" => r.pid = value.parse().ok().filter(|&pid| pid != 0),
+                "code" => code = Some(value),
+                "status" => status = Some(value),
+                _ => {}
+            }
+        }
+        r.ran = r.pid.is_some();
+        match (code, status) {
+            (Some("exited"), Some(status)) if r.ran => {
+                r.exit_code = Some(status.parse().context("Parsing ExecStart status")?)
+            }
+            // e.g. status=9/KILL
+            (Some("killed" | "dumped"), Some(status)) => {
+                r.signal = status.split_once('/').map(|(_, name)| name.to_owned())
+            }
+            _ => {}
+        }
+        Ok(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUCCESS: &str = "Type=oneshot
+Result=success
+ExecMainStartTimestamp=Thu 2025-10-16 10:00:00 UTC
+ExecMainStartTimestampMonotonic=2000000
+ExecMainExitTimestamp=Thu 2025-10-16 10:00:03 UTC
+ExecMainExitTimestampMonotonic=5500000
+ExecMainPID=0
+ExecMainCode=1
+ExecMainStatus=0
+ExecStart={ path=/bin/echo ; argv[]=/bin/echo hello ; ignore_errors=no ; start_time=[Thu 2025-10-16 10:00:00 UTC] ; stop_time=[Thu 2025-10-16 10:00:00 UTC] ; pid=401 ; code=exited ; status=0 }
+ExecStart={ path=/bin/sh ; argv[]=/bin/sh -c sleep 3 ; ignore_errors=no ; start_time=[Thu 2025-10-16 10:00:00 UTC] ; stop_time=[Thu 2025-10-16 10:00:03 UTC] ; pid=402 ; code=exited ; status=0 }
+ExecStartEx={ path=/bin/echo ; argv[]=/bin/echo hello ; flags= ; start_time=[n/a] ; stop_time=[n/a] ; pid=0 ; code=(null) ; status=0/0 }
+";
+
+    const FAILED: &str = "Result=exit-code
+ExecMainCode=1
+ExecMainStatus=3
+ExecStart={ path=/bin/false ; argv[]=/bin/sh -c exit 3 ; ignore_errors=no ; start_time=[Thu 2025-10-16 10:00:00 UTC] ; stop_time=[Thu 2025-10-16 10:00:00 UTC] ; pid=401 ; code=exited ; status=3 }
+ExecStart={ path=/bin/echo ; argv[]=/bin/echo unreached ; ignore_errors=no ; start_time=[n/a] ; stop_time=[n/a] ; pid=0 ; code=(null) ; status=0/0 }
+";
+
+    const KILLED: &str = "Result=signal
+ExecMainCode=2
+ExecMainStatus=9
+ExecStart={ path=/bin/sleep ; argv[]=/bin/sleep 100 ; ignore_errors=no ; start_time=[Thu 2025-10-16 10:00:00 UTC] ; stop_time=[Thu 2025-10-16 10:00:09 UTC] ; pid=401 ; code=killed ; status=9/KILL }
+";
+
+    #[test]
+    fn test_parse_execute_result() -> Result<()> {
+        let r = ExecuteResult::parse(SUCCESS)?;
+        assert!(r.success());
+        assert_eq!(r.exit_code, Some(0));
+        assert_eq!(r.signal, None);
+        assert_eq!(r.duration_usec, Some(3_500_000));
+        assert_eq!(
+            r.start_timestamp.as_deref(),
+            Some("Thu 2025-10-16 10:00:00 UTC")
+        );
+        assert_eq!(r.commands.len(), 2);
+        assert_eq!(r.commands[1].argv, "/bin/sh -c sleep 3");
+        assert_eq!(r.commands[1].pid, Some(402));
+        assert_eq!(
+            r.commands[1].stop_time.as_deref(),
+            Some("Thu 2025-10-16 10:00:03 UTC")
+        );
+
+        let r = ExecuteResult::parse(FAILED)?;
+        assert!(!r.success());
+        assert_eq!(r.exit_code, Some(3));
+        assert!(r.commands[0].ran);
+        assert!(!r.commands[1].ran);
+        assert_eq!(r.commands[1].exit_code, None);
+        assert_eq!(r.commands[1].start_time, None);
+        assert_eq!(
+            r.failure_message(),
+            "Execute command failed with exit code: 3 (/bin/sh -c exit 3)"
+        );
+
+        let r = ExecuteResult::parse(KILLED)?;
+        assert_eq!(r.exit_code, None);
+        assert_eq!(r.signal.as_deref(), Some("KILL"));
+        assert_eq!(
+            r.failure_message(),
+            "Execute command killed by signal KILL (/bin/sleep 100)"
+        );
+
+        // No status at all is an error rather than success
+        assert!(ExecuteResult::parse("").is_err());
+        Ok(())
+    }
+}
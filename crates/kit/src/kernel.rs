@@ -0,0 +1,345 @@
+//! Locating the kernel to boot in a container image.
+//!
+//! Kernels are found in `/usr/lib/modules/<version>/`, either as `vmlinuz` plus
+//! `initramfs.img` or as a Unified Kernel Image (`*.efi`), and as UKIs in
+//! `/boot/EFI/Linux/`. Since we boot UKIs via QEMU direct kernel boot rather
+//! than through UEFI, their kernel and initrd sections are extracted first.
+
+use std::cmp::Ordering;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use tracing::debug;
+
+/// How a kernel is packaged in the image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum KernelImage {
+    /// A kernel with a separate initramfs
+    Split {
+        vmlinuz: Utf8PathBuf,
+        initramfs: Utf8PathBuf,
+    },
+    /// A Unified Kernel Image bundling the kernel and initramfs
+    Uki(Utf8PathBuf),
+}
+
+/// A bootable kernel found in an image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct KernelInfo {
+    /// The kernel version (`uname -r`)
+    pub(crate) version: String,
+    pub(crate) image: KernelImage,
+}
+
+/// Compare kernel versions, treating runs of digits numerically so that
+/// e.g. `6.10.0` sorts after `6.9.0`.
+pub(crate) fn compare_kernel_versions(a: &str, b: &str) -> Ordering {
+    fn runs(s: &str) -> impl Iterator<Item = &str> {
+        let mut rest = s;
+        std::iter::from_fn(move || {
+            let first = rest.chars().next()?;
+            let is_digit = first.is_ascii_digit();
+            let end = rest
+                .find(|c: char| c.is_ascii_digit() != is_digit)
+                .unwrap_or(rest.len());
+            let (run, tail) = rest.split_at(end);
+            rest = tail;
+            Some(run)
+        })
+    }
+
+    let mut a_runs = runs(a);
+    let mut b_runs = runs(b);
+    loop {
+        match (a_runs.next(), b_runs.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => {
+                let both_numeric = x.starts_with(|c: char| c.is_ascii_digit())
+                    && y.starts_with(|c: char| c.is_ascii_digit());
+                let ord = if both_numeric {
+                    let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                    x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+                } else {
+                    x.cmp(y)
+                };
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+        }
+    }
+}
+
+/// List `*.efi` files in a directory, sorted by name.
+fn list_efi_files(dir: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let mut r = Vec::new();
+    for entry in dir.read_dir_utf8()? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && entry.file_name().ends_with(".efi") {
+            r.push(entry.into_path());
+        }
+    }
+    r.sort();
+    Ok(r)
+}
+
+/// Find all bootable kernels in the image rooted at `root`, newest first.
+pub(crate) fn find_kernels(root: &Utf8Path) -> Result<Vec<KernelInfo>> {
+    let mut kernels = Vec::new();
+
+    let modules_dir = root.join("usr/lib/modules");
+    if modules_dir.try_exists()? {
+        for entry in modules_dir.read_dir_utf8()? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let kernel_dir = entry.path();
+            let vmlinuz = kernel_dir.join("vmlinuz");
+            let initramfs = kernel_dir.join("initramfs.img");
+            let image = if vmlinuz.exists() && initramfs.exists() {
+                KernelImage::Split { vmlinuz, initramfs }
+            } else if let Some(uki) = list_efi_files(kernel_dir)?.into_iter().next() {
+                KernelImage::Uki(uki)
+            } else {
+                debug!("No kernel found in {kernel_dir}");
+                continue;
+            };
+            kernels.push(KernelInfo {
+                version: entry.file_name().to_owned(),
+                image,
+            });
+        }
+    }
+
+    let esp_dir = root.join("boot/EFI/Linux");
+    if esp_dir.try_exists()? {
+        for uki in list_efi_files(&esp_dir)? {
+            let version = match uki_uname(&uki)? {
+                Some(v) => v,
+                None => uki.file_stem().unwrap_or_default().to_owned(),
+            };
+            if kernels.iter().any(|k| k.version == version) {
+                continue;
+            }
+            kernels.push(KernelInfo {
+                version,
+                image: KernelImage::Uki(uki),
+            });
+        }
+    }
+
+    kernels.sort_by(|a, b| compare_kernel_versions(&b.version, &a.version));
+    Ok(kernels)
+}
+
+/// Find the kernel to boot: the requested version, or the newest one.
+pub(crate) fn find_kernel(root: &Utf8Path, version: Option<&str>) -> Result<KernelInfo> {
+    let kernels = find_kernels(root)?;
+    let available = || {
+        kernels
+            .iter()
+            .map(|k| k.version.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    match version {
+        Some(version) => kernels
+            .iter()
+            .find(|k| k.version == version)
+            .cloned()
+            .ok_or_else(|| {
+                eyre!(
+                    "Kernel version {version} not found in image (available: {})",
+                    available()
+                )
+            }),
+        None => kernels.first().cloned().ok_or_else(|| {
+            eyre!("No kernel found in {root}/usr/lib/modules or {root}/boot/EFI/Linux")
+        }),
+    }
+}
+
+/// Find a section in a PE binary (such as a UKI) by name.
+fn pe_section<'a>(data: &'a [u8], name: &str) -> Result<Option<&'a [u8]>> {
+    let truncated = || eyre!("Truncated PE binary");
+    let u16_at = |off: usize| -> Result<usize> {
+        let b = data.get(off..off + 2).ok_or_else(truncated)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]) as usize)
+    };
+    let u32_at = |off: usize| -> Result<usize> {
+        let b = data.get(off..off + 4).ok_or_else(truncated)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+
+    let pe_offset = u32_at(0x3c)?;
+    if data.get(pe_offset..pe_offset + 4) != Some(b"PE\0\0") {
+        return Err(eyre!("Not a PE binary"));
+    }
+    // COFF file header follows the signature, then the optional header
+    let coff = pe_offset + 4;
+    let nsections = u16_at(coff + 2)?;
+    let section_table = coff + 20 + u16_at(coff + 16)?;
+    for i in 0..nsections {
+        let header = section_table + i * 40;
+        let raw_name = data.get(header..header + 8).ok_or_else(truncated)?;
+        let section_name = raw_name.split(|&b| b == 0).next().unwrap_or_default();
+        if section_name != name.as_bytes() {
+            continue;
+        }
+        let virtual_size = u32_at(header + 8)?;
+        let raw_size = u32_at(header + 16)?;
+        let offset = u32_at(header + 20)?;
+        // The raw data is padded to the file alignment
+        let size = if virtual_size > 0 && virtual_size <= raw_size {
+            virtual_size
+        } else {
+            raw_size
+        };
+        return data
+            .get(offset..offset + size)
+            .map(Some)
+            .ok_or_else(truncated);
+    }
+    Ok(None)
+}
+
+/// Read the kernel version from the `.uname` section of a UKI, if present.
+fn uki_uname(uki: &Utf8Path) -> Result<Option<String>> {
+    let data = std::fs::read(uki).with_context(|| format!("Reading {uki}"))?;
+    let uname = pe_section(&data, ".uname").with_context(|| format!("Parsing {uki}"))?;
+    Ok(uname.map(|v| {
+        String::from_utf8_lossy(v)
+            .trim_end_matches('\0')
+            .trim()
+            .to_owned()
+    }))
+}
+
+/// Extract the kernel and initrd from a UKI for direct kernel boot.
+pub(crate) fn extract_uki(
+    uki: &Utf8Path,
+    kernel_dest: &Utf8Path,
+    initramfs_dest: &Utf8Path,
+) -> Result<()> {
+    let data = std::fs::read(uki).with_context(|| format!("Reading {uki}"))?;
+    for (section, dest) in [(".linux", kernel_dest), (".initrd", initramfs_dest)] {
+        let contents = pe_section(&data, section)
+            .with_context(|| format!("Parsing {uki}"))?
+            .ok_or_else(|| eyre!("UKI {uki} has no {section} section"))?;
+        std::fs::write(dest, contents).with_context(|| format!("Writing {dest}"))?;
+    }
+    debug!("Extracted kernel and initrd from {uki}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a minimal PE binary with the given sections.
+    fn make_pe(sections: &[(&str, &[u8])]) -> Vec<u8> {
+        let pe_offset = 0x40;
+        let section_table = pe_offset + 4 + 20;
+        let mut data_offset = section_table + sections.len() * 40;
+        let mut pe = vec![0u8; data_offset];
+        pe[0..2].copy_from_slice(b"MZ");
+        pe[0x3c..0x40].copy_from_slice(&(pe_offset as u32).to_le_bytes());
+        pe[pe_offset..pe_offset + 4].copy_from_slice(b"PE\0\0");
+        let coff = pe_offset + 4;
+        pe[coff + 2..coff + 4].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+        for (i, (name, contents)) in sections.iter().enumerate() {
+            let header = section_table + i * 40;
+            pe[header..header + name.len()].copy_from_slice(name.as_bytes());
+            // Pad the raw data like a linker would
+            let raw_size = contents.len().next_multiple_of(16);
+            pe[header + 8..header + 12].copy_from_slice(&(contents.len() as u32).to_le_bytes());
+            pe[header + 16..header + 20].copy_from_slice(&(raw_size as u32).to_le_bytes());
+            pe[header + 20..header + 24].copy_from_slice(&(data_offset as u32).to_le_bytes());
+            data_offset += raw_size;
+        }
+        for (_, contents) in sections {
+            pe.extend_from_slice(contents);
+            pe.resize(pe.len().next_multiple_of(16), 0);
+        }
+        pe
+    }
+
+    #[test]
+    fn test_compare_kernel_versions() {
+        let mut versions = vec![
+            "6.9.0-1.fc42.x86_64",
+            "6.10.0-1.fc42.x86_64",
+            "6.10.0-10.fc42.x86_64",
+            "6.10.0-2.fc42.x86_64",
+        ];
+        versions.sort_by(|a, b| compare_kernel_versions(b, a));
+        assert_eq!(
+            versions,
+            [
+                "6.10.0-10.fc42.x86_64",
+                "6.10.0-2.fc42.x86_64",
+                "6.10.0-1.fc42.x86_64",
+                "6.9.0-1.fc42.x86_64",
+            ]
+        );
+        assert_eq!(compare_kernel_versions("6.1", "6.01"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_pe_section() -> Result<()> {
+        let pe = make_pe(&[
+            (".uname", b"6.12.0-1.el10.x86_64"),
+            (".linux", b"kernel"),
+            (".initrd", b"initrd"),
+        ]);
+        assert_eq!(
+            pe_section(&pe, ".uname")?,
+            Some(&b"6.12.0-1.el10.x86_64"[..])
+        );
+        assert_eq!(pe_section(&pe, ".linux")?, Some(&b"kernel"[..]));
+        assert_eq!(pe_section(&pe, ".initrd")?, Some(&b"initrd"[..]));
+        assert_eq!(pe_section(&pe, ".cmdline")?, None);
+        assert!(pe_section(b"not a PE binary", ".linux").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_kernels() -> Result<()> {
+        let td = tempfile::tempdir()?;
+        let root = Utf8Path::from_path(td.path()).unwrap();
+        for version in ["6.9.0", "6.10.0"] {
+            let dir = root.join(format!("usr/lib/modules/{version}"));
+            std::fs::create_dir_all(&dir)?;
+            std::fs::write(dir.join("vmlinuz"), "kernel")?;
+            std::fs::write(dir.join("initramfs.img"), "initrd")?;
+        }
+        let uki_dir = root.join("usr/lib/modules/6.11.0");
+        std::fs::create_dir_all(&uki_dir)?;
+        std::fs::write(uki_dir.join("vmlinuz.efi"), make_pe(&[(".linux", b"k")]))?;
+        let esp = root.join("boot/EFI/Linux");
+        std::fs::create_dir_all(&esp)?;
+        std::fs::write(
+            esp.join("fedora.efi"),
+            make_pe(&[(".uname", b"6.12.0"), (".linux", b"k")]),
+        )?;
+        // Modules directory without a kernel is ignored
+        std::fs::create_dir_all(root.join("usr/lib/modules/extra"))?;
+
+        let kernels = find_kernels(root)?;
+        let versions = kernels
+            .iter()
+            .map(|k| k.version.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(versions, ["6.12.0", "6.11.0", "6.10.0", "6.9.0"]);
+        assert_eq!(kernels[0].image, KernelImage::Uki(esp.join("fedora.efi")));
+
+        let k = find_kernel(root, Some("6.9.0"))?;
+        assert!(matches!(k.image, KernelImage::Split { .. }));
+        assert!(find_kernel(root, Some("5.0")).is_err());
+        Ok(())
+    }
+}
@@ -4,14 +4,14 @@
 //! bootc volumes in storage pools, with automatic resource configuration and
 //! metadata-driven setup.
 
-use crate::common_opts::{MemoryOpts, DEFAULT_MEMORY_USER_STR};
+use crate::common_opts::{CredentialOpts, MemoryOpts, DEFAULT_MEMORY_USER_STR};
 use crate::images;
 use crate::install_options::InstallOptions;
 use crate::libvirt::domain::DomainBuilder;
 use crate::libvirt::upload::LibvirtUploadOpts;
 use crate::run_ephemeral::default_vcpus;
 use crate::ssh::generate_ssh_keypair;
-use crate::sshcred::smbios_cred_for_root_ssh;
+use crate::sshcred::{smbios_creds_for_ssh, SshUser};
 use base64::Engine;
 use camino::{Utf8Path, Utf8PathBuf};
 use clap::Parser;
@@ -103,6 +103,13 @@ pub struct LibvirtCreateOpts {
     /// SSH port for port forwarding (default: auto-assign)
     #[clap(long)]
     pub ssh_port: Option<u16>,
+
+    /// Create a non-root user NAME[:GROUPS] with passwordless sudo (and the SSH key), used by default for SSH
+    #[clap(long, value_name = "NAME[:GROUPS]")]
+    pub user: Option<String>,
+
+    #[clap(flatten)]
+    pub credentials: CredentialOpts,
 }
 
 /// Metadata extracted from bootc volume
@@ -444,14 +451,16 @@ impl LibvirtCreateOpts {
             &self.network
         };
 
-        if let Some(ref ssh) = ssh_config {
-            // Generate SMBIOS credential for SSH key injection
-            let smbios_cred = smbios_cred_for_root_ssh(&ssh.public_key)?;
-            debug!("Injecting SSH key via SMBIOS credential");
-
+        // Generate SMBIOS credentials for SSH key injection and user creation
+        let ssh_user = self.user.as_deref().map(SshUser::parse).transpose()?;
+        let pubkey = ssh_config.as_ref().map(|ssh| ssh.public_key.as_str());
+        for smbios_cred in smbios_creds_for_ssh(pubkey, ssh_user.as_ref())? {
+            debug!("Injecting SSH key or user via SMBIOS credential");
             qemu_args.push("-smbios".to_string());
             qemu_args.push(format!("type=11,value={}", smbios_cred));
+        }
 
+        if let Some(ref ssh) = ssh_config {
             // Add SSH port forwarding - this replaces the default network
             // Use explicit PCI address to avoid conflicts with libvirt's device management
             qemu_args.push("-netdev".to_string());
@@ -460,6 +469,11 @@ impl LibvirtCreateOpts {
             qemu_args.push("virtio-net-pci,netdev=ssh0,addr=0x3".to_string());
         }
 
+        for credential in self.credentials.smbios_credentials()? {
+            qemu_args.push("-smbios".to_string());
+            qemu_args.push(format!("type=11,value={}", credential));
+        }
+
         // Build domain configuration
         let mut domain_builder = DomainBuilder::new()
             .with_name(&domain_name)
@@ -500,6 +514,9 @@ impl LibvirtCreateOpts {
                 .with_metadata("ssh-port", &ssh.port.to_string())
                 .with_metadata("ssh-generated", &ssh.is_generated.to_string());
         }
+        if let Some(ref user) = ssh_user {
+            domain_builder = domain_builder.with_metadata("ssh-user", &user.name);
+        }
 
         let domain_xml = domain_builder.build_xml()?;
 
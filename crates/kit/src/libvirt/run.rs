@@ -10,7 +10,7 @@ use std::collections::hash_map::DefaultHasher;
 use std::fs;
 use std::hash::{Hash, Hasher};
 
-use crate::common_opts::MemoryOpts;
+use crate::common_opts::{CredentialOpts, MemoryOpts};
 use crate::domain_list::DomainLister;
 use crate::utils::parse_memory_to_mb;
 
@@ -58,6 +58,13 @@ pub struct LibvirtRunOpts {
     /// Automatically SSH into the VM after creation
     #[clap(long)]
     pub ssh: bool,
+
+    /// Create a non-root user NAME[:GROUPS] with the SSH key and passwordless sudo, used by default for SSH
+    #[clap(long, value_name = "NAME[:GROUPS]")]
+    pub user: Option<String>,
+
+    #[clap(flatten)]
+    pub credentials: CredentialOpts,
 }
 
 /// Execute the libvirt run command
@@ -118,9 +125,14 @@ pub fn run_vm_impl(opts: LibvirtRunOpts) -> Result<()> {
             debug: false,
             virtio_serial_out: vec![],
             execute: vec![],
-            ssh_keygen: true, // Enable SSH key generation
+            ssh_keygen: true,                // Enable SSH key generation
+            user: None,                      // --user applies to the domain, not the installer
+            credentials: Default::default(), // --credential applies to the domain, not the installer
         },
         label: vec![],
+        timeout: None,
+        artifacts_dir: None,
+        artifacts: Default::default(),
     };
 
     // Run the disk creation
@@ -148,7 +160,7 @@ pub fn run_vm_impl(opts: LibvirtRunOpts) -> Result<()> {
         let ssh_opts = crate::libvirt::ssh::LibvirtSshOpts {
             domain_name: vm_name,
             connect: None,
-            user: "root".to_string(),
+            user: None,
             command: vec![],
             strict_host_keys: false,
             timeout: 30,
@@ -331,7 +343,7 @@ fn create_libvirt_domain_from_disk(
 ) -> Result<()> {
     use crate::libvirt::domain::DomainBuilder;
     use crate::ssh::generate_ssh_keypair;
-    use crate::sshcred::smbios_cred_for_root_ssh;
+    use crate::sshcred::{smbios_creds_for_ssh, SshUser};
     use std::process::Command;
     use tracing::debug;
 
@@ -370,11 +382,25 @@ fn create_libvirt_domain_from_disk(
     );
     debug!("Generated ephemeral SSH keypair (will be stored in domain XML)");
 
-    // Generate SMBIOS credential for SSH key injection
-    let smbios_cred = smbios_cred_for_root_ssh(&public_key_content)?;
+    let ssh_user = opts.user.as_deref().map(SshUser::parse).transpose()?;
 
     let memory = parse_memory_to_mb(&opts.memory.memory)?;
 
+    let mut qemu_args = vec![
+        "-netdev".to_string(),
+        format!("user,id=ssh0,hostfwd=tcp::{}-:22", ssh_port),
+        "-device".to_string(),
+        "virtio-net-pci,netdev=ssh0,addr=0x3".to_string(),
+    ];
+    // Generate SMBIOS credentials for SSH key injection
+    for credential in smbios_creds_for_ssh(Some(&public_key_content), ssh_user.as_ref())?
+        .into_iter()
+        .chain(opts.credentials.smbios_credentials()?)
+    {
+        qemu_args.push("-smbios".to_string());
+        qemu_args.push(format!("type=11,value={}", credential));
+    }
+
     // Build domain XML using the existing DomainBuilder with bootc metadata and SSH keys
     let domain_xml = DomainBuilder::new()
         .with_name(domain_name)
@@ -391,14 +417,11 @@ fn create_libvirt_domain_from_disk(
         .with_metadata("bootc:ssh-generated", "true")
         .with_metadata("bootc:ssh-private-key-base64", &private_key_base64)
         .with_metadata("bootc:ssh-port", &ssh_port.to_string())
-        .with_qemu_args(vec![
-            "-smbios".to_string(),
-            format!("type=11,value={}", smbios_cred),
-            "-netdev".to_string(),
-            format!("user,id=ssh0,hostfwd=tcp::{}-:22", ssh_port),
-            "-device".to_string(),
-            "virtio-net-pci,netdev=ssh0,addr=0x3".to_string(),
-        ])
+        .with_metadata(
+            "bootc:ssh-user",
+            ssh_user.as_ref().map_or("root", |u| u.name.as_str()),
+        )
+        .with_qemu_args(qemu_args)
         .build_xml()
         .with_context(|| "Failed to build domain XML")?;
 
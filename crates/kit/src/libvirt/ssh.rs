@@ -25,9 +25,9 @@ pub struct LibvirtSshOpts {
     #[clap(short = 'c', long = "connect")]
     pub connect: Option<String>,
 
-    /// SSH username to use for connection (defaults to 'root')
-    #[clap(long, default_value = "root")]
-    pub user: String,
+    /// SSH username to use for connection (defaults to the domain's --user, or 'root')
+    #[clap(long)]
+    pub user: Option<String>,
 
     /// Command to execute on remote host
     pub command: Vec<String>,
@@ -47,6 +47,8 @@ struct DomainSshConfig {
     private_key_content: String,
     ssh_port: u16,
     is_generated: bool,
+    /// User configured at creation time via --user
+    ssh_user: Option<String>,
 }
 
 impl LibvirtSshOpts {
@@ -185,10 +187,13 @@ impl LibvirtSshOpts {
             .unwrap_or_else(|| "false".to_string())
             == "true";
 
+        let ssh_user = extract_xml_metadata(&xml, "ssh-user");
+
         Ok(DomainSshConfig {
             private_key_content: private_key,
             ssh_port,
             is_generated,
+            ssh_user,
         })
     }
 
@@ -232,9 +237,14 @@ impl LibvirtSshOpts {
 
     /// Execute SSH connection to domain
     fn connect_ssh(&self, ssh_config: &DomainSshConfig) -> Result<()> {
+        let user = self
+            .user
+            .as_deref()
+            .or(ssh_config.ssh_user.as_deref())
+            .unwrap_or("root");
         debug!(
             "Connecting to domain '{}' via SSH on port {} (user: {})",
-            self.domain_name, ssh_config.ssh_port, self.user
+            self.domain_name, ssh_config.ssh_port, user
         );
 
         if ssh_config.is_generated {
@@ -271,7 +281,7 @@ impl LibvirtSshOpts {
         }
 
         // Target host
-        ssh_cmd.arg(format!("{}@127.0.0.1", self.user));
+        ssh_cmd.arg(format!("{}@127.0.0.1", user));
 
         // Add command if specified
         if !self.command.is_empty() {
@@ -42,7 +42,7 @@ pub fn start_vm_impl(opts: LibvirtStartOpts) -> Result<()> {
             let ssh_opts = crate::libvirt::ssh::LibvirtSshOpts {
                 domain_name: opts.name,
                 connect: None,
-                user: "root".to_string(),
+                user: None,
                 command: vec![],
                 strict_host_keys: false,
                 timeout: 30,
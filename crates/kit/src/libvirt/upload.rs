@@ -223,6 +223,9 @@ pub fn run(opts: LibvirtUploadOpts) -> Result<()> {
         format: crate::to_disk::Format::Raw, // Default to raw format
         disk_size: Some(disk_size.to_string()),
         label: Default::default(),
+        timeout: None,
+        artifacts_dir: None,
+        artifacts: Default::default(),
         common: crate::run_ephemeral::CommonVmOpts {
             memory: opts.memory.clone(),
             vcpus: opts.vcpus,
@@ -233,6 +236,8 @@ pub fn run(opts: LibvirtUploadOpts) -> Result<()> {
             virtio_serial_out: vec![],
             execute: Default::default(),
             ssh_keygen: false,
+            user: None,
+            credentials: Default::default(),
         },
     };
 
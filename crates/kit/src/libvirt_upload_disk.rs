@@ -261,6 +261,9 @@ pub fn run(opts: LibvirtUploadDiskOpts) -> Result<()> {
         disk_size: Some(disk_size.to_string()),
         format: crate::to_disk::Format::Raw, // Default to raw format
         label: Default::default(),
+        timeout: None,
+        artifacts_dir: None,
+        artifacts: Default::default(),
         common: crate::run_ephemeral::CommonVmOpts {
             memory: opts.memory.clone(),
             vcpus: opts.vcpus,
@@ -271,6 +274,8 @@ pub fn run(opts: LibvirtUploadDiskOpts) -> Result<()> {
             virtio_serial_out: vec![],
             execute: Default::default(),
             ssh_keygen: false,
+            user: None,
+            credentials: Default::default(),
         },
     };
 
@@ -6,16 +6,27 @@ use color_eyre::{eyre::Context as _, Report, Result};
 
 mod arch;
 mod boot_progress;
+mod boot_report;
 mod cli_json;
 mod common_opts;
+mod console;
 mod container_entrypoint;
 pub(crate) mod containerenv;
 mod domain_list;
 mod envdetect;
 mod ephemeral;
+mod ephemeral_cp;
+mod ephemeral_inspect;
+mod ephemeral_lifecycle;
+mod ephemeral_logs;
+mod ephemeral_ps;
+mod ephemeral_test;
+mod ephemeral_wait;
+mod execute_result;
 mod hostexec;
 mod images;
 mod install_options;
+mod kernel;
 mod libvirt;
 mod libvirt_upload_disk;
 #[allow(dead_code)]
@@ -24,6 +35,7 @@ mod podman;
 mod qemu;
 mod run_ephemeral;
 mod run_ephemeral_ssh;
+mod selinux;
 mod ssh;
 #[allow(dead_code)]
 mod sshcred;
@@ -32,6 +44,7 @@ mod supervisor_status;
 pub(crate) mod systemd;
 mod to_disk;
 mod utils;
+mod vsock_exec;
 
 pub const CONTAINER_STATEDIR: &str = "/var/lib/bcvk";
 
@@ -104,7 +117,7 @@ enum Commands {
 
     /// Manage ephemeral VMs for bootc containers
     #[clap(subcommand)]
-    Ephemeral(ephemeral::EphemeralCommands),
+    Ephemeral(Box<ephemeral::EphemeralCommands>),
 
     /// Install bootc images to persistent disk images
     #[clap(name = "to-disk")]
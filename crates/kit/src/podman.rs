@@ -1,3 +1,5 @@
+use std::process::Command;
+
 use bootc_utils::CommandRunExt;
 use color_eyre::{eyre::eyre, Result};
 use serde::Deserialize;
@@ -49,3 +51,33 @@ pub fn get_image_size(image: &str) -> Result<u64> {
 
     Ok(inspect_result[0].size)
 }
+
+/// Whether the container is running, or an error if there is no such container.
+pub(crate) fn container_running(container_name: &str) -> Result<bool> {
+    let exists = Command::new("podman")
+        .args(["container", "exists", container_name])
+        .status()
+        .map_err(|e| eyre!("Failed to run podman: {}", e))?;
+    match exists.code() {
+        Some(0) => {}
+        Some(1) => return Err(eyre!("No such container: {container_name}")),
+        _ => return Err(eyre!("Failed to look up container {container_name}")),
+    }
+    let output = Command::new("podman")
+        .args([
+            "container",
+            "inspect",
+            "--format",
+            "{{.State.Running}}",
+            container_name,
+        ])
+        .output()
+        .map_err(|e| eyre!("Failed to run podman inspect: {}", e))?;
+    if !output.status.success() {
+        return Err(eyre!(
+            "podman inspect failed for {container_name}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim() == "true")
+}
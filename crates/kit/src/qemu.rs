@@ -4,12 +4,13 @@
 //! automatic process cleanup, and SMBIOS credential injection.
 
 use std::fs::{File, OpenOptions};
-use std::io::ErrorKind;
+use std::io::{BufRead, BufReader, ErrorKind, Write as _};
 use std::os::fd::{AsRawFd as _, OwnedFd};
+use std::os::unix::net::UnixStream;
 use std::os::unix::process::CommandExt as _;
 use std::process::{Child, Command, Stdio};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use cap_std_ext::cmdext::CapStdExtCommandExt;
 use color_eyre::eyre::{eyre, Context};
@@ -22,6 +23,9 @@ use vsock::VsockAddr;
 /// The device for vsock allocation
 pub const VHOST_VSOCK: &str = "/dev/vhost-vsock";
 
+/// Where ephemeral VMs listen for QMP connections inside the container
+pub const QMP_SOCKET_PATH: &str = "/run/qemu-qmp.sock";
+
 /// VirtIO-FS mount point configuration.
 #[derive(Debug, Clone)]
 pub struct VirtiofsMount {
@@ -31,6 +35,9 @@ pub struct VirtiofsMount {
     pub tag: String,
 }
 
+/// Port forwarded to the guest's SSH port 22 by default
+pub(crate) const SSH_FORWARD_PORT: u16 = 2222;
+
 /// VirtIO-Serial device for guest-to-host communication.
 /// Appears as /dev/virtio-ports/{name} in guest.
 #[derive(Debug)]
@@ -150,6 +157,10 @@ pub struct QemuConfig {
     pub resource_limits: ResourceLimits,
     /// Deprecated: use display_mode
     pub enable_console: bool,
+    /// Connect the serial console to this unix socket when it is not shown on the terminal
+    pub serial_socket: Option<String>,
+    /// Listen for QMP connections on this unix socket
+    pub qmp_socket: Option<String>,
     /// UEFI firmware path (auto-detected if None)
     pub uefi_firmware_path: Option<String>,
     /// UEFI variables file
@@ -238,6 +249,18 @@ impl QemuConfig {
         self
     }
 
+    /// Connect the serial console to a listening unix socket (ignored in console mode)
+    pub fn set_serial_socket(&mut self, path: impl Into<String>) -> &mut Self {
+        self.serial_socket = Some(path.into());
+        self
+    }
+
+    /// Listen for QMP connections on a unix socket
+    pub fn set_qmp_socket(&mut self, path: impl Into<String>) -> &mut Self {
+        self.qmp_socket = Some(path.into());
+        self
+    }
+
     /// Validate configuration before VM creation
     pub fn validate(&self) -> Result<()> {
         // Memory validation
@@ -361,14 +384,22 @@ impl QemuConfig {
         self
     }
 
+    /// Forward a host port to a guest port via user-mode networking
+    pub fn add_port_forward(
+        &mut self,
+        protocol: &str,
+        host_port: u16,
+        guest_port: u16,
+    ) -> &mut Self {
+        let NetworkMode::User { hostfwd } = &mut self.network_mode;
+        hostfwd.push(format!("{protocol}::{host_port}-:{guest_port}"));
+        self
+    }
+
     /// Enable SSH access by configuring port forwarding
     pub fn enable_ssh_access(&mut self, host_port: Option<u16>) -> &mut Self {
-        let port = host_port.unwrap_or(2222); // Default to port 2222 on host
-        let hostfwd = format!("tcp::{}-:22", port); // Forward host port to guest port 22
-        self.network_mode = NetworkMode::User {
-            hostfwd: vec![hostfwd],
-        };
-        self
+        let port = host_port.unwrap_or(SSH_FORWARD_PORT);
+        self.add_port_forward("tcp", port, 22) // Forward host port to guest port 22
     }
 }
 
@@ -638,12 +669,24 @@ fn spawn(
     match &config.display_mode {
         DisplayMode::None => {
             cmd.args(["-nographic"]);
+            if let Some(path) = &config.serial_socket {
+                cmd.args([
+                    "-chardev",
+                    &format!("socket,id=serial0,path={path}"),
+                    "-serial",
+                    "chardev:serial0",
+                ]);
+            }
         }
         DisplayMode::Console => {
             cmd.args(["-serial", "stdio", "-display", "none"]);
         }
     }
 
+    if let Some(path) = &config.qmp_socket {
+        cmd.args(["-qmp", &format!("unix:{path},server=on,wait=off")]);
+    }
+
     // Apply resource limits
     if let Some(affinity) = &config.resource_limits.cpu_affinity {
         // Note: CPU affinity is typically set via taskset or systemd, not QEMU args
@@ -707,6 +750,10 @@ struct VsockCopier {
 pub struct RunningQemu {
     pub qemu_process: Child,
     pub virtiofsd_processes: Vec<tokio::process::Child>,
+    /// Guest vsock CID, if vsock is enabled
+    pub vsock_cid: Option<u32>,
+    /// When all virtiofsd sockets were ready, if there are any
+    pub virtiofsd_ready: Option<SystemTime>,
     sd_notification: Option<VsockCopier>,
 }
 
@@ -835,12 +882,16 @@ impl RunningQemu {
             wait_for_virtiofsd_socket(&virtiofs_config.socket_path, Duration::from_secs(10))
                 .await?;
         }
+        let virtiofsd_ready = (!virtiofsd_processes.is_empty()).then(SystemTime::now);
         // Spawn QEMU process with additional VSOCK credential if needed
+        let vsock_cid = vsockdata.as_ref().map(|(_, cid)| *cid);
         let qemu_process = spawn(&config, &creds, vsockdata)?;
 
         Ok(Self {
             qemu_process,
             virtiofsd_processes,
+            vsock_cid,
+            virtiofsd_ready,
             sd_notification,
         })
     }
@@ -851,9 +902,108 @@ impl RunningQemu {
     }
 
     /// Wait for QEMU process to exit
+    ///
+    /// This polls rather than blocking so that futures joined with it (such as
+    /// the `--execute` output copiers) keep making progress.
     pub async fn wait(&mut self) -> Result<std::process::ExitStatus> {
-        let r = self.qemu_process.wait()?;
-        Ok(r)
+        loop {
+            if let Some(r) = self.qemu_process.try_wait()? {
+                return Ok(r);
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+}
+
+/// A minimal QMP client for controlling a running QEMU.
+pub struct QmpClient {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl QmpClient {
+    /// Connect to a QMP socket and negotiate capabilities.
+    pub fn connect(path: &str) -> Result<Self> {
+        let stream = UnixStream::connect(path)
+            .map_err(|e| eyre!("Failed to connect to QMP socket {path}: {e}"))?;
+        Self::new(stream)
+    }
+
+    fn new(stream: UnixStream) -> Result<Self> {
+        let mut client = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        let greeting = client
+            .read_message()?
+            .ok_or_else(|| eyre!("QMP connection closed before greeting"))?;
+        if greeting.get("QMP").is_none() {
+            return Err(eyre!("Unexpected QMP greeting: {greeting}"));
+        }
+        client.execute("qmp_capabilities")?;
+        Ok(client)
+    }
+
+    /// Read the next message, or None if QEMU closed the connection.
+    fn read_message(&mut self) -> Result<Option<serde_json::Value>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let msg = serde_json::from_str(&line).with_context(|| format!("Parsing QMP: {line}"))?;
+        trace!("QMP: {line}");
+        Ok(Some(msg))
+    }
+
+    /// Run a QMP command without arguments, returning its result.
+    pub fn execute(&mut self, command: &str) -> Result<serde_json::Value> {
+        let request = serde_json::json!({ "execute": command });
+        writeln!(self.writer, "{request}")?;
+        loop {
+            let msg = self
+                .read_message()?
+                .ok_or_else(|| eyre!("QMP connection closed during {command}"))?;
+            if let Some(r) = msg.get("return") {
+                return Ok(r.clone());
+            }
+            if let Some(e) = msg.get("error") {
+                let desc = e.get("desc").and_then(|d| d.as_str()).unwrap_or_default();
+                return Err(eyre!("QMP {command} failed: {desc}"));
+            }
+            // Anything else is an asynchronous event
+        }
+    }
+
+    /// The VM run state, e.g. `running` or `paused`.
+    pub fn status(&mut self) -> Result<String> {
+        let r = self.execute("query-status")?;
+        r.get("status")
+            .and_then(|s| s.as_str())
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| eyre!("Unexpected query-status result: {r}"))
+    }
+
+    /// Wait for QEMU to exit, which closes the connection. Returns false on timeout.
+    pub fn wait_closed(&mut self, timeout: Duration) -> Result<bool> {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                return Ok(false);
+            }
+            self.reader.get_ref().set_read_timeout(Some(remaining))?;
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return Ok(true),
+                Ok(_) => trace!("QMP: {line}"),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(false)
+                }
+                // QEMU may reset the connection as it exits
+                Err(e) if e.kind() == ErrorKind::ConnectionReset => return Ok(true),
+                Err(e) => return Err(e.into()),
+            }
+        }
     }
 }
 
@@ -863,6 +1013,48 @@ impl RunningQemu {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_qmp_client() -> Result<()> {
+        let (client, server) = UnixStream::pair()?;
+        let qemu = std::thread::spawn(move || -> Result<Vec<String>> {
+            let mut reader = BufReader::new(server.try_clone()?);
+            let mut server = server;
+            let mut requests = Vec::new();
+            writeln!(
+                server,
+                r#"{{"QMP": {{"version": {{}}, "capabilities": []}}}}"#
+            )?;
+            for reply in [
+                r#"{"return": {}}"#,
+                r#"{"event": "STOP", "timestamp": {}}
+{"return": {"status": "paused", "running": false}}"#,
+                r#"{"error": {"class": "GenericError", "desc": "nope"}}"#,
+            ] {
+                let mut line = String::new();
+                reader.read_line(&mut line)?;
+                requests.push(line.trim().to_owned());
+                writeln!(server, "{reply}")?;
+            }
+            Ok(requests)
+        });
+
+        let mut qmp = QmpClient::new(client)?;
+        assert_eq!(qmp.status()?, "paused");
+        let err = qmp.execute("system_reset").unwrap_err();
+        assert_eq!(err.to_string(), "QMP system_reset failed: nope");
+        // The server side is gone
+        assert!(qmp.wait_closed(Duration::from_secs(5))?);
+        assert_eq!(
+            qemu.join().unwrap()?,
+            [
+                r#"{"execute":"qmp_capabilities"}"#,
+                r#"{"execute":"query-status"}"#,
+                r#"{"execute":"system_reset"}"#
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_virtio_serial_device_creation() {
         let mut config = QemuConfig::new_direct_boot(
@@ -886,6 +1078,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_validate_virtiofsd_id_maps() {
+        let td = tempfile::tempdir().unwrap();
+        let mut config = VirtiofsConfig {
+            socket_path: td.path().join("test.sock").to_str().unwrap().to_string(),
+            shared_dir: td.path().to_str().unwrap().to_string(),
+            uid_map: vec![":0:1000:1:".to_string()],
+            ..Default::default()
+        };
+        // Id maps need the namespace sandbox
+        assert!(validate_virtiofsd_config(&config).is_err());
+        config.sandbox = "namespace".to_string();
+        validate_virtiofsd_config(&config).unwrap();
+        config.gid_map = vec!["0:1000:1".to_string()];
+        assert!(validate_virtiofsd_config(&config).is_err());
+    }
+
     #[test]
     fn test_virtio_blk_device_creation() {
         let mut config = QemuConfig::new_disk_boot(1024, 1, "/tmp/boot.img".to_string());
@@ -923,6 +1132,7 @@ mod tests {
 
 /// VirtiofsD daemon configuration.
 /// Cache modes: always(default)/auto/none. Sandbox: none(default)/namespace/chroot.
+/// UID/GID maps require the namespace sandbox.
 #[derive(Debug, Clone)]
 pub struct VirtiofsConfig {
     /// Unix socket for QEMU communication
@@ -934,6 +1144,13 @@ pub struct VirtiofsConfig {
     /// Sandbox: none/namespace/chroot
     pub sandbox: String,
     pub debug: bool,
+    /// Pass extended attributes through to the guest, with SELinux labels
+    /// remapped per [`SELINUX_XATTRMAP`]
+    pub xattr: bool,
+    /// UID ranges mapped into the sandbox (`:namespace_uid:host_uid:count:`); requires sandbox=namespace
+    pub uid_map: Vec<String>,
+    /// GID ranges mapped into the sandbox (`:namespace_gid:host_gid:count:`); requires sandbox=namespace
+    pub gid_map: Vec<String>,
 }
 
 impl Default for VirtiofsConfig {
@@ -944,10 +1161,26 @@ impl Default for VirtiofsConfig {
             cache_mode: "always".to_string(),
             sandbox: "none".to_string(),
             debug: false,
+            xattr: false,
+            uid_map: Vec::new(),
+            gid_map: Vec::new(),
         }
     }
 }
 
+/// The xattr the guest's SELinux label of a file is stored in on the host.
+pub const SELINUX_XATTR_HOST: &str = "trusted.virtiofs.security.selinux";
+
+/// virtiofsd `--xattrmap` storing the guest's `security.selinux` as
+/// [`SELINUX_XATTR_HOST`]. Host labels are hidden from the guest, and the
+/// guest cannot change them or reach the remapped names directly.
+pub const SELINUX_XATTRMAP: &str = concat!(
+    ":prefix:all:security.selinux:trusted.virtiofs.:",
+    ":bad:all:security.selinux::",
+    ":bad:all:trusted.virtiofs.::",
+    ":ok:all:::",
+);
+
 /// Spawn virtiofsd daemon process as tokio::process::Child.
 /// Searches for binary in /usr/libexec, /usr/bin, /usr/local/bin.
 /// Creates socket directory if needed, redirects output unless debug=true.
@@ -990,6 +1223,15 @@ pub async fn spawn_virtiofsd_async(config: &VirtiofsConfig) -> Result<tokio::pro
         "--sandbox",
         &config.sandbox,
     ]);
+    if config.xattr {
+        cmd.args(["--xattr", &format!("--xattrmap={SELINUX_XATTRMAP}")]);
+    }
+    for map in &config.uid_map {
+        cmd.arg(format!("--uid-map={map}"));
+    }
+    for map in &config.gid_map {
+        cmd.arg(format!("--gid-map={map}"));
+    }
 
     // Redirect stdout/stderr to /dev/null unless debug mode is enabled
     if !config.debug {
@@ -1051,6 +1293,15 @@ pub fn spawn_virtiofsd(config: &VirtiofsConfig) -> Result<Child> {
         "--sandbox",
         &config.sandbox,
     ]);
+    if config.xattr {
+        cmd.args(["--xattr", &format!("--xattrmap={SELINUX_XATTRMAP}")]);
+    }
+    for map in &config.uid_map {
+        cmd.arg(format!("--uid-map={map}"));
+    }
+    for map in &config.gid_map {
+        cmd.arg(format!("--gid-map={map}"));
+    }
 
     // Redirect stdout/stderr to /dev/null unless debug mode is enabled
     if !config.debug {
@@ -1163,5 +1414,28 @@ pub fn validate_virtiofsd_config(config: &VirtiofsConfig) -> Result<()> {
         ));
     }
 
+    // Validate uid/gid maps
+    for map in config.uid_map.iter().chain(&config.gid_map) {
+        let valid = map
+            .strip_prefix(':')
+            .and_then(|m| m.strip_suffix(':'))
+            .is_some_and(|m| {
+                let fields: Vec<&str> = m.split(':').collect();
+                fields.len() == 3 && fields.iter().all(|f| f.parse::<u32>().is_ok())
+            });
+        if !valid {
+            return Err(eyre!(
+                "Invalid virtiofsd id map: '{}'. Expected :namespace_id:host_id:count:",
+                map
+            ));
+        }
+    }
+    if (!config.uid_map.is_empty() || !config.gid_map.is_empty()) && config.sandbox != "namespace" {
+        return Err(eyre!(
+            "virtiofsd uid/gid maps require the namespace sandbox (got '{}')",
+            config.sandbox
+        ));
+    }
+
     Ok(())
 }
@@ -56,21 +56,29 @@
 //!   - Additional daemons: one per host mount (`--bind`/`--ro-bind`)
 //! - Generates systemd `.mount` units for virtiofs mounts
 //! - Configures and launches QEMU with VirtioFS root
+//! - Without `--console`, logs and relays the serial console (see [`crate::console`])
 //!
 //! ## VirtioFS Architecture
 //!
 //! The system uses VirtioFS for high-performance filesystem sharing:
 //! - **Root FS**: Container image mounted via main virtiofsd at `/run/inner-shared/virtiofs.sock`
 //! - **Host Mounts**: Separate virtiofsd per mount at `/run/inner-shared/virtiofs-<name>.sock`
-//! - **VM Access**: Mounts appear at `/run/virtiofs-mnt-<name>` via systemd units
+//! - **VM Access**: Mounts appear at `/run/virtiofs-mnt-<name>` (or a given guest path) via systemd units
 //!
 //! ## Command Execution (`--execute`)
 //!
 //! For running commands inside the VM:
 //! 1. Creates systemd services (`bootc-execute.service`, `bootc-execute-finish.service`)
-//! 2. Uses VirtioSerial devices for output (`execute`) and status (`executestatus`)
+//! 2. Uses VirtioSerial devices for stdout (`execute`), stderr (`executeerr`) and status (`executestatus`)
 //! 3. Streams output in real-time via monitoring thread on host
-//! 4. Captures exit codes via systemd service status
+//! 4. `--execute-script` installs a host script into the image and runs it after
+//!    any `--execute` commands, with `--execute-env` setting the service environment
+//! 5. Captures exit codes via systemd service status, optionally written as JSON with `--execute-result`
+//! 6. With `--timeout`, a watchdog prints the console tail and pending guest units,
+//!    stops QEMU and exits with [`TIMEOUT_EXIT_CODE`] if the command does not finish
+//! 7. `--artifacts-dir` shares a writable host directory into which the guest
+//!    collects its journal and unit state before powering off; the container
+//!    adds the serial console log
 //!
 //! ## Security Model
 //!
@@ -91,10 +99,11 @@ use std::fs::File;
 use std::io::{BufWriter, Seek, Write};
 use std::os::unix::process::CommandExt;
 use std::process::Command;
+use std::time::Duration;
 
 use bootc_utils::CommandRunExt;
 use camino::{Utf8Path, Utf8PathBuf};
-use clap::Parser;
+use clap::{ArgGroup, Parser, ValueEnum};
 use color_eyre::eyre::{eyre, Context};
 use color_eyre::Result;
 use rustix::path::Arg;
@@ -102,7 +111,62 @@ use serde::{Deserialize, Serialize};
 use tokio::io::AsyncReadExt;
 use tracing::debug;
 
-const ENTRYPOINT: &str = "/var/lib/bcvk/entrypoint";
+pub(crate) const ENTRYPOINT: &str = "/var/lib/bcvk/entrypoint";
+
+/// Serial of the `--state-disk` virtio-blk device (appears as /dev/disk/by-id/virtio-bcvk-state)
+const STATE_DISK_SERIAL: &str = "bcvk-state";
+/// Where a file-backed state disk is mounted inside the container
+const STATE_DISK_CONTAINER_PATH: &str = "/run/disk-files/bcvk-state";
+/// Where the state disk filesystem is mounted in the guest
+const STATE_DISK_GUEST_MOUNT: &str = "/run/bcvk-state";
+
+/// Where resolved `--credential` values are mounted inside the container,
+/// one SMBIOS credential string per line
+const CREDENTIALS_PATH: &str = "/run/bcvk-credentials";
+
+/// Where `--copy-in` and `--copy-in-tar` sources are mounted inside the container
+const COPY_IN_DIR: &str = "/run/copy-in";
+
+/// Where `--kernel`, `--initrd` and `--modules-dir` are mounted inside the container
+const HOST_KERNEL_DIR: &str = "/run/host-kernel";
+
+/// Exit code when `--timeout` expires, as with timeout(1)
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Where the `--execute-script` script is mounted inside the container
+const EXECUTE_SCRIPT_CONTAINER_PATH: &str = "/run/bcvk-execute-script";
+
+/// Where the `--execute-script` script is installed in the guest
+const EXECUTE_SCRIPT_GUEST_PATH: &str = "/usr/libexec/bcvk-execute-script";
+
+/// Where `--artifacts-dir` is mounted in the guest
+const ARTIFACTS_GUEST_DIR: &str = "/run/bcvk-artifacts";
+
+/// Path of the artifact collection script in the guest
+const ARTIFACTS_COLLECTOR_PATH: &str = "/usr/libexec/bcvk-collect-artifacts";
+
+/// Collects diagnostics into the artifacts share once `bootc-execute.service`
+/// has finished; `$1` is the `--artifacts` policy.
+const ARTIFACTS_COLLECTOR_SCRIPT: &str = r#"#!/bin/sh
+# bcvk: collect diagnostics into the --artifacts-dir share
+if [ "$1" != always ] && [ "$(systemctl show --value -p Result bootc-execute.service)" = success ]; then
+    exit 0
+fi
+dir=/run/bcvk-artifacts
+exec >"$dir/collect.log" 2>&1
+journalctl -b --no-pager -o short-precise >"$dir/journal.txt"
+systemctl --failed --no-pager >"$dir/systemctl-failed.txt"
+systemctl status --no-pager bootc-execute.service >"$dir/execute-status.txt"
+if command -v bootc >/dev/null; then
+    bootc status --json >"$dir/bootc-status.json"
+fi
+"#;
+
+/// Where the `--execute-result` file is mounted inside the container
+const EXECUTE_RESULT_PATH: &str = "/run/bcvk-execute-result.json";
+
+/// Lines of console output shown when `--timeout` expires
+const CONSOLE_TAIL_LINES: usize = 50;
 
 /// Get default vCPU count (number of available processors, or 2 as fallback)
 pub fn default_vcpus() -> u32 {
@@ -112,11 +176,17 @@ pub fn default_vcpus() -> u32 {
 }
 
 use crate::qemu;
+use crate::selinux;
 use crate::{
     boot_progress,
-    common_opts::MemoryOpts,
+    boot_report::{BootRecorder, EventSource, BOOT_REPORT_PATH, BOOT_START_ENV},
+    common_opts::{CredentialOpts, MemoryOpts},
+    execute_result::ExecuteResult,
     podman,
-    supervisor_status::{StatusWriter, SupervisorState, SupervisorStatus},
+    supervisor_status::{
+        BootFailure, BootFailureKind, MountInfo, StatusWriter, SupervisorState, SupervisorStatus,
+        VmInfo, SUPERVISOR_STATUS_PATH,
+    },
     systemd, utils, CONTAINER_STATEDIR,
 };
 
@@ -199,6 +269,16 @@ pub struct CommonVmOpts {
         help = "Generate SSH keypair and inject via systemd credentials"
     )]
     pub ssh_keygen: bool,
+
+    #[clap(
+        long = "user",
+        value_name = "NAME[:GROUPS]",
+        help = "Create a non-root user with passwordless sudo (and the SSH key with -K), used by default for SSH; GROUPS is comma-separated"
+    )]
+    pub user: Option<String>,
+
+    #[clap(flatten)]
+    pub credentials: CredentialOpts,
 }
 
 impl CommonVmOpts {
@@ -219,7 +299,8 @@ impl CommonVmOpts {
 }
 
 /// Ephemeral VM options: container-style flags, host bind mounts, systemd injection.
-#[derive(Parser, Debug, Clone, Serialize, Deserialize)]
+#[derive(Parser, Debug, Clone, Default, Serialize, Deserialize)]
+#[clap(group(ArgGroup::new("execute_any").args(["execute", "execute_script"]).multiple(true)))]
 pub struct RunEphemeralOpts {
     #[clap(help = "Container image to run as ephemeral VM")]
     pub image: String,
@@ -232,21 +313,21 @@ pub struct RunEphemeralOpts {
 
     #[clap(
         long = "bind",
-        value_name = "HOST_PATH[:NAME]",
-        help = "Bind mount host directory (RW) at /run/virtiofs-mnt-<name>"
+        value_name = "HOST_PATH[:NAME|:GUEST_PATH][:OPTIONS]",
+        help = "Bind mount host directory (RW) at GUEST_PATH or /run/virtiofs-mnt-<name>; OPTIONS: ro, cache=none|auto|always, uid-map=:GUEST:HOST:COUNT:, gid-map=..."
     )]
     pub bind_mounts: Vec<String>,
 
     #[clap(
         long = "ro-bind",
-        value_name = "HOST_PATH[:NAME]",
-        help = "Bind mount host directory (RO) at /run/virtiofs-mnt-<name>"
+        value_name = "HOST_PATH[:NAME|:GUEST_PATH][:OPTIONS]",
+        help = "Bind mount host directory (RO) at GUEST_PATH or /run/virtiofs-mnt-<name>; takes the same OPTIONS as --bind"
     )]
     pub ro_bind_mounts: Vec<String>,
 
     #[clap(
         long = "systemd-units",
-        help = "Directory with systemd units to inject (expects system/ subdirectory)"
+        help = "Directory with systemd units to inject, laid out like /etc/systemd (system/, user/, system-preset/, user-preset/)"
     )]
     pub systemd_units_dir: Option<String>,
 
@@ -271,8 +352,627 @@ pub struct RunEphemeralOpts {
         help = "Mount disk file as virtio-blk device at /dev/disk/by-id/virtio-<name>"
     )]
     pub mount_disk_files: Vec<String>,
+
+    #[clap(
+        short = 'p',
+        long = "publish",
+        value_name = "HOST_PORT:GUEST_PORT[/PROTO]",
+        help = "Publish a guest port on the host (PROTO is tcp or udp, default tcp)"
+    )]
+    pub publish: Vec<String>,
+
+    #[clap(
+        long = "state-disk",
+        value_name = "SIZE|FILE",
+        help = "Back the writable /etc and /var with a disk instead of RAM; a SIZE (e.g. 20G) is discarded on exit, a FILE is preserved across runs"
+    )]
+    pub state_disk: Option<String>,
+
+    #[clap(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "enforcing",
+        help = "Boot with SELinux enabled, labeling the image with its own policy (default mode: enforcing)"
+    )]
+    pub selinux: Option<SelinuxMode>,
+
+    #[clap(
+        long = "kernel-version",
+        value_name = "VERSION",
+        help = "Kernel version to boot when the image has several (default: newest)"
+    )]
+    pub kernel_version: Option<String>,
+
+    #[clap(
+        long = "kernel",
+        value_name = "PATH",
+        conflicts_with = "kernel_version",
+        help = "Boot this kernel from the host instead of the image's kernel"
+    )]
+    pub kernel: Option<String>,
+
+    #[clap(
+        long = "initrd",
+        value_name = "PATH",
+        requires = "kernel",
+        help = "Initramfs from the host to use with --kernel (default: the image's initramfs)"
+    )]
+    pub initrd: Option<String>,
+
+    #[clap(
+        long = "modules-dir",
+        value_name = "PATH",
+        requires = "kernel",
+        help = "Host kernel modules to mount at /usr/lib/modules (a modules tree or an INSTALL_MOD_PATH root)"
+    )]
+    pub modules_dir: Option<String>,
+
+    #[clap(
+        long = "copy-in",
+        value_name = "HOST_PATH:GUEST_PATH",
+        help = "Copy a host file or directory into the image before boot, preserving ownership and mode"
+    )]
+    pub copy_in: Vec<String>,
+
+    #[clap(
+        long = "copy-in-tar",
+        value_name = "TARBALL[:GUEST_DIR]",
+        help = "Extract a (possibly compressed) tarball into the image before boot (default GUEST_DIR: /); applied before --copy-in"
+    )]
+    pub copy_in_tar: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "DURATION",
+        requires = "execute_any",
+        help = "Time limit for --execute (e.g. 90s, 30m, 1h); on expiry dump guest state, stop the VM and exit with code 124"
+    )]
+    pub timeout: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        requires = "execute_any",
+        help = "Write the --execute result (exit code, signal, systemd Result=, timestamps and per-command status) as JSON to FILE"
+    )]
+    pub execute_result: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "PATH [ARGS...]",
+        help = "Run a script from the host in the VM after any --execute commands; ARGS are split like a shell would, and scripts without a #! line run with /bin/sh"
+    )]
+    pub execute_script: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "NAME=VALUE",
+        requires = "execute_any",
+        help = "Set an environment variable for --execute and --execute-script"
+    )]
+    pub execute_env: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        requires = "execute_any",
+        help = "Collect the guest journal, systemctl --failed, bootc status and the serial console log into DIR when --execute fails"
+    )]
+    pub artifacts_dir: Option<String>,
+
+    #[clap(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        default_value_t,
+        requires = "artifacts_dir",
+        help = "When to collect --artifacts-dir"
+    )]
+    pub artifacts: ArtifactsPolicy,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "Write the time each boot phase was reached as JSON to FILE, updated as the VM boots"
+    )]
+    pub boot_report: Option<String>,
+}
+
+/// When to collect `--artifacts-dir`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArtifactsPolicy {
+    /// Only when the executed command fails or times out
+    #[default]
+    OnFailure,
+    /// After every run
+    Always,
+}
+
+impl ArtifactsPolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ArtifactsPolicy::OnFailure => "on-failure",
+            ArtifactsPolicy::Always => "always",
+        }
+    }
+}
+
+/// Guest SELinux mode for `--selinux`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelinuxMode {
+    /// Load the policy but only log denials
+    Permissive,
+    /// Load and enforce the policy
+    Enforcing,
 }
 
+/// Backing storage for the writable `/etc` and `/var` overlays, from `--state-disk`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum StateDisk {
+    /// A disposable disk of the given size in bytes, discarded when the VM exits
+    Size(u64),
+    /// A disk image file on the host, created if missing and preserved across runs
+    File(Utf8PathBuf),
+}
+
+impl StateDisk {
+    /// Parse a `SIZE|FILE` specification; anything that is not a valid size is a path.
+    pub(crate) fn parse(spec: &str) -> Result<Self> {
+        match utils::parse_size(spec) {
+            Ok(0) => Err(eyre!("State disk size must be non-zero: {spec}")),
+            Ok(size) => Ok(Self::Size(size)),
+            Err(_) if spec.contains(':') => {
+                Err(eyre!("State disk path must not contain ':': {spec}"))
+            }
+            Err(_) => Ok(Self::File(spec.into())),
+        }
+    }
+}
+
+/// A host directory shared with the guest via virtiofs (`--bind`/`--ro-bind`).
+///
+/// The specification is `HOST_PATH[:NAME|:GUEST_PATH][:OPTIONS]`, where
+/// OPTIONS is a comma-separated list of `ro`, `rw`, `cache=none|auto|always`,
+/// `uid-map=:GUEST:HOST:COUNT:` and `gid-map=:GUEST:HOST:COUNT:`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BindMount {
+    pub(crate) host_path: String,
+    /// Identifies the mount; used for the container path, virtiofs tag and socket
+    pub(crate) name: String,
+    /// Where the guest mounts it (default: /run/virtiofs-mnt-<name>)
+    pub(crate) guest_path: String,
+    pub(crate) readonly: bool,
+    /// virtiofsd cache mode
+    pub(crate) cache: String,
+    pub(crate) uid_map: Vec<String>,
+    pub(crate) gid_map: Vec<String>,
+}
+
+impl BindMount {
+    /// Parse a mount specification; `readonly` is the default for the option used.
+    pub(crate) fn parse(spec: &str, readonly: bool) -> Result<Self> {
+        let mut parts = spec.splitn(3, ':');
+        let host_path = parts.next().unwrap_or_default().to_string();
+        if host_path.is_empty() {
+            return Err(eyre!("Missing host path in mount: {spec}"));
+        }
+        let (name, guest_path) = match parts.next().filter(|s| !s.is_empty()) {
+            Some(guest_path) if guest_path.starts_with('/') => {
+                let guest_path = guest_path.trim_end_matches('/');
+                if guest_path.is_empty() || guest_path.split('/').any(|c| c == "..") {
+                    return Err(eyre!("Invalid guest mount path in mount: {spec}"));
+                }
+                let name = guest_path.trim_start_matches('/').replace('/', "-");
+                (name, Some(guest_path.to_string()))
+            }
+            Some(name) => (name.to_string(), None),
+            None => {
+                let name = Utf8Path::new(&host_path).file_name().unwrap_or("mount");
+                (name.to_string(), None)
+            }
+        };
+        let guest_path = guest_path.unwrap_or_else(|| format!("/run/virtiofs-mnt-{name}"));
+
+        let mut r = Self {
+            host_path,
+            name,
+            guest_path,
+            readonly,
+            cache: "always".to_string(),
+            uid_map: Vec::new(),
+            gid_map: Vec::new(),
+        };
+        for option in parts.next().into_iter().flat_map(|o| o.split(',')) {
+            match option.split_once('=') {
+                None if option == "ro" => r.readonly = true,
+                None if option == "rw" && !readonly => r.readonly = false,
+                Some(("cache", v)) => r.cache = v.to_string(),
+                Some(("uid-map", v)) => r.uid_map.push(v.to_string()),
+                Some(("gid-map", v)) => r.gid_map.push(v.to_string()),
+                _ => return Err(eyre!("Unknown mount option '{option}' in: {spec}")),
+            }
+        }
+        Ok(r)
+    }
+
+    /// The virtiofsd configuration sharing `shared_dir` (this mount as seen in the container)
+    pub(crate) fn virtiofs_config(&self, shared_dir: &str) -> qemu::VirtiofsConfig {
+        let has_id_maps = !self.uid_map.is_empty() || !self.gid_map.is_empty();
+        qemu::VirtiofsConfig {
+            socket_path: format!("/run/inner-shared/virtiofs-{}.sock", self.name),
+            shared_dir: shared_dir.to_string(),
+            cache_mode: self.cache.clone(),
+            // Mapping ids needs virtiofsd's user namespace
+            sandbox: if has_id_maps { "namespace" } else { "none" }.to_string(),
+            uid_map: self.uid_map.clone(),
+            gid_map: self.gid_map.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Parse all `--bind` and `--ro-bind` mounts, rejecting duplicate names.
+fn parse_bind_mounts(opts: &RunEphemeralOpts) -> Result<Vec<BindMount>> {
+    let rw = opts.bind_mounts.iter().map(|s| BindMount::parse(s, false));
+    let ro = opts
+        .ro_bind_mounts
+        .iter()
+        .map(|s| BindMount::parse(s, true));
+    let mounts = rw.chain(ro).collect::<Result<Vec<_>>>()?;
+    let mut names = std::collections::HashSet::new();
+    for mount in &mounts {
+        if !names.insert(mount.name.as_str()) {
+            return Err(eyre!("Duplicate bind mount name: {}", mount.name));
+        }
+    }
+    Ok(mounts)
+}
+
+/// A file, directory or tarball added to the image before boot (`--copy-in`, `--copy-in-tar`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CopyIn {
+    pub(crate) host_path: Utf8PathBuf,
+    /// Destination in the guest; for tarballs, the directory to extract into
+    pub(crate) guest_path: Utf8PathBuf,
+    pub(crate) tar: bool,
+}
+
+impl CopyIn {
+    /// Parse `HOST_PATH:GUEST_PATH`, or `TARBALL[:GUEST_DIR]` if `tar` is set.
+    pub(crate) fn parse(spec: &str, tar: bool) -> Result<Self> {
+        let (host_path, guest_path) = match spec.split_once(':') {
+            Some((host, guest)) => (host, guest),
+            None if tar => (spec, "/"),
+            None => {
+                return Err(eyre!(
+                    "Invalid --copy-in {spec:?}, expected HOST_PATH:GUEST_PATH"
+                ))
+            }
+        };
+        let guest_path = Utf8Path::new(guest_path);
+        if !guest_path.is_absolute() || guest_path.as_str().split('/').any(|c| c == "..") {
+            return Err(eyre!("Guest path must be absolute: {spec}"));
+        }
+        let host_path = Utf8PathBuf::from(host_path);
+        if host_path.file_name().is_none() {
+            return Err(eyre!("Cannot determine the file name to copy from: {spec}"));
+        }
+        Ok(Self {
+            host_path,
+            guest_path: guest_path.to_owned(),
+            tar,
+        })
+    }
+
+    /// Where the `index`th source is mounted in the container; the file name
+    /// is kept so copying into a directory uses the right name.
+    fn container_path(&self, index: usize) -> Utf8PathBuf {
+        let kind = if self.tar { "tar" } else { "path" };
+        // file_name() was checked in parse()
+        let name = self.host_path.file_name().unwrap();
+        Utf8Path::new(COPY_IN_DIR)
+            .join(format!("{kind}{index}"))
+            .join(name)
+    }
+
+    /// Copy from `source` into the image at `root`, like `cp -a` or `tar -xp`.
+    fn apply(&self, source: &Utf8Path, root: &Utf8Path) -> Result<()> {
+        let guest_path = self.guest_path.as_path();
+        if self.tar {
+            // tar would follow symlinks in the image (even absolute ones) while
+            // extracting, so unpack elsewhere and copy each entry in confined
+            let scratch = tempfile::tempdir()?;
+            let scratch = Utf8Path::from_path(scratch.path())
+                .ok_or_else(|| eyre!("Non-UTF-8 temporary directory"))?;
+            Command::new("tar")
+                .args(["-x", "-p", "--numeric-owner", "-f"])
+                .arg(source)
+                .arg("-C")
+                .arg(scratch)
+                .run()
+                .map_err(|e| eyre!("Extracting {}: {e}", self.host_path))?;
+            copy_tree_in_root(scratch, root, guest_path, false)
+                .with_context(|| format!("Copying {} into the image", self.host_path))?;
+        } else if source.is_dir() {
+            // Like `cp -a`, copy into an existing directory or create the
+            // destination, but resolve every directory within the image;
+            // cp would follow absolute symlinks out of it.
+            let into = match (guest_path.parent(), guest_path.file_name()) {
+                (Some(parent), Some(name)) if !guest_path.as_str().ends_with('/') => {
+                    let dest = resolve_in_root(root, parent)?.join(name);
+                    dest.symlink_metadata().is_ok()
+                }
+                _ => true,
+            };
+            let dest = if into {
+                // file_name() was checked in parse()
+                guest_path.join(self.host_path.file_name().unwrap())
+            } else {
+                guest_path.to_owned()
+            };
+            copy_tree_in_root(source, root, &dest, true)
+                .with_context(|| format!("Copying {} to {}", self.host_path, self.guest_path))?;
+        } else {
+            // A trailing slash means "into this directory", creating it if needed;
+            // a symlink is followed within the image rather than replaced.
+            let dest = match (guest_path.parent(), guest_path.file_name()) {
+                (Some(parent), Some(name)) if !guest_path.as_str().ends_with('/') => {
+                    let dest = resolve_in_root(root, parent)?.join(name);
+                    if dest.is_symlink() {
+                        resolve_in_root(root, guest_path)?
+                    } else {
+                        dest
+                    }
+                }
+                _ => resolve_in_root(root, guest_path)?,
+            };
+            Command::new("cp")
+                .args(["-a", "--no-dereference", "--no-preserve=context"])
+                .arg(source)
+                .arg(&dest)
+                .run()
+                .map_err(|e| eyre!("Copying {} to {}: {e}", self.host_path, self.guest_path))?;
+        }
+        debug!(
+            "Copied {} into the image at {}",
+            self.host_path, self.guest_path
+        );
+        Ok(())
+    }
+}
+
+/// Copy the contents of the directory `src` to `dest` inside the image at
+/// `root`, resolving every destination directory with [`resolve_in_root`] so
+/// nothing is written outside the image. Existing non-directories are
+/// replaced rather than followed, like `tar -x` does. With `preserve`, the
+/// owner and mode of `src` are applied to `dest`.
+fn copy_tree_in_root(
+    src: &Utf8Path,
+    root: &Utf8Path,
+    dest: &Utf8Path,
+    preserve: bool,
+) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dest_dir = resolve_in_root(root, dest)?;
+    if preserve {
+        let meta = src.symlink_metadata()?;
+        std::os::unix::fs::chown(&dest_dir, Some(meta.uid()), Some(meta.gid()))?;
+        std::fs::set_permissions(&dest_dir, meta.permissions())?;
+    }
+
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for entry in src.read_dir_utf8()? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push(entry.file_name().to_owned());
+            continue;
+        }
+        let target = dest_dir.join(entry.file_name());
+        match target.symlink_metadata() {
+            Ok(m) if m.is_dir() => {
+                return Err(eyre!("Cannot replace directory {target} with a file"))
+            }
+            Ok(_) => std::fs::remove_file(&target)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        files.push(entry.into_path());
+    }
+    if !files.is_empty() {
+        Command::new("cp")
+            .args(["-a", "--no-dereference", "--no-preserve=context", "-t"])
+            .arg(&dest_dir)
+            .args(&files)
+            .run()
+            .map_err(|e| eyre!("Copying into {dest}: {e}"))?;
+    }
+    for name in dirs {
+        copy_tree_in_root(&src.join(&name), root, &dest.join(&name), true)?;
+    }
+    Ok(())
+}
+
+/// Resolve the directory `path` inside the image at `root`, creating missing
+/// directories along the way.
+///
+/// Symlinks are followed relative to `root` rather than the host, so
+/// e.g. `/usr/local -> ../var/usrlocal` in bootc images works even when the
+/// target has not been created yet (that normally happens at boot via tmpfiles).
+fn resolve_in_root(root: &Utf8Path, path: &Utf8Path) -> Result<Utf8PathBuf> {
+    use camino::Utf8Component;
+
+    let mut resolved = root.to_owned();
+    let mut pending: Vec<String> = path
+        .components()
+        .rev()
+        .filter_map(|c| match c {
+            Utf8Component::Normal(n) => Some(n.to_owned()),
+            Utf8Component::ParentDir => Some("..".to_owned()),
+            _ => None,
+        })
+        .collect();
+    let mut links = 0;
+    while let Some(name) = pending.pop() {
+        if name == ".." {
+            if resolved != root {
+                resolved.pop();
+            }
+            continue;
+        }
+        let candidate = resolved.join(&name);
+        match candidate.symlink_metadata() {
+            Ok(meta) if meta.is_symlink() => {
+                links += 1;
+                if links > 40 {
+                    return Err(eyre!("Too many levels of symbolic links resolving {path}"));
+                }
+                let target = candidate.read_link_utf8()?;
+                if target.is_absolute() {
+                    resolved = root.to_owned();
+                }
+                pending.extend(target.components().rev().filter_map(|c| match c {
+                    Utf8Component::Normal(n) => Some(n.to_owned()),
+                    Utf8Component::ParentDir => Some("..".to_owned()),
+                    _ => None,
+                }));
+            }
+            Ok(meta) if meta.is_dir() => resolved = candidate,
+            Ok(_) => return Err(eyre!("{path}: {name} is not a directory in the image")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                std::fs::create_dir(&candidate).with_context(|| format!("Creating {candidate}"))?;
+                resolved = candidate;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(resolved)
+}
+
+/// Parse `--copy-in-tar` and `--copy-in`, in the order they are applied.
+fn parse_copy_in(opts: &RunEphemeralOpts) -> Result<Vec<CopyIn>> {
+    let tars = opts.copy_in_tar.iter().map(|s| CopyIn::parse(s, true));
+    let paths = opts.copy_in.iter().map(|s| CopyIn::parse(s, false));
+    tars.chain(paths).collect()
+}
+
+/// Transport protocol of a published port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PortProtocol {
+    Tcp,
+    Udp,
+}
+
+impl PortProtocol {
+    /// Protocol name as understood by both podman and QEMU hostfwd
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            PortProtocol::Tcp => "tcp",
+            PortProtocol::Udp => "udp",
+        }
+    }
+}
+
+/// A guest port published on the host via `--publish`.
+///
+/// The port is forwarded twice: podman maps the host port to the same-numbered
+/// port in the container, and QEMU user networking forwards that container port
+/// to the guest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PublishedPort {
+    pub(crate) host_port: u16,
+    pub(crate) guest_port: u16,
+    pub(crate) protocol: PortProtocol,
+}
+
+impl PublishedPort {
+    /// Parse a `HOST_PORT:GUEST_PORT[/PROTO]` specification.
+    pub(crate) fn parse(spec: &str) -> Result<Self> {
+        let (ports, protocol) = match spec.split_once('/') {
+            Some((ports, "tcp")) => (ports, PortProtocol::Tcp),
+            Some((ports, "udp")) => (ports, PortProtocol::Udp),
+            Some((_, proto)) => {
+                return Err(eyre!(
+                    "Unsupported protocol '{proto}' in publish spec: {spec}"
+                ))
+            }
+            None => (spec, PortProtocol::Tcp),
+        };
+        let (host, guest) = ports
+            .split_once(':')
+            .ok_or_else(|| eyre!("Invalid publish spec (expected HOST_PORT:GUEST_PORT): {spec}"))?;
+        let parse_port = |v: &str| -> Result<u16> {
+            match v.parse::<u16>() {
+                Ok(0) | Err(_) => Err(eyre!("Invalid port '{v}' in publish spec: {spec}")),
+                Ok(port) => Ok(port),
+            }
+        };
+        Ok(Self {
+            host_port: parse_port(host)?,
+            guest_port: parse_port(guest)?,
+            protocol,
+        })
+    }
+
+    /// Parse all `--publish` specifications, rejecting guest ports that are
+    /// published twice or, with `ssh`, that collide with the SSH forward.
+    pub(crate) fn parse_all(specs: &[String], ssh: bool) -> Result<Vec<Self>> {
+        let mut ports: Vec<Self> = Vec::with_capacity(specs.len());
+        for spec in specs {
+            let port = Self::parse(spec)?;
+            let proto = port.protocol.as_str();
+            if ssh
+                && port.protocol == PortProtocol::Tcp
+                && port.guest_port == qemu::SSH_FORWARD_PORT
+            {
+                return Err(eyre!(
+                    "Cannot publish guest port {}/{proto}: it is used to forward SSH to the VM",
+                    port.guest_port
+                ));
+            }
+            if ports
+                .iter()
+                .any(|p| p.guest_port == port.guest_port && p.protocol == port.protocol)
+            {
+                return Err(eyre!(
+                    "Guest port {}/{proto} is published more than once",
+                    port.guest_port
+                ));
+            }
+            ports.push(port);
+        }
+        Ok(ports)
+    }
+
+    /// Argument for `podman run --publish`; the container side uses the guest port number.
+    fn podman_arg(&self) -> String {
+        format!(
+            "{}:{}/{}",
+            self.host_port,
+            self.guest_port,
+            self.protocol.as_str()
+        )
+    }
+}
+
+/// Error returned by [`run_synchronous`] when the `--timeout` for `--execute` expired.
+#[derive(Debug)]
+pub struct ExecuteTimedOut;
+
+impl std::fmt::Display for ExecuteTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Timed out waiting for the executed command to finish")
+    }
+}
+
+impl std::error::Error for ExecuteTimedOut {}
+
 /// Launch privileged container with QEMU+KVM for ephemeral VM, spawning as subprocess.
 /// Returns the container ID instead of executing the command.
 pub fn run_detached(opts: RunEphemeralOpts) -> Result<String> {
@@ -310,6 +1010,9 @@ pub fn run_synchronous(opts: RunEphemeralOpts) -> Result<()> {
 
     // Use the same approach as run_detached but wait for completion instead of detaching
     let status = cmd.status().context("Failed to execute podman command")?;
+    if status.code() == Some(TIMEOUT_EXIT_CODE) {
+        return Err(ExecuteTimedOut.into());
+    }
     if !status.success() {
         return Err(color_eyre::eyre::eyre!("ephemeral run failed {status:?}",));
     }
@@ -319,7 +1022,7 @@ pub fn run_synchronous(opts: RunEphemeralOpts) -> Result<()> {
 }
 
 fn prepare_run_command_with_temp(
-    opts: RunEphemeralOpts,
+    mut opts: RunEphemeralOpts,
 ) -> Result<(std::process::Command, tempfile::TempDir)> {
     debug!("Running QEMU inside hybrid container for {}", opts.image);
 
@@ -342,9 +1045,44 @@ fn prepare_run_command_with_temp(
     let self_exe = std::env::current_exe()?;
     let self_exe = self_exe.as_str()?;
 
+    // Catch an invalid --timeout before creating the container
+    if let Some(timeout) = opts.timeout.as_deref() {
+        utils::parse_duration(timeout)?;
+    }
+
+    // The guest writes artifacts through a writable share; the container
+    // adds the console log to the same directory
+    if let Some(dir) = opts.artifacts_dir.as_deref() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Creating {dir}"))?;
+        let dir = Utf8Path::new(dir).canonicalize_utf8()?;
+        if dir.as_str().contains(':') {
+            return Err(eyre!("--artifacts-dir cannot contain ':': {dir}"));
+        }
+        opts.bind_mounts
+            .push(format!("{dir}:{ARTIFACTS_GUEST_DIR}"));
+    }
+
     // Process disk files and create them if needed
     let processed_disk_files = process_disk_files(&opts.mount_disk_files, &opts.image)?;
 
+    // A file-backed state disk is created on the host like --mount-disk-file; a
+    // sized one is allocated inside the container instead
+    let state_disk_file = match opts
+        .state_disk
+        .as_deref()
+        .map(StateDisk::parse)
+        .transpose()?
+    {
+        Some(StateDisk::File(path)) => {
+            let spec = format!("{path}:{STATE_DISK_SERIAL}");
+            process_disk_files(&[spec], &opts.image)?
+                .into_iter()
+                .next()
+                .map(|(path, _, _)| path)
+        }
+        Some(StateDisk::Size(_)) | None => None,
+    };
+
     // Parse mount arguments (both bind and ro-bind)
     let mut host_mounts = Vec::new();
 
@@ -364,45 +1102,42 @@ fn prepare_run_command_with_temp(
         // true = read-only
     }
 
-    // Parse writable bind mounts
-    for mount_spec in &opts.bind_mounts {
-        let (host_path, mount_name) = if let Some((path, name)) = mount_spec.split_once(':') {
-            (path.to_string(), name.to_string())
-        } else {
-            let path = mount_spec.clone();
-            let name = Utf8Path::new(&path)
-                .file_name()
-                .unwrap_or("mount")
-                .to_string();
-            (path, name)
-        };
-        host_mounts.push((host_path, mount_name, false)); // false = writable
+    // Parse bind mounts (both writable and read-only)
+    for mount in parse_bind_mounts(&opts)? {
+        host_mounts.push((mount.host_path, mount.name, mount.readonly));
     }
 
-    // Parse read-only bind mounts
-    for mount_spec in &opts.ro_bind_mounts {
-        let (host_path, mount_name) = if let Some((path, name)) = mount_spec.split_once(':') {
-            (path.to_string(), name.to_string())
-        } else {
-            let path = mount_spec.clone();
-            let name = Utf8Path::new(&path)
-                .file_name()
-                .unwrap_or("mount")
-                .to_string();
-            (path, name)
-        };
-        host_mounts.push((host_path, mount_name, true)); // true = read-only
-    }
+    let published_ports = PublishedPort::parse_all(&opts.publish, opts.common.ssh_keygen)?;
 
     // Run the container with the setup script
     let mut cmd = Command::new("podman");
     cmd.arg("run");
     // We always have a label
     cmd.arg("--label=bcvk.ephemeral=1");
+    if let Some(user) = opts.common.user.as_deref() {
+        let user = crate::sshcred::SshUser::parse(user)?;
+        cmd.arg(format!(
+            "--label={}={}",
+            crate::ssh::SSH_USER_LABEL,
+            user.name
+        ));
+    }
     for label in opts.podman.label.iter() {
         cmd.arg(format!("--label={label}"));
     }
-    cmd.arg(format!("--net={}", opts.common.net_string().as_str()));
+    match opts.common.net.as_deref() {
+        // Published ports need a container network; use the podman default
+        None if !published_ports.is_empty() => {}
+        Some("none") if !published_ports.is_empty() => {
+            return Err(eyre!("--publish cannot be combined with --net=none"));
+        }
+        _ => {
+            cmd.arg(format!("--net={}", opts.common.net_string().as_str()));
+        }
+    }
+    for port in &published_ports {
+        cmd.arg(format!("--publish={}", port.podman_arg()));
+    }
 
     // Add container name if specified
     if let Some(ref name) = opts.podman.name {
@@ -482,16 +1217,102 @@ fn prepare_run_command_with_temp(
         cmd.args(["-v", &format!("{}:{}:rw", disk_file, container_disk_path)]);
     }
 
+    // Mount the persistent state disk into the container
+    if let Some(state_disk_file) = &state_disk_file {
+        cmd.args([
+            "-v",
+            &format!("{state_disk_file}:{STATE_DISK_CONTAINER_PATH}:rw"),
+        ]);
+    }
+
     // Mount systemd units directory if specified
     if let Some(ref units_dir) = opts.systemd_units_dir {
         cmd.args(["-v", &format!("{}:/run/systemd-units:ro", units_dir)]);
     }
 
+    // Mount --copy-in sources; they are copied into the image before boot
+    for (index, copy_in) in parse_copy_in(&opts)?.iter().enumerate() {
+        let host_path = copy_in
+            .host_path
+            .canonicalize_utf8()
+            .with_context(|| format!("Failed to resolve {}", copy_in.host_path))?;
+        let container_path = copy_in.container_path(index);
+        cmd.args(["-v", &format!("{host_path}:{container_path}:ro")]);
+    }
+
+    // Mount kernel overrides from the host
+    for (path, name, is_dir) in [
+        (&opts.kernel, "vmlinuz", false),
+        (&opts.initrd, "initramfs", false),
+        (&opts.modules_dir, "modules", true),
+    ] {
+        let Some(path) = path else { continue };
+        let mut path = Utf8Path::new(path)
+            .canonicalize_utf8()
+            .with_context(|| format!("Failed to resolve {path}"))?;
+        if is_dir {
+            // Accept the root passed to `make modules_install INSTALL_MOD_PATH=...`
+            let modules = path.join("lib/modules");
+            if modules.is_dir() {
+                path = modules;
+            }
+            if !path.is_dir() {
+                return Err(eyre!("Modules directory is not a directory: {path}"));
+            }
+        } else if !path.is_file() {
+            return Err(eyre!("Not a regular file: {path}"));
+        }
+        cmd.args(["-v", &format!("{path}:{HOST_KERNEL_DIR}/{name}:ro")]);
+    }
+
+    if let Some(spec) = opts.execute_script.as_deref() {
+        let (path, _) = parse_execute_script(spec)?;
+        let path = Utf8Path::new(&path)
+            .canonicalize_utf8()
+            .with_context(|| format!("Opening --execute-script {path}"))?;
+        if !path.is_file() {
+            return Err(eyre!("--execute-script is not a regular file: {path}"));
+        }
+        cmd.args(["-v", &format!("{path}:{EXECUTE_SCRIPT_CONTAINER_PATH}:ro")]);
+    }
+    for assignment in &opts.execute_env {
+        validate_execute_env(assignment)?;
+    }
+
+    // The result is written by the container into a file created here
+    if let Some(path) = opts.execute_result.as_deref() {
+        File::create(path).with_context(|| format!("Creating {path}"))?;
+        let path = Utf8Path::new(path).canonicalize_utf8()?;
+        cmd.args(["-v", &format!("{path}:{EXECUTE_RESULT_PATH}")]);
+    }
+
+    // Likewise the boot report, which the container keeps updated
+    if let Some(path) = opts.boot_report.as_deref() {
+        File::create(path).with_context(|| format!("Creating {path}"))?;
+        let path = Utf8Path::new(path).canonicalize_utf8()?;
+        cmd.args(["-v", &format!("{path}:{BOOT_REPORT_PATH}")]);
+    }
+
+    // Credential files are read on the host; the resolved values are kept out of
+    // BCK_CONFIG so they don't show up in the podman command line
+    let credentials = opts.common.credentials.smbios_credentials()?;
+    if !credentials.is_empty() {
+        let credentials_path = format!("{td_path}/credentials");
+        std::fs::write(&credentials_path, credentials.join("\n"))?;
+        cmd.args(["-v", &format!("{credentials_path}:{CREDENTIALS_PATH}:ro")]);
+    }
+
     // Propagate this by default
     if let Some(log) = std::env::var("RUST_LOG").ok() {
         cmd.arg(format!("--env=RUST_LOG={log}"));
     }
 
+    // The boot report starts from here, covering the podman overhead too
+    cmd.arg(format!(
+        "--env={BOOT_START_ENV}={}",
+        chrono::Utc::now().timestamp_millis()
+    ));
+
     // Pass configuration as JSON via BCK_CONFIG environment variable
     let config = serde_json::to_string(&opts).unwrap();
     cmd.args(["-e", &format!("BCK_CONFIG={config}")]);
@@ -622,85 +1443,335 @@ pub(crate) fn process_disk_files(
     Ok(processed_disks)
 }
 
-/// Copy systemd units from /run/systemd-units/system/ to container image /etc/systemd/system/.
-/// Auto-enables .mount units in local-fs.target.wants/, preserves default.target.wants/ symlinks.
-fn inject_systemd_units() -> Result<()> {
+/// Ensure the state disk carries an ext4 filesystem with the overlay directories,
+/// formatting it only if it is blank so that a preserved disk keeps its contents.
+fn prepare_state_disk(disk_path: &Utf8Path) -> Result<()> {
+    let output = Command::new("blkid")
+        .args(["-p", "-o", "value", "-s", "TYPE", disk_path.as_str()])
+        .output()
+        .context("Failed to run blkid")?;
+    let fstype = String::from_utf8_lossy(&output.stdout);
+    match fstype.trim() {
+        "" => {}
+        "ext4" => {
+            debug!("Reusing existing state disk: {disk_path}");
+            return Ok(());
+        }
+        other => {
+            return Err(eyre!(
+                "State disk {disk_path} contains an unexpected filesystem: {other}"
+            ))
+        }
+    }
+
+    debug!("Formatting state disk: {disk_path}");
+    let populate = tempfile::tempdir()?;
+    for dir in ["etc/upper", "etc/work", "var/upper", "var/work"] {
+        std::fs::create_dir_all(populate.path().join(dir))?;
+    }
+    let populate: &Utf8Path = populate.path().try_into()?;
+    Command::new("mkfs.ext4")
+        .args(["-q", "-L", STATE_DISK_SERIAL, "-d", populate.as_str()])
+        .arg(disk_path.as_str())
+        .run()
+        .map_err(|e| eyre!("Formatting state disk: {e}"))?;
+    Ok(())
+}
+
+/// Kernel arguments mounting the state disk and the `/etc` and `/var` overlays
+/// from the initrd, so the real root starts out with the persisted state (e.g.
+/// units enabled in a previous run) and no early write bypasses the disk.
+/// The state disk is mounted on the initrd's /run, which is moved into the
+/// real root along with its mounts.
+fn state_disk_kernel_args() -> Vec<String> {
+    let mut args = vec![format!(
+        "rd.systemd.mount-extra=/dev/disk/by-id/virtio-{STATE_DISK_SERIAL}:{STATE_DISK_GUEST_MOUNT}:ext4"
+    )];
+    for dir in ["etc", "var"] {
+        // After systemd-volatile-root.service has replaced /sysroot with the
+        // overlay that systemd.volatile=overlay sets up
+        args.push(format!(
+            "rd.systemd.mount-extra=overlay:/sysroot/{dir}:overlay:\
+             lowerdir=/sysroot/{dir},\
+             upperdir={STATE_DISK_GUEST_MOUNT}/{dir}/upper,\
+             workdir={STATE_DISK_GUEST_MOUNT}/{dir}/work,\
+             x-systemd.requires-mounts-for={STATE_DISK_GUEST_MOUNT},\
+             x-systemd.after=initrd-root-fs.target"
+        ));
+    }
+    args
+}
+
+/// Generate guest mount units that overlay `/etc` and `/var` with upper
+/// directories on the state disk, and enable them in local-fs.target.
+///
+/// This is the fallback for images whose systemd lacks `systemd.mount-extra=`
+/// (see [`state_disk_kernel_args`]). PID 1 has already loaded units from the
+/// image's `/etc` by then, so units enabled in a previous run only take effect
+/// after a `systemctl daemon-reload`, and anything written to `/etc` or `/var`
+/// before local-fs.target is not kept on the disk.
+fn write_state_disk_units(target_unitdir: &Utf8Path) -> Result<()> {
     use std::fs;
 
-    debug!("Injecting systemd units from /run/systemd-units");
+    let state_unit = r#"run-bcvk\x2dstate.mount"#;
+    let state_mount = format!(
+        r#"[Unit]
+Description=bcvk ephemeral state disk
+DefaultDependencies=no
+Before=local-fs.target shutdown.target
+
+[Mount]
+What=/dev/disk/by-id/virtio-{STATE_DISK_SERIAL}
+Where={STATE_DISK_GUEST_MOUNT}
+Type=ext4
+"#
+    );
+    fs::write(target_unitdir.join(state_unit), state_mount)?;
 
-    let source_units = Utf8Path::new("/run/systemd-units/system");
-    if !source_units.exists() {
-        debug!("No systemd units to inject at {}", source_units);
+    let wants_dir = target_unitdir.join("local-fs.target.wants");
+    fs::create_dir_all(&wants_dir)?;
+    for dir in ["etc", "var"] {
+        let unit_name = format!("{dir}.mount");
+        let unit = format!(
+            r#"[Unit]
+Description=bcvk ephemeral state overlay for /{dir}
+DefaultDependencies=no
+RequiresMountsFor={STATE_DISK_GUEST_MOUNT}
+Before=local-fs.target shutdown.target
+
+[Mount]
+What=overlay
+Where=/{dir}
+Type=overlay
+Options=lowerdir=/{dir},upperdir={STATE_DISK_GUEST_MOUNT}/{dir}/upper,workdir={STATE_DISK_GUEST_MOUNT}/{dir}/work
+"#
+        );
+        fs::write(target_unitdir.join(&unit_name), unit)?;
+        std::os::unix::fs::symlink(format!("../{unit_name}"), wants_dir.join(&unit_name))?;
+        debug!("Generated state overlay unit: {unit_name}");
+    }
+    Ok(())
+}
+
+/// Extract `SELINUXTYPE=` from an `/etc/selinux/config` file.
+fn parse_selinux_type(config: &str) -> Option<&str> {
+    config
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("SELINUXTYPE="))
+        .map(|v| v.trim().trim_matches('"'))
+        .next_back()
+}
+
+/// Label /run/source-image from the image's own SELinux policy. The labels only go
+/// to [`qemu::SELINUX_XATTR_HOST`], which virtiofsd presents to the guest; see
+/// [`qemu::SELINUX_XATTRMAP`]. The host's labels are left alone.
+fn relabel_source_image() -> Result<()> {
+    let root = Utf8Path::new("/run/source-image");
+    let config_path = root.join("etc/selinux/config");
+    let config = std::fs::read_to_string(&config_path)
+        .with_context(|| format!("--selinux requires an image with {config_path}"))?;
+    let policy = parse_selinux_type(&config).unwrap_or("targeted");
+    let contexts_dir = root.join(format!("etc/selinux/{policy}/contexts/files"));
+    if !contexts_dir.join("file_contexts").exists() {
+        return Err(eyre!(
+            "SELinux policy '{policy}' not found in image: missing {contexts_dir}/file_contexts"
+        ));
+    }
+
+    debug!("Labeling {root} using {contexts_dir}");
+    let contexts = selinux::FileContexts::load(&contexts_dir)?;
+    selinux::label_tree(root.as_std_path(), &contexts, qemu::SELINUX_XATTR_HOST)
+        .with_context(|| format!("Labeling {root}"))
+}
+
+/// Copy the unit tree from /run/systemd-units/ to container image /etc/systemd/.
+/// Auto-enables system .mount units in local-fs.target.wants/; see [`crate::systemd::install_unit_tree`].
+fn inject_systemd_units() -> Result<()> {
+    let source = Utf8Path::new("/run/systemd-units");
+    if !source.exists() {
+        debug!("No systemd units to inject at {}", source);
         return Ok(());
     }
-    let target_units = "/run/source-image/etc/systemd/system";
+    debug!("Injecting systemd units from {source}");
+    crate::systemd::install_unit_tree(source, Utf8Path::new("/run/source-image"))
+        .context("Injecting --systemd-units")?;
+    debug!("Systemd unit injection complete");
+    Ok(())
+}
 
-    // Create target directories
-    fs::create_dir_all(target_units)?;
-    fs::create_dir_all(&format!("{}/default.target.wants", target_units))?;
-    fs::create_dir_all(&format!("{}/local-fs.target.wants", target_units))?;
+/// Whether the VM runs `--execute` commands or an `--execute-script`.
+fn has_execute(opts: &RunEphemeralOpts) -> bool {
+    !opts.common.execute.is_empty() || opts.execute_script.is_some()
+}
 
-    // Copy all .service and .mount files
-    for entry in fs::read_dir(source_units)? {
-        let entry = entry?;
-        let path = entry.path();
-        let extension = path.extension().map(|ext| ext.to_string_lossy());
-        if matches!(extension.as_deref(), Some("service") | Some("mount")) {
-            let filename = path.file_name().unwrap().to_string_lossy();
-            let target_path = format!("{}/{}", target_units, filename);
-            fs::copy(&path, &target_path)?;
-            debug!("Copied systemd unit: {}", filename);
-
-            // Create symlinks for mount units to enable them
-            if extension.as_deref() == Some("mount") {
-                let wants_dir = format!("{}/local-fs.target.wants", target_units);
-                let symlink_path = format!("{}/{}", wants_dir, filename);
-                let relative_target = format!("../{}", filename);
-                std::os::unix::fs::symlink(&relative_target, &symlink_path).ok();
-                debug!("Enabled mount unit: {}", filename);
-            }
-        }
+/// Split `--execute-script` into the script path and its arguments.
+fn parse_execute_script(spec: &str) -> Result<(String, Vec<String>)> {
+    let mut words = shlex::split(spec)
+        .ok_or_else(|| eyre!("Invalid quoting in --execute-script: {spec}"))?
+        .into_iter();
+    let path = words
+        .next()
+        .ok_or_else(|| eyre!("--execute-script requires a path"))?;
+    Ok((path, words.collect()))
+}
+
+/// Validate an `--execute-env` assignment.
+fn validate_execute_env(assignment: &str) -> Result<()> {
+    let name = assignment
+        .split_once('=')
+        .map(|(name, _)| name)
+        .ok_or_else(|| eyre!("Invalid --execute-env {assignment:?}, expected NAME=VALUE"))?;
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(eyre!("Invalid environment variable name: {name:?}"));
     }
+    Ok(())
+}
 
-    // Copy wants directory if it exists
-    let source_wants = "/run/systemd-units/system/default.target.wants";
-    let target_wants = &format!("{}/default.target.wants", target_units);
+/// Build the `ExecStart=` line for `--execute-script`, installing the script
+/// (mounted in the container at `script`) into the image at `image_root`.
+fn install_execute_script(spec: &str, script: &Utf8Path, image_root: &Utf8Path) -> Result<String> {
+    use std::os::unix::fs::PermissionsExt;
 
-    if Utf8Path::new(source_wants).exists() {
-        for entry in fs::read_dir(source_wants)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_symlink() || path.is_file() {
-                let filename = path.file_name().unwrap().to_string_lossy();
-                let target_path = format!("{}/{}", target_wants, filename);
-
-                if path.is_symlink() {
-                    let link_target = fs::read_link(&path)?;
-                    let _ = fs::remove_file(&target_path); // Remove if exists
-                    std::os::unix::fs::symlink(link_target, &target_path)?;
-                } else {
-                    fs::copy(&path, &target_path)?;
+    let (_, args) = parse_execute_script(spec)?;
+    let dest = image_root.join(EXECUTE_SCRIPT_GUEST_PATH.trim_start_matches('/'));
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(script, &dest).with_context(|| format!("Copying {script} to {dest}"))?;
+    std::fs::set_permissions(&dest, std::fs::Permissions::from_mode(0o755))?;
+
+    // Without a #! line exec fails, so run those with the shell like a user would
+    let mut shebang = [0u8; 2];
+    let has_shebang = File::open(script)
+        .and_then(|mut f| std::io::Read::read_exact(&mut f, &mut shebang))
+        .is_ok()
+        && &shebang == b"#!";
+    let mut argv = Vec::new();
+    if !has_shebang {
+        argv.push("/bin/sh".to_owned());
+    }
+    argv.push(EXECUTE_SCRIPT_GUEST_PATH.to_owned());
+    argv.extend(args);
+    let words: Vec<_> = argv.iter().map(|a| systemd::quote_exec_arg(a)).collect();
+    Ok(format!("ExecStart={}\n", words.join(" ")))
+}
+
+/// Install the `--artifacts-dir` collection script into the image at `image_root`.
+fn install_artifacts_collector(image_root: &Utf8Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dest = image_root.join(ARTIFACTS_COLLECTOR_PATH.trim_start_matches('/'));
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&dest, ARTIFACTS_COLLECTOR_SCRIPT).with_context(|| format!("Writing {dest}"))?;
+    std::fs::set_permissions(&dest, std::fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+/// Save the serial console log into `--artifacts-dir`, if requested for this outcome.
+/// Failures only warn, so they do not mask the result of the run.
+fn save_console_log(opts: &RunEphemeralOpts, failed: bool) {
+    if opts.artifacts_dir.is_none()
+        || opts.common.console
+        || !(failed || opts.artifacts == ArtifactsPolicy::Always)
+    {
+        return;
+    }
+    let r = parse_bind_mounts(opts).and_then(|mounts| {
+        let mount = mounts
+            .into_iter()
+            .find(|m| m.guest_path == ARTIFACTS_GUEST_DIR)
+            .ok_or_else(|| eyre!("Missing --artifacts-dir mount"))?;
+        let dest = format!("/run/host-mounts/{}/console.log", mount.name);
+        std::fs::copy(crate::console::CONSOLE_LOG_PATH, &dest)
+            .with_context(|| format!("Copying console log to {dest}"))?;
+        Ok(())
+    });
+    if let Err(e) = r {
+        tracing::warn!("Failed to save the console log: {e:#}");
+    }
+}
+
+/// Write the `--execute-result` JSON for the host.
+fn write_execute_result(result: &ExecuteResult) -> Result<()> {
+    let json = serde_json::to_string_pretty(result)?;
+    // Written in place, since this is a file bind mount
+    std::fs::write(EXECUTE_RESULT_PATH, json)
+        .with_context(|| format!("Writing {EXECUTE_RESULT_PATH}"))?;
+    Ok(())
+}
+
+/// Enforces `--timeout` for `--execute`: if QEMU is still running when the
+/// timeout expires, shows what the guest was doing and stops QEMU.
+struct ExecuteWatchdog {
+    done: std::sync::mpsc::Sender<()>,
+    thread: std::thread::JoinHandle<bool>,
+}
+
+impl ExecuteWatchdog {
+    fn start(timeout: Duration, qemu_pid: u32, console_log: Option<&'static str>) -> Self {
+        let (done, rx) = std::sync::mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            if rx.recv_timeout(timeout) != Err(std::sync::mpsc::RecvTimeoutError::Timeout) {
+                return false;
+            }
+            eprintln!("error: Execute timed out after {timeout:?}, stopping the VM");
+            dump_guest_state(console_log);
+            if let Some(pid) = rustix::process::Pid::from_raw(qemu_pid as i32) {
+                if let Err(e) = rustix::process::kill_process(pid, rustix::process::Signal::TERM) {
+                    eprintln!("Failed to stop QEMU: {e}");
                 }
-                debug!("Copied systemd wants link: {}", filename);
             }
-        }
+            true
+        });
+        Self { done, thread }
     }
 
-    debug!("Systemd unit injection complete");
-    Ok(())
+    /// Stop the watchdog once QEMU has exited, returning whether the timeout expired.
+    fn finish(self) -> bool {
+        drop(self.done);
+        self.thread.join().unwrap_or(true)
+    }
 }
 
-/// Parse exit code from systemd service status output
-fn parse_service_exit_code(status_content: &str) -> Result<i32> {
-    for line in status_content.lines() {
-        if let Some(codeval) = line.strip_prefix("ExecMainStatus=") {
-            let exit_code: i32 = codeval.parse().context("Parsing ExecMainStatus")?;
-            return Ok(exit_code);
+/// Print the end of the console log and the guest's pending jobs and units to stderr.
+fn dump_guest_state(console_log: Option<&str>) {
+    if let Some(path) = console_log {
+        match std::fs::read_to_string(path) {
+            Ok(console) => {
+                let lines: Vec<_> = console.lines().collect();
+                let tail = &lines[lines.len().saturating_sub(CONSOLE_TAIL_LINES)..];
+                eprintln!("--- Last {} lines of the console ---", tail.len());
+                for line in tail {
+                    eprintln!("{line}");
+                }
+            }
+            Err(e) => eprintln!("Failed to read console log: {e}"),
         }
     }
-    // If no exit code found, assume success
-    Ok(0)
+
+    // This needs the vsock exec agent; don't wait long for a guest that is stuck
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        // The artifacts collector is only installed with --artifacts-dir
+        let script = format!("echo '--- Jobs ---'; systemctl list-jobs --no-pager; \
+            echo '--- Running units ---'; systemctl list-units --state=activating,deactivating,failed --no-pager; \
+            echo '--- bootc-execute.service ---'; systemctl status --no-pager bootc-execute.service; \
+            if test -x {ARTIFACTS_COLLECTOR_PATH}; then {ARTIFACTS_COLLECTOR_PATH} always; fi");
+        let args = ["/bin/sh".to_owned(), "-c".to_owned(), script];
+        let _ = tx.send(crate::vsock_exec::exec_diagnostic_in_vm(&args));
+    });
+    match rx.recv_timeout(Duration::from_secs(30)) {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => eprintln!("Could not query guest units: {e}"),
+        Err(_) => eprintln!("Could not query guest units: no response from the guest"),
+    }
 }
 
 /// VM execution inside container: extracts kernel/initramfs, starts virtiofsd processes,
@@ -712,8 +1783,9 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
     debug!("Running QEMU implementation inside container");
 
     // Initialize status writer for supervisor monitoring
-    let status_writer = StatusWriter::new("/run/supervisor-status.json");
+    let status_writer = StatusWriter::new(SUPERVISOR_STATUS_PATH);
     status_writer.update_state(SupervisorState::WaitingForSystemd)?;
+    let boot_recorder = BootRecorder::from_env(BOOT_REPORT_PATH);
 
     // Check systemd version from the container image
     let systemd_version = {
@@ -725,30 +1797,39 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
     };
     debug!("Container image systemd version: {systemd_version:?}");
 
-    // Find kernel and initramfs from the container image (not the host)
-    let modules_dir = Utf8Path::new("/run/source-image/usr/lib/modules");
-    let mut vmlinuz_path = None;
-    let mut initramfs_path = None;
-
-    for entry in fs::read_dir(modules_dir)? {
-        let entry = entry?;
-        let kernel_dir = entry.path();
-        if kernel_dir.is_dir() {
-            let vmlinuz = kernel_dir.join("vmlinuz");
-            let initramfs = kernel_dir.join("initramfs.img");
-            if vmlinuz.exists() && initramfs.exists() {
-                debug!("Found kernel at: {:?}", vmlinuz);
-                vmlinuz_path = Some(vmlinuz);
-                initramfs_path = Some(initramfs);
-                break;
+    // Find kernel and initramfs from the container image (not the host),
+    // replacing them with ones from the host if given. The image is not
+    // searched at all when the host provides both.
+    let host_kernel_dir = Utf8Path::new(HOST_KERNEL_DIR);
+    let (boot_image, kernel_version) = if opts.kernel.is_some() && opts.initrd.is_some() {
+        let image = crate::kernel::KernelImage::Split {
+            vmlinuz: host_kernel_dir.join("vmlinuz"),
+            initramfs: host_kernel_dir.join("initramfs"),
+        };
+        (image, None)
+    } else {
+        let kernel = crate::kernel::find_kernel(
+            Utf8Path::new("/run/source-image"),
+            opts.kernel_version.as_deref(),
+        )?;
+        debug!("Booting kernel {}: {:?}", kernel.version, kernel.image);
+        match (&opts.kernel, kernel.image) {
+            (None, image) => (image, Some(kernel.version)),
+            (Some(_), crate::kernel::KernelImage::Split { initramfs, .. }) => {
+                let image = crate::kernel::KernelImage::Split {
+                    vmlinuz: host_kernel_dir.join("vmlinuz"),
+                    initramfs,
+                };
+                (image, None)
+            }
+            (Some(_), crate::kernel::KernelImage::Uki(_)) => {
+                return Err(eyre!(
+                    "The image only ships a UKI; --kernel requires --initrd for it"
+                ));
             }
         }
-    }
-
-    let vmlinuz_path = vmlinuz_path
-        .ok_or_else(|| eyre!("No kernel found in /run/source-image/usr/lib/modules"))?;
-    let initramfs_path = initramfs_path
-        .ok_or_else(|| eyre!("No initramfs found in /run/source-image/usr/lib/modules"))?;
+    };
+    debug!("Boot image: {boot_image:?}");
 
     // Verify KVM access
     if !Utf8Path::new("/dev/kvm").exists() || !fs::File::open("/dev/kvm").is_ok() {
@@ -759,40 +1840,41 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
     fs::create_dir_all("/run/qemu")?;
     let kernel_mount = "/run/qemu/kernel";
     let initramfs_mount = "/run/qemu/initramfs";
-    fs::File::create(&kernel_mount)?;
-    fs::File::create(&initramfs_mount)?;
-
-    // Bind mount kernel and initramfs
-    let mut mount_cmd = Command::new("mount");
-    mount_cmd.args([
-        "--bind",
-        "-o",
-        "ro",
-        vmlinuz_path.to_str().unwrap(),
-        &kernel_mount,
-    ]);
-    let status = mount_cmd.status().context("Failed to bind mount kernel")?;
-    if !status.success() {
-        return Err(eyre!("Failed to bind mount kernel"));
-    }
 
-    let mut mount_cmd = Command::new("mount");
-    mount_cmd.args([
-        "--bind",
-        "-o",
-        "ro",
-        initramfs_path.to_str().unwrap(),
-        &initramfs_mount,
-    ]);
-    let status = mount_cmd
-        .status()
-        .context("Failed to bind mount initramfs")?;
-    if !status.success() {
-        return Err(eyre!("Failed to bind mount initramfs"));
+    match &boot_image {
+        crate::kernel::KernelImage::Split { vmlinuz, initramfs } => {
+            fs::File::create(&kernel_mount)?;
+            fs::File::create(&initramfs_mount)?;
+
+            // Bind mount kernel and initramfs
+            let mut mount_cmd = Command::new("mount");
+            mount_cmd.args(["--bind", "-o", "ro", vmlinuz.as_str(), &kernel_mount]);
+            let status = mount_cmd.status().context("Failed to bind mount kernel")?;
+            if !status.success() {
+                return Err(eyre!("Failed to bind mount kernel"));
+            }
+
+            let mut mount_cmd = Command::new("mount");
+            mount_cmd.args(["--bind", "-o", "ro", initramfs.as_str(), &initramfs_mount]);
+            let status = mount_cmd
+                .status()
+                .context("Failed to bind mount initramfs")?;
+            if !status.success() {
+                return Err(eyre!("Failed to bind mount initramfs"));
+            }
+        }
+        crate::kernel::KernelImage::Uki(uki) => {
+            crate::kernel::extract_uki(
+                uki,
+                Utf8Path::new(kernel_mount),
+                Utf8Path::new(initramfs_mount),
+            )?;
+        }
     }
 
     // Process host mounts and prepare virtiofsd instances for each using async manager
     let mut additional_mounts = Vec::new();
+    let mut mount_infos = Vec::new();
 
     debug!(
         "Checking for host mounts directory: /run/host-mounts exists = {}",
@@ -806,6 +1888,7 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
     let target_unitdir = "/run/source-image/etc/systemd/system";
 
     if Utf8Path::new("/run/host-mounts").exists() {
+        let bind_mounts = parse_bind_mounts(&opts)?;
         for entry in fs::read_dir("/run/host-mounts")? {
             let entry = entry?;
             let mount_name = entry.file_name();
@@ -823,26 +1906,30 @@ pub(crate) async fn run_impl(opts: RunEphemeralOpts) -> Result<()> {
                 mount_name_str, mode
             );
 
-            // Create virtiofs socket path and tag
-            let socket_path = format!("/run/inner-shared/virtiofs-{}.sock", mount_name_str);
+            // Mounts added implicitly (e.g. --bind-storage-ro) use the defaults
+            let mount = match bind_mounts.iter().find(|m| m.name == mount_name_str) {
+                Some(mount) => mount.clone(),
+                None => BindMount::parse(&format!("{mount_path}:{mount_name_str}"), is_readonly)?,
+            };
             let tag = format!("mount_{}", mount_name_str);
 
             // Store virtiofsd config to be spawned later by QEMU
-            let virtiofsd_config = qemu::VirtiofsConfig {
-                socket_path: socket_path.clone(),
-                shared_dir: source_path.to_string_lossy().to_string(),
-                cache_mode: "always".to_string(),
-                sandbox: "none".to_string(),
-                debug: false,
-            };
+            let virtiofsd_config = mount.virtiofs_config(&source_path.to_string_lossy());
+            qemu::validate_virtiofsd_config(&virtiofsd_config)
+                .with_context(|| format!("Invalid options for mount {mount_name_str}"))?;
             additional_mounts.push((virtiofsd_config, tag.clone()));
+            mount_infos.push(MountInfo {
+                source: mount.host_path.clone(),
+                target: mount.guest_path.clone(),
+                readonly: mount.readonly,
+            });
 
             // Create individual .mount unit for this virtiofs mount
-            let mount_point = format!("/run/virtiofs-mnt-{}", mount_name_str);
+            let mount_point = mount.guest_path.as_str();
 
             // Use systemd-escape to properly escape the mount path
             let escaped_path = Command::new("systemd-escape")
-                .args(["-p", &mount_point])
+                .args(["-p", mount_point])
                 .output()
                 .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
                 .unwrap_or_else(|_| {
@@ -897,55 +1984,115 @@ WantedBy=local-fs.target
         }
     }
 
+    // Mount host kernel modules over the image's
+    if opts.modules_dir.is_some() {
+        let tag = "mount_hostmodules".to_string();
+        additional_mounts.push((
+            qemu::VirtiofsConfig {
+                socket_path: "/run/inner-shared/virtiofs-hostmodules.sock".to_string(),
+                shared_dir: format!("{HOST_KERNEL_DIR}/modules"),
+                ..Default::default()
+            },
+            tag.clone(),
+        ));
+
+        // Modules must be in place before udev and modules-load.d run
+        let mount_unit_name = "usr-lib-modules.mount";
+        let mount_unit_content = format!(
+            r#"[Unit]
+Description=Kernel modules from the host
+DefaultDependencies=no
+After=systemd-remount-fs.service
+Before=local-fs.target systemd-udevd.service systemd-modules-load.service shutdown.target
+
+[Mount]
+What={tag}
+Where=/usr/lib/modules
+Type=virtiofs
+Options=ro
+"#
+        );
+        fs::write(
+            format!("{target_unitdir}/{mount_unit_name}"),
+            mount_unit_content,
+        )?;
+        let wants_dir = format!("{target_unitdir}/local-fs.target.wants");
+        fs::create_dir_all(&wants_dir)?;
+        std::os::unix::fs::symlink(
+            format!("../{mount_unit_name}"),
+            format!("{wants_dir}/{mount_unit_name}"),
+        )?;
+        debug!("Generated mount unit: {mount_unit_name}");
+    }
+
     // Handle --execute: pipes will be created when adding to qemu_config later
     // No need to create files anymore as we're using pipes
 
     let default_wantsdir = format!("{target_unitdir}/default.target.wants");
     fs::create_dir_all(&default_wantsdir)?;
 
-    match opts.common.execute.as_slice() {
-        [] => {}
-        elts => {
-            let mut service_content = format!(
-                r#"[Unit]
+    if has_execute(&opts) {
+        let mut service_content = format!(
+            r#"[Unit]
 Description=Execute Script Service
-Requires=dev-virtio\\x2dports-execute.device
+Requires=dev-virtio\\x2dports-execute.device dev-virtio\\x2dports-executeerr.device
 
 [Service]
 Type=oneshot
 RemainAfterExit=yes
 StandardOutput=file:/dev/virtio-ports/execute
-StandardError=inherit
+StandardError=file:/dev/virtio-ports/executeerr
 "#
-            );
-            for elt in elts {
-                service_content.push_str(&format!("ExecStart={elt}\n"));
-            }
+        );
+        for assignment in &opts.execute_env {
+            validate_execute_env(assignment)?;
+            let assignment = systemd::quote_environment(assignment);
+            service_content.push_str(&format!("Environment={assignment}\n"));
+        }
+        for elt in &opts.common.execute {
+            service_content.push_str(&format!("ExecStart={elt}\n"));
+        }
+        if let Some(spec) = opts.execute_script.as_deref() {
+            service_content.push_str(&install_execute_script(
+                spec,
+                Utf8Path::new(EXECUTE_SCRIPT_CONTAINER_PATH),
+                Utf8Path::new("/run/source-image"),
+            )?);
+        }
 
-            let service_finish = format!(
-                r#"[Unit]
+        // Collect artifacts before reporting the status, since that powers off
+        let (mut finish_unit_extra, mut finish_service_extra) = (String::new(), String::new());
+        if opts.artifacts_dir.is_some() {
+            install_artifacts_collector(Utf8Path::new("/run/source-image"))?;
+            finish_unit_extra = format!("RequiresMountsFor={ARTIFACTS_GUEST_DIR}\n");
+            finish_service_extra = format!(
+                "ExecStart=-{ARTIFACTS_COLLECTOR_PATH} {}\n",
+                opts.artifacts.as_str()
+            );
+        }
+        let service_finish = format!(
+            r#"[Unit]
 Description=Execute Script Service Completion
 After=bootc-execute.service
 Requires=dev-virtio\\x2dports-executestatus.device
-
+{finish_unit_extra}
 [Service]
 Type=oneshot
-ExecStart=systemctl show bootc-execute
+{finish_service_extra}ExecStart=systemctl show bootc-execute
 ExecStart=systemctl poweroff
 StandardOutput=file:/dev/virtio-ports/executestatus
 "#
-            );
+        );
 
-            let service_path = format!("{target_unitdir}/bootc-execute.service");
-            fs::write(service_path, service_content)?;
-            let service_path = format!("{target_unitdir}/bootc-execute-finish.service");
-            fs::write(service_path, service_finish)?;
+        let service_path = format!("{target_unitdir}/bootc-execute.service");
+        fs::write(service_path, service_content)?;
+        let service_path = format!("{target_unitdir}/bootc-execute-finish.service");
+        fs::write(service_path, service_finish)?;
 
-            for svc in ["bootc-execute.service", "bootc-execute-finish.service"] {
-                let wants_link = format!("{default_wantsdir}/{svc}");
-                debug!("Creating execute service symlink: {}", &wants_link);
-                std::os::unix::fs::symlink(format!("../{svc}"), wants_link)?;
-            }
+        for svc in ["bootc-execute.service", "bootc-execute-finish.service"] {
+            let wants_link = format!("{default_wantsdir}/{svc}");
+            debug!("Creating execute service symlink: {}", &wants_link);
+            std::os::unix::fs::symlink(format!("../{svc}"), wants_link)?;
         }
     }
 
@@ -953,9 +2100,21 @@ StandardOutput=file:/dev/virtio-ports/executestatus
     // Also inject if we created mount units that need to be copied
     inject_systemd_units()?;
 
+    // Add user files last, so they can override anything generated above
+    for (index, copy_in) in parse_copy_in(&opts)?.iter().enumerate() {
+        copy_in.apply(
+            &copy_in.container_path(index),
+            Utf8Path::new("/run/source-image"),
+        )?;
+    }
+
     // Prepare main virtiofsd config for the source image (will be spawned by QEMU)
-    let mut main_virtiofsd_config = qemu::VirtiofsConfig::default();
-    main_virtiofsd_config.debug = std::env::var("DEBUG_MODE").is_ok();
+    let main_virtiofsd_config = qemu::VirtiofsConfig {
+        debug: std::env::var("DEBUG_MODE").is_ok(),
+        // The guest needs to see the labels we apply to the image before boot
+        xattr: opts.selinux.is_some(),
+        ..Default::default()
+    };
 
     std::fs::create_dir_all(CONTAINER_STATEDIR)?;
 
@@ -972,32 +2131,85 @@ StandardOutput=file:/dev/virtio-ports/executestatus
     let vsock_force_disabled = std::env::var("BCVK_DEBUG").as_deref() == Ok("disable-vsock");
     let vsock_enabled = !vsock_force_disabled && qemu_config.enable_vsock().is_ok();
 
-    // Handle SSH key generation and credential injection
-    if opts.common.ssh_keygen {
+    // For `bcvk ephemeral stop` and the other lifecycle commands
+    qemu_config.set_qmp_socket(qemu::QMP_SOCKET_PATH);
+
+    // Forward the guest journal for `bcvk ephemeral logs`
+    crate::ephemeral_logs::write_guest_forwarder(Utf8Path::new(target_unitdir))?;
+    let journal_pipefd: File = qemu_config
+        .add_virtio_serial_pipe(crate::ephemeral_logs::JOURNAL_PORT)?
+        .into();
+    crate::ephemeral_logs::record_journal(journal_pipefd)?;
+
+    // Install the agent backing `bcvk ephemeral exec`
+    let exec_token = if vsock_enabled {
+        let token = crate::vsock_exec::generate_token();
+        crate::vsock_exec::write_guest_agent(
+            Utf8Path::new("/run/source-image"),
+            Utf8Path::new(target_unitdir),
+            &token,
+        )?;
+        Some(token)
+    } else {
+        None
+    };
+
+    // Handle SSH key generation, user creation and credential injection
+    let pubkey = if opts.common.ssh_keygen {
         let key_pair = crate::ssh::generate_default_keypair()?;
-        // Create credential and add to kernel args
-        let pubkey = std::fs::read_to_string(key_pair.public_key_path.as_path())?;
-        let credential = crate::sshcred::smbios_cred_for_root_ssh(&pubkey)?;
+        Some(std::fs::read_to_string(key_pair.public_key_path.as_path())?)
+    } else {
+        None
+    };
+    let ssh_user = opts
+        .common
+        .user
+        .as_deref()
+        .map(crate::sshcred::SshUser::parse)
+        .transpose()?;
+    for credential in crate::sshcred::smbios_creds_for_ssh(pubkey.as_deref(), ssh_user.as_ref())? {
         qemu_config.add_smbios_credential(credential);
     }
+    if Utf8Path::new(CREDENTIALS_PATH).exists() {
+        let credentials = fs::read_to_string(CREDENTIALS_PATH)
+            .with_context(|| format!("Reading {CREDENTIALS_PATH}"))?;
+        for credential in credentials.lines() {
+            qemu_config.add_smbios_credential(credential.to_owned());
+        }
+    }
     // Build kernel command line
     let mut kernel_cmdline = vec![
         "rootfstype=virtiofs".to_string(),
         "root=rootfs".to_string(),
-        "selinux=0".to_string(),
         "systemd.volatile=overlay".to_string(),
     ];
-
-    if opts.common.console {
-        kernel_cmdline.push("console=ttyS0".to_string());
+    match opts.selinux {
+        None => kernel_cmdline.push("selinux=0".to_string()),
+        Some(SelinuxMode::Permissive) => kernel_cmdline.push("enforcing=0".to_string()),
+        Some(SelinuxMode::Enforcing) => kernel_cmdline.push("enforcing=1".to_string()),
     }
 
+    let timeout = opts
+        .timeout
+        .as_deref()
+        .map(utils::parse_duration)
+        .transpose()?;
+    // Without --console, the serial console is logged and can be attached
+    // to with `bcvk ephemeral console`
+    kernel_cmdline.push("console=ttyS0".to_string());
+    let console_mux = if opts.common.console {
+        None
+    } else {
+        qemu_config.set_serial_socket(crate::console::SERIAL_SOCKET_PATH);
+        Some(crate::console::ConsoleMux::bind(status_writer.clone())?)
+    };
+
     kernel_cmdline.extend(opts.common.kernel_args.clone());
 
     // TODO allocate unlinked unnamed file and pass via fd
     let mut tmp_swapfile = None;
-    if let Some(size) = opts.add_swap {
-        let size = utils::parse_size(&size)?;
+    if let Some(size) = opts.add_swap.as_deref() {
+        let size = utils::parse_size(size)?;
         debug!("Allocating swap: {size}");
         let mut tmpf = tempfile::NamedTempFile::new_in("/var/tmp")?;
         tmpf.as_file_mut()
@@ -1036,6 +2248,41 @@ Options=
         tmp_swapfile = Some(tmpf);
     }
 
+    // Back the writable /etc and /var with a disk instead of guest RAM
+    let mut tmp_state_disk = None;
+    if let Some(spec) = opts.state_disk.as_deref() {
+        let disk_path = match StateDisk::parse(spec)? {
+            StateDisk::Size(size) => {
+                debug!("Allocating state disk: {size}");
+                let tmpf = tempfile::NamedTempFile::new_in("/var/tmp")?;
+                tmpf.as_file()
+                    .set_len(size)
+                    .context("Allocating state disk tempfile")?;
+                let path: Utf8PathBuf = tmpf.path().to_owned().try_into()?;
+                tmp_state_disk = Some(tmpf);
+                path
+            }
+            StateDisk::File(_) => Utf8PathBuf::from(STATE_DISK_CONTAINER_PATH),
+        };
+        prepare_state_disk(&disk_path)?;
+        qemu_config.add_virtio_blk_device_with_format(
+            disk_path.into(),
+            STATE_DISK_SERIAL.into(),
+            crate::to_disk::Format::Raw,
+        );
+        if systemd_version
+            .as_ref()
+            .is_some_and(systemd::SystemdVersion::has_mount_extra)
+        {
+            kernel_cmdline.extend(state_disk_kernel_args());
+        } else {
+            tracing::warn!(
+                "The image's systemd does not support systemd.mount-extra=; the state disk is only mounted at local-fs.target"
+            );
+            write_state_disk_units(Utf8Path::new(target_unitdir))?;
+        }
+    }
+
     // Parse disk files from environment variable
     let mut virtio_blk_devices = Vec::new();
     if let Ok(disk_env) = std::env::var("BOOTC_DISK_FILES") {
@@ -1064,6 +2311,18 @@ Options=
         .set_kernel_cmdline(kernel_cmdline)
         .set_console(opts.common.console);
 
+    let published_ports = PublishedPort::parse_all(&opts.publish, opts.common.ssh_keygen)?;
+    for port in &published_ports {
+        // The container already maps the host port to the same-numbered container port
+        qemu_config.add_port_forward(port.protocol.as_str(), port.guest_port, port.guest_port);
+        debug!(
+            "Enabled port forwarding: container port {} -> guest port {}/{}",
+            port.guest_port,
+            port.guest_port,
+            port.protocol.as_str()
+        );
+    }
+
     if opts.common.ssh_keygen {
         qemu_config.enable_ssh_access(None); // Use default port 2222
         debug!("Enabled SSH port forwarding: host port 2222 -> guest port 22");
@@ -1081,10 +2340,11 @@ Options=
         qemu_config.add_virtiofs(virtiofs_config, &tag);
     }
 
-    let exec_pipes = if !opts.common.execute.is_empty() {
+    let exec_pipes = if has_execute(&opts) {
         let execute_pipefd: File = qemu_config.add_virtio_serial_pipe("execute")?.into();
+        let stderr_pipefd: File = qemu_config.add_virtio_serial_pipe("executeerr")?.into();
         let status_pipefd: File = qemu_config.add_virtio_serial_pipe("executestatus")?.into();
-        Some((execute_pipefd, status_pipefd))
+        Some((execute_pipefd, stderr_pipefd, status_pipefd))
     } else {
         None
     };
@@ -1098,7 +2358,10 @@ Options=
         );
     }
 
-    let status_writer_clone = StatusWriter::new("/run/supervisor-status.json");
+    // Label the image only now that all generated units and mount points are in place
+    if opts.selinux.is_some() {
+        relabel_source_image()?;
+    }
 
     // Only enable systemd notification debugging if the systemd version supports it
     // and the host has vsock enabled
@@ -1113,7 +2376,8 @@ Options=
         // Run this in the background
         let _ = tokio::task::spawn(boot_progress::monitor_boot_progress(
             File::from(piper),
-            status_writer_clone,
+            status_writer.clone(),
+            boot_recorder.clone(),
         ));
     } else {
         debug!("systemd version does not support vmm.notify_socket",);
@@ -1126,12 +2390,45 @@ Options=
 
     debug!("Starting QEMU with systemd debugging enabled");
 
+    if let Some(mux) = console_mux {
+        tokio::spawn(async move {
+            if let Err(e) = mux.run().await {
+                tracing::warn!("Serial console relay failed: {e:#}");
+            }
+        });
+    }
+
     // Spawn QEMU with all virtiofsd processes handled internally
     let mut qemu = crate::qemu::RunningQemu::spawn(qemu_config).await?;
+    if let Some(at) = qemu.virtiofsd_ready {
+        boot_recorder.record_at(EventSource::Host, "virtiofsd-ready", at.into());
+    }
+    boot_recorder.record(EventSource::Host, "qemu-spawn");
+    status_writer.set_vm_info(VmInfo {
+        qemu_pid: qemu.qemu_process.id(),
+        vsock_cid: qemu.vsock_cid,
+        memory_mb: opts.common.memory_mb()?,
+        vcpus: opts.common.vcpus(),
+        published_ports: published_ports
+            .iter()
+            .map(PublishedPort::podman_arg)
+            .collect(),
+        mounts: mount_infos,
+        kernel_version,
+        started: chrono::Utc::now(),
+    })?;
+
+    if let (Some(cid), Some(token)) = (qemu.vsock_cid, exec_token) {
+        crate::vsock_exec::ExecAgentInfo { cid, token }.write()?;
+    }
 
     // Handle execute command output streaming if needed
-    if let Some((exec_pipefd, status_pipefd)) = exec_pipes {
+    if let Some((exec_pipefd, stderr_pipefd, status_pipefd)) = exec_pipes {
         tracing::debug!("Starting execute output streaming with pipes");
+        let watchdog = timeout.map(|timeout| {
+            let console_log = (!opts.common.console).then_some(crate::console::CONSOLE_LOG_PATH);
+            ExecuteWatchdog::start(timeout, qemu.qemu_process.id(), console_log)
+        });
         let output_copier = async move {
             let fd = tokio::fs::File::from(exec_pipefd);
             let mut bufr = tokio::io::BufReader::new(fd);
@@ -1140,40 +2437,379 @@ Options=
             tracing::debug!("Output copy result: {:?}", result);
             result
         };
+        let stderr_copier = async move {
+            let fd = tokio::fs::File::from(stderr_pipefd);
+            let mut bufr = tokio::io::BufReader::new(fd);
+            let mut stderr = tokio::io::stderr();
+            tokio::io::copy(&mut bufr, &mut stderr).await
+        };
         let mut status_reader = tokio::io::BufReader::new(tokio::fs::File::from(status_pipefd));
         let mut status = String::new();
         let status_reader = status_reader.read_to_string(&mut status);
 
         // And wait for all tasks
-        let (qemu, output_copier, execstatus) =
-            tokio::join!(qemu.wait(), output_copier, status_reader);
+        let (qemu, output_copier, stderr_copier, execstatus) =
+            tokio::join!(qemu.wait(), output_copier, stderr_copier, status_reader);
+        boot_recorder.record(EventSource::Host, "qemu-exit");
         // Do check for errors from reading from the execstatus pipe
         let _ = execstatus.context("Reading execstatus")?;
 
         // Discard errors from qemu and the output copier
         tracing::debug!("qemu exit status: {qemu:?}");
         tracing::debug!("output copy: {output_copier:?}");
+        tracing::debug!("stderr copy: {stderr_copier:?}");
 
-        // Parse exit code from systemd service status
-        let exit_code = parse_service_exit_code(&status)?;
-        if exit_code != 0 {
-            return Err(eyre!(
-                "Execute command failed with exit code: {}",
-                exit_code
-            ));
+        if watchdog.is_some_and(ExecuteWatchdog::finish) {
+            if opts.execute_result.is_some() {
+                write_execute_result(&ExecuteResult::timed_out())?;
+            }
+            save_console_log(&opts, true);
+            drop(tmp_swapfile);
+            drop(tmp_state_disk);
+            status_writer.finish()?;
+            std::process::exit(TIMEOUT_EXIT_CODE);
+        }
+
+        // Parse the result from systemd service status
+        let result = ExecuteResult::parse(&status);
+        save_console_log(&opts, !result.as_ref().is_ok_and(ExecuteResult::success));
+        let result = result?;
+        if opts.execute_result.is_some() {
+            write_execute_result(&result)?;
+        }
+        if !result.success() {
+            return Err(eyre!("{}", result.failure_message()));
         }
     } else {
         // Wait for QEMU to complete
         let exit_status = qemu.wait().await?;
+        boot_recorder.record(EventSource::Host, "qemu-exit");
         if !exit_status.success() {
+            let failure = BootFailure::new(
+                BootFailureKind::QemuExited,
+                format!("QEMU exited with non-zero status: {exit_status}"),
+            );
+            status_writer.fail(failure, false)?;
             return Err(eyre!("QEMU exited with non-zero status: {}", exit_status));
         }
     }
 
     drop(tmp_swapfile);
+    drop(tmp_state_disk);
 
     debug!("QEMU completed successfully");
     status_writer.finish()?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_in() -> Result<()> {
+        let c = CopyIn::parse("./app.conf:/etc/app/app.conf", false)?;
+        assert_eq!(c.host_path, "./app.conf");
+        assert_eq!(c.guest_path, "/etc/app/app.conf");
+        assert_eq!(c.container_path(1), "/run/copy-in/path1/app.conf");
+        let c = CopyIn::parse("/tmp/overlay.tar.gz", true)?;
+        assert_eq!(c.guest_path, "/");
+        assert_eq!(c.container_path(0), "/run/copy-in/tar0/overlay.tar.gz");
+        assert!(CopyIn::parse("app.conf", false).is_err());
+        assert!(CopyIn::parse("app.conf:etc/app.conf", false).is_err());
+        assert!(CopyIn::parse("app.conf:/etc/../root", false).is_err());
+        assert!(CopyIn::parse("..:/etc", false).is_err());
+
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+        let td = tempfile::tempdir()?;
+        let td = Utf8Path::from_path(td.path()).unwrap();
+        let root = td.join("root");
+        fs::create_dir_all(root.join("usr/local/bin"))?;
+        let tool = td.join("tool");
+        fs::write(&tool, "#!/bin/sh\n")?;
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o751))?;
+
+        // Copying into an existing directory keeps the name and mode
+        CopyIn::parse(&format!("{tool}:/usr/local/bin"), false)?.apply(&tool, &root)?;
+        let copied = root.join("usr/local/bin/tool");
+        assert_eq!(fs::metadata(&copied)?.permissions().mode() & 0o7777, 0o751);
+        // Parent directories are created
+        CopyIn::parse(&format!("{tool}:/etc/app/renamed"), false)?.apply(&tool, &root)?;
+        assert!(root.join("etc/app/renamed").is_file());
+        // Symlinks are resolved inside the image, creating dangling targets
+        std::os::unix::fs::symlink("/var/opt", root.join("opt"))?;
+        std::os::unix::fs::symlink("../var/srv", root.join("srv"))?;
+        CopyIn::parse(&format!("{tool}:/srv/app/"), false)?.apply(&tool, &root)?;
+        assert!(root.join("var/srv/app/tool").is_file());
+
+        let tarball = td.join("files.tar");
+        Command::new("tar")
+            .arg("-cf")
+            .arg(&tarball)
+            .arg("-C")
+            .arg(td)
+            .arg("tool")
+            .run()
+            .map_err(|e| eyre!("{e}"))?;
+        CopyIn::parse(&format!("{tarball}:/opt"), true)?.apply(&tarball, &root)?;
+        assert_eq!(
+            fs::metadata(root.join("var/opt/tool"))?
+                .permissions()
+                .mode()
+                & 0o7777,
+            0o751
+        );
+
+        // Symlinks in the image cannot redirect extraction outside of it
+        let outside = td.join("outside");
+        fs::create_dir(&outside)?;
+        std::os::unix::fs::symlink(&outside, root.join("escape"))?;
+        let staging = td.join("staging");
+        fs::create_dir_all(staging.join("escape/nested"))?;
+        fs::write(staging.join("escape/pwned"), "x")?;
+        fs::write(staging.join("escape/nested/pwned"), "x")?;
+        let tarball = td.join("escape.tar");
+        Command::new("tar")
+            .arg("-cf")
+            .arg(&tarball)
+            .arg("-C")
+            .arg(&staging)
+            .arg("escape")
+            .run()
+            .map_err(|e| eyre!("{e}"))?;
+        CopyIn::parse(&format!("{tarball}:/"), true)?.apply(&tarball, &root)?;
+        assert_eq!(fs::read_dir(&outside)?.count(), 0);
+        let confined = root.join(outside.strip_prefix("/").unwrap());
+        assert!(confined.join("pwned").is_file());
+        assert!(confined.join("nested/pwned").is_file());
+
+        // The same holds for directories copied with --copy-in
+        let dir = td.join("copy/escape");
+        fs::create_dir_all(dir.join("nested"))?;
+        fs::write(dir.join("copied"), "x")?;
+        fs::write(dir.join("nested/copied"), "x")?;
+        CopyIn::parse(&format!("{dir}:/"), false)?.apply(&dir, &root)?;
+        assert_eq!(fs::read_dir(&outside)?.count(), 0);
+        assert!(confined.join("copied").is_file());
+        assert!(confined.join("nested/copied").is_file());
+        // A missing destination becomes the copy, like with cp -a
+        CopyIn::parse(&format!("{dir}:/etc/copied"), false)?.apply(&dir, &root)?;
+        assert!(root.join("etc/copied/nested/copied").is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn test_artifacts_opts() -> Result<()> {
+        let opts = RunEphemeralOpts::try_parse_from([
+            "run",
+            "--execute",
+            "false",
+            "--artifacts-dir",
+            "out",
+            "image",
+        ])?;
+        assert_eq!(opts.artifacts, ArtifactsPolicy::OnFailure);
+        let opts = RunEphemeralOpts::try_parse_from([
+            "run",
+            "--execute",
+            "true",
+            "--artifacts-dir",
+            "out",
+            "--artifacts",
+            "always",
+            "image",
+        ])?;
+        assert_eq!(opts.artifacts, ArtifactsPolicy::Always);
+        // Artifacts are only collected for --execute runs
+        assert!(
+            RunEphemeralOpts::try_parse_from(["run", "--artifacts-dir", "out", "image"]).is_err()
+        );
+        assert!(RunEphemeralOpts::try_parse_from([
+            "run",
+            "--execute",
+            "true",
+            "--artifacts",
+            "always",
+            "image"
+        ])
+        .is_err());
+
+        let td = tempfile::tempdir()?;
+        let root = Utf8Path::from_path(td.path()).unwrap();
+        install_artifacts_collector(root)?;
+        let collector =
+            std::fs::read_to_string(root.join(ARTIFACTS_COLLECTOR_PATH.trim_start_matches('/')))?;
+        assert!(collector.contains(ARTIFACTS_GUEST_DIR));
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_script() -> Result<()> {
+        let (path, args) = parse_execute_script("./t.sh --name 'a b' \"$HOME\"")?;
+        assert_eq!(path, "./t.sh");
+        assert_eq!(args, ["--name", "a b", "$HOME"]);
+        assert!(parse_execute_script("").is_err());
+        assert!(parse_execute_script("t.sh 'unterminated").is_err());
+
+        validate_execute_env("FOO_1=x y")?;
+        validate_execute_env("EMPTY=")?;
+        for invalid in ["FOO", "1FOO=x", "=x", "FO-O=x"] {
+            assert!(validate_execute_env(invalid).is_err(), "{invalid}");
+        }
+
+        // The other execute options accept --execute-script in place of --execute
+        let opts = RunEphemeralOpts::try_parse_from([
+            "run",
+            "--execute-script",
+            "t.sh",
+            "--timeout",
+            "5m",
+            "--execute-env",
+            "A=1",
+            "image",
+        ])?;
+        assert_eq!(opts.execute_script.as_deref(), Some("t.sh"));
+        assert!(RunEphemeralOpts::try_parse_from(["run", "--timeout", "5m", "image"]).is_err());
+
+        let td = tempfile::tempdir()?;
+        let td = Utf8Path::from_path(td.path()).unwrap();
+        let root = td.join("root");
+        let script = td.join("t.sh");
+        std::fs::write(&script, "echo hi\n")?;
+        let line = install_execute_script("t.sh 'a b' 100%", &script, &root)?;
+        assert_eq!(
+            line,
+            "ExecStart=/bin/sh /usr/libexec/bcvk-execute-script \"a b\" \"100%%\"\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("usr/libexec/bcvk-execute-script"))?,
+            "echo hi\n"
+        );
+        std::fs::write(&script, "#!/bin/bash\necho hi\n")?;
+        let line = install_execute_script("t.sh", &script, &root)?;
+        assert_eq!(line, "ExecStart=/usr/libexec/bcvk-execute-script\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_bind_mount() -> Result<()> {
+        let m = BindMount::parse("/srv/data", false)?;
+        assert_eq!(m.name, "data");
+        assert_eq!(m.guest_path, "/run/virtiofs-mnt-data");
+        assert!(!m.readonly);
+        assert_eq!(m.cache, "always");
+
+        let m = BindMount::parse("/srv/data:mydata", true)?;
+        assert_eq!(m.name, "mydata");
+        assert_eq!(m.guest_path, "/run/virtiofs-mnt-mydata");
+        assert!(m.readonly);
+
+        let m = BindMount::parse(
+            "/srv/data:/var/lib/app/:ro,cache=auto,uid-map=:0:1000:1:,uid-map=:1:100000:65535:",
+            false,
+        )?;
+        assert_eq!(m.name, "var-lib-app");
+        assert_eq!(m.guest_path, "/var/lib/app");
+        assert!(m.readonly);
+        assert_eq!(m.cache, "auto");
+        assert_eq!(m.uid_map, [":0:1000:1:", ":1:100000:65535:"]);
+        let config = m.virtiofs_config("/run/host-mounts/var-lib-app");
+        assert_eq!(config.sandbox, "namespace");
+        assert_eq!(config.cache_mode, "auto");
+
+        assert!(BindMount::parse("/srv/data:/:ro", false).is_err());
+        assert!(BindMount::parse("/srv/data:/var/../etc", false).is_err());
+        assert!(BindMount::parse("/srv/data:data:bogus", false).is_err());
+        // Neither QEMU's vhost-user-fs-pci nor virtiofsd support DAX
+        let err = BindMount::parse("/srv/data::dax", false).unwrap_err();
+        assert!(
+            err.to_string().contains("Unknown mount option 'dax'"),
+            "{err}"
+        );
+        assert!(BindMount::parse("/srv/data::dax=2G", false).is_err());
+        assert!(BindMount::parse("/srv/data:data:rw", true).is_err());
+        assert!(BindMount::parse(":data", false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_state_disk() -> Result<()> {
+        assert_eq!(
+            StateDisk::parse("20G")?,
+            StateDisk::Size(20 * 1024 * 1024 * 1024)
+        );
+        assert_eq!(
+            StateDisk::parse("/var/tmp/state.img")?,
+            StateDisk::File("/var/tmp/state.img".into())
+        );
+        assert_eq!(
+            StateDisk::parse("state1.img")?,
+            StateDisk::File("state1.img".into())
+        );
+        assert!(StateDisk::parse("0").is_err());
+        assert!(StateDisk::parse("/tmp/a:b").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_disk_kernel_args() {
+        let args = state_disk_kernel_args();
+        assert_eq!(
+            args[0],
+            "rd.systemd.mount-extra=/dev/disk/by-id/virtio-bcvk-state:/run/bcvk-state:ext4"
+        );
+        assert_eq!(
+            args[1],
+            "rd.systemd.mount-extra=overlay:/sysroot/etc:overlay:lowerdir=/sysroot/etc,upperdir=/run/bcvk-state/etc/upper,workdir=/run/bcvk-state/etc/work,x-systemd.requires-mounts-for=/run/bcvk-state,x-systemd.after=initrd-root-fs.target"
+        );
+        for arg in &args {
+            // WHAT:WHERE:FSTYPE[:OPTIONS], so the options cannot contain a colon
+            let spec = arg.strip_prefix("rd.systemd.mount-extra=").unwrap();
+            assert!(matches!(spec.split(':').count(), 3 | 4), "{arg}");
+            assert!(!arg.contains(char::is_whitespace), "{arg}");
+        }
+    }
+
+    #[test]
+    fn test_parse_selinux_type() {
+        let config = "# comment\nSELINUX=enforcing\nSELINUXTYPE=targeted\n";
+        assert_eq!(parse_selinux_type(config), Some("targeted"));
+        assert_eq!(parse_selinux_type("SELINUXTYPE=\"mls\"\n"), Some("mls"));
+        assert_eq!(parse_selinux_type("SELINUX=disabled\n"), None);
+    }
+
+    #[test]
+    fn test_parse_published_port() -> Result<()> {
+        let port = PublishedPort::parse("8080:80")?;
+        assert_eq!(port.host_port, 8080);
+        assert_eq!(port.guest_port, 80);
+        assert_eq!(port.protocol, PortProtocol::Tcp);
+        assert_eq!(port.podman_arg(), "8080:80/tcp");
+
+        let port = PublishedPort::parse("5353:53/udp")?;
+        assert_eq!(port.protocol, PortProtocol::Udp);
+        assert_eq!(port.podman_arg(), "5353:53/udp");
+
+        assert!(PublishedPort::parse("8080").is_err());
+        assert!(PublishedPort::parse("8080:80/sctp").is_err());
+        assert!(PublishedPort::parse("0:80").is_err());
+        assert!(PublishedPort::parse("8080:http").is_err());
+
+        let specs = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let ports = PublishedPort::parse_all(&specs(&["8080:80", "5353:80/udp"]), true)?;
+        assert_eq!(ports.len(), 2);
+        let err = PublishedPort::parse_all(&specs(&["8080:80", "9090:80/tcp"]), true).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Guest port 80/tcp is published more than once"
+        );
+        let err = PublishedPort::parse_all(&specs(&["2222:2222"]), true).unwrap_err();
+        assert!(err.to_string().contains("used to forward SSH"), "{err}");
+        // Only TCP is forwarded for SSH, and only with SSH enabled
+        PublishedPort::parse_all(&specs(&["2222:2222/udp"]), true)?;
+        PublishedPort::parse_all(&specs(&["2222:2222"]), false)?;
+        Ok(())
+    }
+}
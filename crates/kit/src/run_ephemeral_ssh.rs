@@ -9,7 +9,7 @@ use tracing::debug;
 
 use crate::run_ephemeral::{run_detached, RunEphemeralOpts};
 use crate::ssh;
-use crate::supervisor_status::{SupervisorState, SupervisorStatus};
+use crate::supervisor_status::{SupervisorState, SupervisorStatus, SUPERVISOR_STATUS_PATH};
 
 #[derive(Debug, clap::Parser, serde::Serialize, serde::Deserialize)]
 pub struct RunEphemeralSshOpts {
@@ -26,6 +26,7 @@ pub struct RunEphemeralSshOpts {
 /// Monitors /run/supervisor-status.json inside the container for SSH.
 /// Returns Ok(true) when systemd indicates ssh is probably ready.
 /// Returns Ok(false) if we don't support systemd status notifications.
+/// Fails as soon as the supervisor reports that the VM failed or exited.
 pub fn wait_for_vm_ssh(
     container_name: &str,
     timeout: Duration,
@@ -65,35 +66,65 @@ pub fn wait_for_vm_ssh(
         let status = serde_json::from_str::<SupervisorStatus>(&line)?;
         debug!("Status update: {:?}", status.state);
 
-        if status.ssh_access {
-            // End the monitor
-            let _ = child.kill();
-            return Ok((true, progress));
-        }
-
-        if let Some(state) = status.state {
-            match state {
-                SupervisorState::Ready => {
-                    debug!("VM is ready!");
-                    progress.set_message("Ready");
-                }
-                SupervisorState::ReachedTarget(ref target) => {
-                    progress.set_message(format!("Reached target {}", target));
-                    debug!("Boot progress: Reached {}", target);
-                }
-                SupervisorState::WaitingForSystemd => {
-                    progress.set_message("Waiting for systemd...");
-                    debug!("Waiting for systemd to initialize...");
-                }
+        match &status.state {
+            Some(SupervisorState::Failed(failure)) => {
+                let _ = child.kill();
+                return Err(eyre!("VM failed to boot: {failure}"));
+            }
+            _ if !status.running => {
+                let _ = child.kill();
+                return Err(eyre!("VM exited before it was ready"));
+            }
+            _ if status.ssh_access => {
+                // End the monitor
+                let _ = child.kill();
+                return Ok((true, progress));
+            }
+            Some(SupervisorState::Ready) => {
+                debug!("VM is ready!");
+                progress.set_message("Ready");
+            }
+            Some(SupervisorState::ReachedTarget(target)) => {
+                progress.set_message(format!("Reached target {}", target));
+                debug!("Boot progress: Reached {}", target);
+            }
+            Some(SupervisorState::WaitingForSystemd) => {
+                progress.set_message("Waiting for systemd...");
+                debug!("Waiting for systemd to initialize...");
+            }
+            None => {
+                debug!("Target does not support systemd readiness");
+                return Ok((false, progress));
             }
-        } else {
-            debug!("Target does not support systemd readiness");
-            return Ok((false, progress));
         }
     }
 
     let status = child.wait()?;
-    Err(eyre!("Monitor process exited unexpectedly: {status:?}"))
+    Err(eyre!(
+        "Monitor process exited unexpectedly: {status:?} (did the VM in {container_name} exit?)"
+    ))
+}
+
+/// An error if the status shows that the VM will not become ready.
+fn check_failed(status: &SupervisorStatus) -> Option<color_eyre::Report> {
+    match &status.state {
+        Some(SupervisorState::Failed(failure)) => Some(eyre!("VM failed to boot: {failure}")),
+        _ if !status.running => Some(eyre!("VM exited before it was ready")),
+        _ => None,
+    }
+}
+
+/// Read the supervisor status of the VM in the given container, if available.
+pub(crate) fn read_supervisor_status(container_name: &str) -> Option<SupervisorStatus> {
+    let output = Command::new("podman")
+        .args(["exec", container_name, "cat", SUPERVISOR_STATUS_PATH])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    serde_json::from_slice(&output.stdout).ok()
 }
 
 /// Wait for SSH to be ready by polling SSH connection attempts
@@ -135,6 +166,14 @@ pub fn wait_for_ssh_ready(
             }
         }
 
+        // Failures can also be detected later, e.g. a panic seen on the console
+        if let Some(e) = read_supervisor_status(container_name)
+            .as_ref()
+            .and_then(check_failed)
+        {
+            return Err(e);
+        }
+
         thread::sleep(Duration::from_secs(1));
     }
 
@@ -161,7 +200,19 @@ pub fn run_ephemeral_ssh(opts: RunEphemeralSshOpts) -> Result<()> {
     debug!("Using container ID: {}", container_name);
 
     let progress_bar = crate::boot_progress::create_boot_progress_bar();
-    let progress_bar = wait_for_ssh_ready(&container_name, Duration::from_secs(60), progress_bar)?;
+    let progress_bar =
+        match wait_for_ssh_ready(&container_name, Duration::from_secs(60), progress_bar) {
+            Ok(progress_bar) => progress_bar,
+            Err(e) => {
+                // Don't leave a failed VM behind
+                let _ = Command::new("podman")
+                    .args(["rm", "-f", &container_name])
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+                return Err(e);
+            }
+        };
     progress_bar.finish_and_clear();
 
     // Execute SSH connection directly (no thread needed for this)
@@ -0,0 +1,372 @@
+//! Computing SELinux labels for an image from its own policy.
+//!
+//! `--selinux` boots images whose policy may differ from the host's, so the
+//! image is not relabeled in place with `setfiles` (which would write the
+//! host's `security.selinux`). Instead the labels are looked up in the image's
+//! `file_contexts`, following the rules of libselinux's file backend, and
+//! stored in a separate xattr that virtiofsd presents to the guest.
+
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use camino::Utf8Path;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use regex::bytes::Regex;
+use tracing::{debug, warn};
+
+/// File type a `file_contexts` entry is restricted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FileType {
+    Regular,
+    Dir,
+    Symlink,
+    Char,
+    Block,
+    Fifo,
+    Socket,
+}
+
+impl FileType {
+    /// Parse the optional file type field of a `file_contexts` entry.
+    fn from_flag(flag: &str) -> Option<Self> {
+        Some(match flag {
+            "--" => Self::Regular,
+            "-d" => Self::Dir,
+            "-l" => Self::Symlink,
+            "-c" => Self::Char,
+            "-b" => Self::Block,
+            "-p" => Self::Fifo,
+            "-s" => Self::Socket,
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn of(file_type: std::fs::FileType) -> Self {
+        use std::os::unix::fs::FileTypeExt;
+
+        if file_type.is_dir() {
+            Self::Dir
+        } else if file_type.is_symlink() {
+            Self::Symlink
+        } else if file_type.is_char_device() {
+            Self::Char
+        } else if file_type.is_block_device() {
+            Self::Block
+        } else if file_type.is_fifo() {
+            Self::Fifo
+        } else if file_type.is_socket() {
+            Self::Socket
+        } else {
+            Self::Regular
+        }
+    }
+}
+
+/// One line of a `file_contexts` file
+#[derive(Debug)]
+struct Spec {
+    regex: Regex,
+    /// A plain path rather than a regular expression
+    exact: bool,
+    /// Leading path component without regex characters, e.g. `/usr`
+    stem: Option<Vec<u8>>,
+    file_type: Option<FileType>,
+    /// None for `<<none>>`, which leaves matching files unlabeled
+    context: Option<String>,
+}
+
+/// Whether a `file_contexts` regex is a plain path; these take precedence
+/// over all regular expressions.
+fn is_exact(regex: &str) -> bool {
+    let mut chars = regex.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' | '^' | '$' | '?' | '*' | '+' | '|' | '[' | '(' | '{' => return false,
+            '\\' => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    true
+}
+
+/// The first component of a `file_contexts` regex, if it has no regex characters.
+fn spec_stem(regex: &str) -> Option<Vec<u8>> {
+    let end = regex.get(1..)?.find('/')? + 1;
+    let stem = &regex[..end];
+    (is_exact(stem) && !stem.contains('\\')).then(|| stem.as_bytes().to_vec())
+}
+
+/// The first component of `path`, if it has more than one.
+fn path_stem(path: &[u8]) -> Option<&[u8]> {
+    let end = path.get(1..)?.iter().position(|&c| c == b'/')? + 1;
+    Some(&path[..end])
+}
+
+/// The file labeling rules of an SELinux policy.
+#[derive(Debug, Default)]
+pub(crate) struct FileContexts {
+    /// Regular expressions first, then exact paths; the last match wins
+    specs: Vec<Spec>,
+    /// Path aliases from `file_contexts.subs` and then `file_contexts.subs_dist`
+    subs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl FileContexts {
+    /// Load `file_contexts` and its companion files from `dir`, e.g.
+    /// `/etc/selinux/targeted/contexts/files`.
+    pub(crate) fn load(dir: &Utf8Path) -> Result<Self> {
+        let read_optional = |name: &str| -> Result<Option<String>> {
+            let path = dir.join(name);
+            match std::fs::read_to_string(&path) {
+                Ok(contents) => Ok(Some(contents)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e).with_context(|| format!("Reading {path}")),
+            }
+        };
+        let main = dir.join("file_contexts");
+        let contents = std::fs::read_to_string(&main).with_context(|| format!("Reading {main}"))?;
+        let mut r = Self::default();
+        r.add_specs(&contents)
+            .with_context(|| format!("Parsing {main}"))?;
+        for name in ["file_contexts.homedirs", "file_contexts.local"] {
+            if let Some(contents) = read_optional(name)? {
+                r.add_specs(&contents)
+                    .with_context(|| format!("Parsing {dir}/{name}"))?;
+            }
+        }
+        for name in ["file_contexts.subs", "file_contexts.subs_dist"] {
+            if let Some(contents) = read_optional(name)? {
+                r.add_subs(&contents);
+            }
+        }
+        r.sort();
+        Ok(r)
+    }
+
+    fn add_specs(&mut self, contents: &str) -> Result<()> {
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (regex, file_type, context) = match fields[..] {
+                [regex, context] => (regex, None, context),
+                [regex, flag, context] => {
+                    let file_type = FileType::from_flag(flag)
+                        .ok_or_else(|| eyre!("Invalid file type '{flag}' in: {line}"))?;
+                    (regex, Some(file_type), context)
+                }
+                _ => return Err(eyre!("Invalid file_contexts line: {line}")),
+            };
+            let compiled = match Regex::new(&format!("^(?:{regex})$")) {
+                Ok(r) => r,
+                Err(e) => {
+                    // libselinux uses PCRE2; skip the rare pattern it accepts and we don't
+                    warn!("Ignoring file_contexts entry {regex}: {e}");
+                    continue;
+                }
+            };
+            self.specs.push(Spec {
+                regex: compiled,
+                exact: is_exact(regex),
+                stem: spec_stem(regex),
+                file_type,
+                context: (context != "<<none>>").then(|| context.to_string()),
+            });
+        }
+        Ok(())
+    }
+
+    fn add_subs(&mut self, contents: &str) {
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some((alias, target)) = line.split_once(char::is_whitespace) {
+                let alias = alias.trim_end_matches('/');
+                let target = target.trim().trim_end_matches('/');
+                self.subs
+                    .push((alias.as_bytes().to_vec(), target.as_bytes().to_vec()));
+            }
+        }
+    }
+
+    /// Move exact paths after all regular expressions, keeping the file order otherwise.
+    fn sort(&mut self) {
+        let specs = std::mem::take(&mut self.specs);
+        let (exact, regexes): (Vec<_>, Vec<_>) = specs.into_iter().partition(|spec| spec.exact);
+        self.specs = regexes;
+        self.specs.extend(exact);
+    }
+
+    /// Apply the first path alias matching `path`.
+    fn substitute(&self, path: &[u8]) -> Vec<u8> {
+        for (alias, target) in &self.subs {
+            if let Some(rest) = path.strip_prefix(alias.as_slice()) {
+                if rest.is_empty() || rest.starts_with(b"/") {
+                    return [target.as_slice(), rest].concat();
+                }
+            }
+        }
+        path.to_vec()
+    }
+
+    /// The context for the absolute `path` in the image, if it should be labeled.
+    pub(crate) fn lookup(&self, path: &Path, file_type: FileType) -> Option<&str> {
+        let path = self.substitute(path.as_os_str().as_bytes());
+        let stem = path_stem(&path);
+        self.specs
+            .iter()
+            .rev()
+            .filter(|spec| spec.stem.is_none() || spec.stem.as_deref() == stem)
+            .filter(|spec| spec.file_type.is_none_or(|t| t == file_type))
+            .find(|spec| spec.regex.is_match(&path))
+            .and_then(|spec| spec.context.as_deref())
+    }
+}
+
+/// Store the label of every file under `root`, looked up as if `root` were `/`,
+/// in the `xattr` extended attribute. Other attributes, including the host's
+/// `security.selinux`, are not touched. Mount points below `root` are skipped.
+pub(crate) fn label_tree(root: &Path, contexts: &FileContexts, xattr: &str) -> Result<()> {
+    // Directories on an overlay always have its device number, while other
+    // files may report that of the layer they come from
+    let dev = root.symlink_metadata()?.dev();
+    let mut labeled = 0usize;
+    let mut pending = vec![(root.to_owned(), PathBuf::from("/"))];
+    while let Some((path, image_path)) = pending.pop() {
+        let meta = path.symlink_metadata()?;
+        if meta.is_dir() && meta.dev() != dev {
+            debug!("Not labeling mount point {image_path:?}");
+            continue;
+        }
+        if let Some(context) = contexts.lookup(&image_path, FileType::of(meta.file_type())) {
+            // NUL-terminated, like the labels setfiles writes
+            let value = [context.as_bytes(), b"\0"].concat();
+            rustix::fs::lsetxattr(&path, xattr, &value, rustix::fs::XattrFlags::empty())
+                .with_context(|| format!("Setting {xattr} on {path:?}"))?;
+            labeled += 1;
+        }
+        if meta.is_dir() {
+            for entry in std::fs::read_dir(&path)? {
+                let entry = entry?;
+                pending.push((entry.path(), image_path.join(entry.file_name())));
+            }
+        }
+    }
+    debug!("Labeled {labeled} files under {root:?}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE_CONTEXTS: &str = indoc::indoc! {r#"
+        # comment
+        /.*                         system_u:object_r:default_t:s0
+        /usr(/.*)?                  system_u:object_r:usr_t:s0
+        /usr/bin(/.*)?              system_u:object_r:bin_t:s0
+        /usr/bin/sudo       --      system_u:object_r:sudo_exec_t:s0
+        /usr/lib/debug(/.*)?        <<none>>
+        /etc(/.*)?                  system_u:object_r:etc_t:s0
+        /etc/shadow         --      system_u:object_r:shadow_t:s0
+        /etc/shadow.*       --      system_u:object_r:backup_t:s0
+        /run(/.*)?                  system_u:object_r:var_run_t:s0
+    "#};
+
+    fn contexts() -> FileContexts {
+        let mut contexts = FileContexts::default();
+        contexts.add_specs(FILE_CONTEXTS).unwrap();
+        contexts.add_subs("/var/run /run\n");
+        contexts.sort();
+        contexts
+    }
+
+    #[test]
+    fn test_lookup() {
+        let contexts = contexts();
+        let lookup = |path: &str, file_type| contexts.lookup(Path::new(path), file_type);
+        assert_eq!(
+            lookup("/usr/bin/ls", FileType::Regular),
+            Some("system_u:object_r:bin_t:s0")
+        );
+        assert_eq!(
+            lookup("/usr/bin/sudo", FileType::Regular),
+            Some("system_u:object_r:sudo_exec_t:s0")
+        );
+        // The type restriction applies
+        assert_eq!(
+            lookup("/usr/bin/sudo", FileType::Dir),
+            Some("system_u:object_r:bin_t:s0")
+        );
+        assert_eq!(
+            lookup("/usr", FileType::Dir),
+            Some("system_u:object_r:usr_t:s0")
+        );
+        assert_eq!(lookup("/usr/lib/debug/x", FileType::Regular), None);
+        // Exact paths win over later regular expressions
+        assert_eq!(
+            lookup("/etc/shadow", FileType::Regular),
+            Some("system_u:object_r:shadow_t:s0")
+        );
+        assert_eq!(
+            lookup("/etc/shadow-", FileType::Regular),
+            Some("system_u:object_r:backup_t:s0")
+        );
+        // Aliases are substituted first
+        assert_eq!(
+            lookup("/var/run/foo", FileType::Regular),
+            Some("system_u:object_r:var_run_t:s0")
+        );
+        assert_eq!(
+            lookup("/", FileType::Dir),
+            Some("system_u:object_r:default_t:s0")
+        );
+
+        let mut invalid = FileContexts::default();
+        assert!(invalid
+            .add_specs("/usr -x system_u:object_r:usr_t:s0\n")
+            .is_err());
+        assert!(invalid.add_specs("/usr\n").is_err());
+    }
+
+    #[test]
+    fn test_label_tree_keeps_host_labels() -> Result<()> {
+        let td = tempfile::tempdir()?;
+        let root = td.path();
+        std::fs::create_dir_all(root.join("usr/bin"))?;
+        std::fs::write(root.join("usr/bin/ls"), "")?;
+        let host_labels = |path: &Path| {
+            let mut buf = [0u8; 256];
+            rustix::fs::lgetxattr(path, "security.selinux", &mut buf).map(|n| buf[..n].to_vec())
+        };
+        let files = ["", "usr", "usr/bin", "usr/bin/ls"].map(|p| root.join(p));
+        let before: Vec<_> = files.iter().map(|p| host_labels(p)).collect();
+
+        // Unprivileged tests cannot write trusted.*; user.* exercises the same path,
+        // though only on regular files and directories
+        let xattr = "user.bcvk-test.selinux";
+        match label_tree(root, &contexts(), xattr) {
+            Err(e)
+                if e.root_cause().downcast_ref::<rustix::io::Errno>()
+                    == Some(&rustix::io::Errno::NOTSUP) =>
+            {
+                eprintln!("Skipping: no user xattrs on {root:?}");
+                return Ok(());
+            }
+            r => r?,
+        }
+
+        let after: Vec<_> = files.iter().map(|p| host_labels(p)).collect();
+        assert_eq!(before, after);
+        let mut buf = [0u8; 256];
+        let n = rustix::fs::lgetxattr(root.join("usr/bin/ls"), xattr, &mut buf)?;
+        assert_eq!(&buf[..n], b"system_u:object_r:bin_t:s0\0");
+        Ok(())
+    }
+}
@@ -92,7 +92,7 @@ pub fn generate_default_keypair() -> Result<SshKeyPair> {
 ///
 /// * `container_name` - Name of the podman container hosting the VM
 /// * `_ssh_key` - Path to SSH private key (unused - key is mounted at /tmp/ssh)
-/// * `ssh_user` - Username for SSH connection (typically "root")
+/// * `ssh_user` - Username for SSH connection (default: the VM's `--user`, or root)
 /// * `args` - Additional arguments to pass to the SSH command
 ///
 /// # Container Requirements
@@ -132,11 +132,11 @@ pub fn generate_default_keypair() -> Result<SshKeyPair> {
 /// use bootc_kit::ssh::connect_via_container;
 ///
 /// // Interactive SSH session
-/// connect_via_container("bootc-vm-abc123", vec![])?;
+/// connect_via_container("bootc-vm-abc123", None, vec![])?;
 ///
 /// // Run a specific command
 /// let args = vec!["systemctl".to_string(), "status".to_string()];
-/// connect_via_container("bootc-vm-abc123", args)?;
+/// connect_via_container("bootc-vm-abc123", None, args)?;
 /// ```
 ///
 /// # Generated Command Structure
@@ -162,8 +162,16 @@ pub fn generate_default_keypair() -> Result<SshKeyPair> {
 /// - No host networking configuration required  
 /// - Container provides additional isolation layer
 /// - VM network access is controlled by QEMU configuration
-pub fn connect_via_container(container_name: &str, args: Vec<String>) -> Result<()> {
-    let status = connect_via_container_with_status(container_name, args)?;
+pub fn connect_via_container(
+    container_name: &str,
+    ssh_user: Option<&str>,
+    args: Vec<String>,
+) -> Result<()> {
+    let options = SshConnectionOptions {
+        user: ssh_user.map(str::to_owned),
+        ..Default::default()
+    };
+    let status = connect_via_container_with_options(container_name, args, &options)?;
     if !status.success() {
         return Err(eyre!(
             "SSH connection failed with exit code: {:?}",
@@ -173,9 +181,14 @@ pub fn connect_via_container(container_name: &str, args: Vec<String>) -> Result<
     Ok(())
 }
 
+/// Container label recording the user that `ephemeral ssh` connects as by default
+pub const SSH_USER_LABEL: &str = "bcvk.ssh-user";
+
 /// SSH connection configuration options
 #[derive(Debug, Clone)]
 pub struct SshConnectionOptions {
+    /// User to connect as (default: the VM's `--user`, or root)
+    pub user: Option<String>,
     /// Connection timeout in seconds (default: 30)
     pub connect_timeout: u32,
     /// Enable/disable TTY allocation (default: true)
@@ -191,6 +204,7 @@ pub struct SshConnectionOptions {
 impl Default for SshConnectionOptions {
     fn default() -> Self {
         Self {
+            user: None,
             connect_timeout: 30,
             allocate_tty: true,
             log_level: "ERROR".to_string(),
@@ -204,6 +218,7 @@ impl SshConnectionOptions {
     /// Create options suitable for quick connectivity tests (short timeout, no TTY)
     pub fn for_connectivity_test() -> Self {
         Self {
+            user: None,
             connect_timeout: 2,
             allocate_tty: false,
             log_level: "ERROR".to_string(),
@@ -226,7 +241,12 @@ pub fn connect_via_container_with_options(
 
     // Verify container exists and is running
     let status = Command::new("podman")
-        .args(["inspect", container_name, "--format", "{{.State.Status}}"])
+        .args([
+            "inspect",
+            container_name,
+            "--format",
+            &format!("{{{{.State.Status}}}} {{{{index .Config.Labels \"{SSH_USER_LABEL}\"}}}}"),
+        ])
         .output()
         .map_err(|e| eyre!("Failed to check container status: {}", e))?;
 
@@ -234,7 +254,11 @@ pub fn connect_via_container_with_options(
         return Err(eyre!("Container '{}' not found", container_name));
     }
 
-    let container_status = String::from_utf8_lossy(&status.stdout).trim().to_string();
+    let inspect_output = String::from_utf8_lossy(&status.stdout);
+    let (container_status, label_user) = inspect_output
+        .trim()
+        .split_once(' ')
+        .unwrap_or((inspect_output.trim(), ""));
     if container_status != "running" {
         return Err(eyre!(
             "Container '{}' is not running (status: {})",
@@ -273,7 +297,12 @@ pub fn connect_via_container_with_options(
     }
 
     // Connect to VM via QEMU port forwarding on localhost
-    cmd.arg(&format!("root@127.0.0.1"));
+    let user = match options.user.as_deref() {
+        Some(user) => user,
+        None if !label_user.is_empty() => label_user,
+        None => "root",
+    };
+    cmd.arg(&format!("{user}@127.0.0.1"));
     cmd.args(["-p", "2222"]); // Use the forwarded port
 
     // Add any additional arguments
@@ -4,8 +4,13 @@
 //! firmware variables (preferred) or kernel command-line arguments. Creates systemd
 //! tmpfiles.d configuration to set up SSH access during VM boot.
 //!
+//! Non-root users (`--user`) are created via a sysusers.d credential, with
+//! their key, home directory and sudo access set up via tmpfiles.d.
+//!
+//! Also builds SMBIOS strings for arbitrary user-provided credentials
+//! (`--credential`), which units consume via `LoadCredential=`/`ImportCredential=`.
 
-use color_eyre::Result;
+use color_eyre::{eyre::eyre, Result};
 
 /// Generate SMBIOS credential string for root SSH access
 ///
@@ -43,6 +48,95 @@ pub fn key_to_root_tmpfiles_d(pubkey: &str) -> String {
     format!("d /root/.ssh 0750 - - -\nf+~ /root/.ssh/authorized_keys 700 - - - {buf}")
 }
 
+/// A non-root user to create in the VM, from `--user NAME[:GROUP,...]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshUser {
+    pub name: String,
+    pub groups: Vec<String>,
+}
+
+/// Whether `name` is a conservative (POSIX-portable) user or group name
+fn is_valid_user_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+        && name.len() <= 32
+}
+
+impl SshUser {
+    /// Parse `NAME[:GROUP,...]`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (name, groups) = match spec.split_once(':') {
+            Some((name, groups)) => (name, groups.split(',').map(str::to_owned).collect()),
+            None => (spec, Vec::new()),
+        };
+        if name == "root" {
+            return Err(eyre!(
+                "--user is for non-root users; root is always configured"
+            ));
+        }
+        for n in std::iter::once(name).chain(groups.iter().map(String::as_str)) {
+            if !is_valid_user_name(n) {
+                return Err(eyre!("Invalid user or group name: {n:?}"));
+            }
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            groups,
+        })
+    }
+
+    /// Generate sysusers.d configuration creating the user and adding it to its groups
+    pub fn sysusers_d(&self) -> String {
+        let name = &self.name;
+        let mut r = format!("u {name} - \"bcvk user\" /home/{name} /bin/bash\n");
+        for group in &self.groups {
+            r.push_str(&format!("m {name} {group}\n"));
+        }
+        r
+    }
+
+    /// Generate tmpfiles.d configuration for the user's home directory,
+    /// passwordless sudo and (optionally) SSH key
+    pub fn tmpfiles_d(&self, pubkey: Option<&str>) -> String {
+        let name = &self.name;
+        let sudoers =
+            data_encoding::BASE64.encode(format!("{name} ALL=(ALL) NOPASSWD: ALL\n").as_bytes());
+        let mut r = format!(
+            "d /home/{name} 0700 {name} {name} -\nf~ /etc/sudoers.d/bcvk-{name} 0440 root root - {sudoers}\n"
+        );
+        if let Some(pubkey) = pubkey {
+            let buf = data_encoding::BASE64.encode(pubkey.as_bytes());
+            r.push_str(&format!(
+                "d /home/{name}/.ssh 0700 {name} {name} -\nf+~ /home/{name}/.ssh/authorized_keys 0600 {name} {name} - {buf}\n"
+            ));
+        }
+        r
+    }
+}
+
+/// Generate SMBIOS credential strings for SSH access as root and, if given, a non-root user
+///
+/// Both share the single `tmpfiles.extra` credential; the user itself is
+/// created through `sysusers.extra`.
+///
+/// Returns strings for use with `qemu -smbios type=11,value="..."`
+pub fn smbios_creds_for_ssh(pubkey: Option<&str>, user: Option<&SshUser>) -> Result<Vec<String>> {
+    let Some(user) = user else {
+        return pubkey.map(smbios_cred_for_root_ssh).into_iter().collect();
+    };
+    let mut tmpfiles = String::new();
+    if let Some(pubkey) = pubkey {
+        tmpfiles.push_str(&key_to_root_tmpfiles_d(pubkey));
+        tmpfiles.push('\n');
+    }
+    tmpfiles.push_str(&user.tmpfiles_d(pubkey));
+    Ok(vec![
+        smbios_cred("sysusers.extra", user.sysusers_d().as_bytes()),
+        smbios_cred("tmpfiles.extra", tmpfiles.as_bytes()),
+    ])
+}
+
 /// Generate SMBIOS credential string for AF_VSOCK systemd notification socket
 ///
 /// Creates a systemd credential that configures systemd to send notifications
@@ -57,6 +151,52 @@ pub fn smbios_cred_for_vsock_notify(host_cid: u32, port: u32) -> String {
     )
 }
 
+/// Check that a credential name is usable as a file name in `$CREDENTIALS_DIRECTORY`
+pub(crate) fn validate_credential_name(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') || name.len() > 255 {
+        return Err(eyre!("Invalid credential name: {name:?}"));
+    }
+    Ok(())
+}
+
+/// Parse a `NAME=VALUE` or `NAME=@FILE` credential specification
+///
+/// Values given with `@` are read from the named file and may be binary.
+pub fn parse_credential(spec: &str) -> Result<(String, Vec<u8>)> {
+    let (name, value) = spec
+        .split_once('=')
+        .ok_or_else(|| eyre!("Invalid credential {spec:?}, expected NAME=VALUE or NAME=@FILE"))?;
+    validate_credential_name(name)?;
+    let value = match value.strip_prefix('@') {
+        Some(path) => std::fs::read(path)
+            .map_err(|e| eyre!("Failed to read credential {name} from {path}: {e}"))?,
+        None => value.as_bytes().to_vec(),
+    };
+    Ok((name.to_string(), value))
+}
+
+/// Generate SMBIOS credential string for an arbitrary systemd credential
+///
+/// Printable ASCII values are passed as-is; anything else (binary data,
+/// newlines, or commas, which QEMU would treat as an option separator) is
+/// base64 encoded.
+///
+/// Returns a string for use with `qemu -smbios type=11,value="..."`
+pub fn smbios_cred(name: &str, value: &[u8]) -> String {
+    let is_plain = value
+        .iter()
+        .all(|&c| (c.is_ascii_graphic() || c == b' ') && c != b',');
+    if is_plain {
+        format!(
+            "io.systemd.credential:{name}={}",
+            String::from_utf8_lossy(value)
+        )
+    } else {
+        let encoded = data_encoding::BASE64.encode(value);
+        format!("io.systemd.credential.binary:{name}={encoded}")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use data_encoding::BASE64;
@@ -91,4 +231,81 @@ mod tests {
         // Test the actual function output
         assert_eq!(smbios_cred_for_root_ssh(STUBKEY).unwrap(), expected);
     }
+
+    #[test]
+    fn test_ssh_user() {
+        let user = SshUser::parse("alice:wheel,adm").unwrap();
+        assert_eq!(user.name, "alice");
+        assert_eq!(user.groups, ["wheel", "adm"]);
+        assert_eq!(
+            user.sysusers_d(),
+            "u alice - \"bcvk user\" /home/alice /bin/bash\nm alice wheel\nm alice adm\n"
+        );
+        assert_eq!(SshUser::parse("bob").unwrap().groups, Vec::<String>::new());
+        assert!(SshUser::parse("root").is_err());
+        assert!(SshUser::parse("Alice").is_err());
+        assert!(SshUser::parse("alice:").is_err());
+        assert!(SshUser::parse("alice:wheel;rm").is_err());
+
+        let tmpfiles = user.tmpfiles_d(Some(STUBKEY));
+        assert!(tmpfiles.contains("f~ /etc/sudoers.d/bcvk-alice 0440 root root - "));
+        assert!(tmpfiles.contains(&format!(
+            "f+~ /home/alice/.ssh/authorized_keys 0600 alice alice - {}",
+            BASE64.encode(STUBKEY.as_bytes())
+        )));
+        assert!(!user.tmpfiles_d(None).contains(".ssh"));
+
+        let creds = smbios_creds_for_ssh(Some(STUBKEY), Some(&user)).unwrap();
+        assert_eq!(creds.len(), 2);
+        assert!(creds[0].starts_with("io.systemd.credential.binary:sysusers.extra="));
+        let tmpfiles = creds[1]
+            .strip_prefix("io.systemd.credential.binary:tmpfiles.extra=")
+            .unwrap();
+        let tmpfiles = String::from_utf8(BASE64.decode(tmpfiles.as_bytes()).unwrap()).unwrap();
+        assert!(tmpfiles.starts_with(&key_to_root_tmpfiles_d(STUBKEY)));
+        assert!(tmpfiles.contains("/home/alice/.ssh/authorized_keys"));
+        assert_eq!(
+            smbios_creds_for_ssh(Some(STUBKEY), None).unwrap(),
+            [smbios_cred_for_root_ssh(STUBKEY).unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_smbios_cred() {
+        assert_eq!(
+            smbios_cred("myapp.token", b"s3cret value"),
+            "io.systemd.credential:myapp.token=s3cret value"
+        );
+        assert_eq!(
+            smbios_cred("conf", b"a=1\nb=2,3"),
+            format!(
+                "io.systemd.credential.binary:conf={}",
+                BASE64.encode(b"a=1\nb=2,3")
+            )
+        );
+        assert_eq!(
+            smbios_cred("bin", &[0, 0xff]),
+            "io.systemd.credential.binary:bin=AP8="
+        );
+    }
+
+    #[test]
+    fn test_parse_credential() {
+        let (name, value) = parse_credential("foo=bar=baz").unwrap();
+        assert_eq!(name, "foo");
+        assert_eq!(value, b"bar=baz");
+
+        let td = tempfile::tempdir().unwrap();
+        let path = td.path().join("cred");
+        std::fs::write(&path, [1u8, 2, 3]).unwrap();
+        let (name, value) = parse_credential(&format!("bin=@{}", path.display())).unwrap();
+        assert_eq!(name, "bin");
+        assert_eq!(value, [1, 2, 3]);
+
+        assert!(parse_credential("novalue").is_err());
+        assert!(parse_credential("=value").is_err());
+        assert!(parse_credential("a/b=value").is_err());
+        assert!(parse_credential("..=value").is_err());
+        assert!(parse_credential("missing=@/nonexistent/file").is_err());
+    }
 }
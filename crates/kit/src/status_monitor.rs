@@ -5,7 +5,7 @@ use std::path::Path;
 use std::sync::mpsc::{self, Receiver};
 use tracing::{debug, warn};
 
-use crate::supervisor_status::SupervisorStatus;
+use crate::supervisor_status::{SupervisorStatus, SUPERVISOR_STATUS_PATH};
 
 /// Monitor a status file for changes using inotify
 pub fn monitor_status_file<P: AsRef<Path>>(
@@ -104,9 +104,7 @@ impl StatusFileIterator {
 
 /// Monitor status and stream updates to stdout as JSON lines
 pub fn monitor_and_stream_status() -> Result<()> {
-    let path = "/run/supervisor-status.json";
-
-    let monitor = monitor_status_file(path)?;
+    let monitor = monitor_status_file(SUPERVISOR_STATUS_PATH)?;
 
     for status_result in monitor {
         match status_result {
@@ -1,8 +1,16 @@
 use cap_std_ext::{cap_std, cap_std::fs::Dir, dirext::CapStdExtDirExt};
+use chrono::{DateTime, Utc};
 use color_eyre::Result;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Where the supervisor writes its status inside the container
+pub const SUPERVISOR_STATUS_PATH: &str = "/run/supervisor-status.json";
+
+/// Lines of console output included in a [`BootFailure`]
+const FAILURE_CONSOLE_LINES: usize = 30;
 
 /// Status of the supervisor process and VM
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
@@ -14,6 +22,12 @@ pub struct SupervisorStatus {
     pub ssh_access: bool,
     /// True if qemu is running
     pub running: bool,
+    /// Units systemd reported as active so far, in order
+    #[serde(default)]
+    pub reached_targets: Vec<String>,
+    /// Configuration of the VM, once QEMU has started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vm: Option<VmInfo>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -25,16 +39,93 @@ pub enum SupervisorState {
     ReachedTarget(String),
     /// VM is ready and accepting connections
     Ready,
+    /// The VM failed and will not become ready
+    Failed(BootFailure),
+}
+
+/// Why the VM will not become ready
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BootFailureKind {
+    /// systemd reached emergency.target
+    EmergencyTarget,
+    /// systemd reached rescue.target
+    RescueTarget,
+    /// The kernel panicked
+    KernelPanic,
+    /// QEMU exited
+    QemuExited,
+}
+
+/// A VM failure, with the console output leading up to it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct BootFailure {
+    pub kind: BootFailureKind,
+    pub message: String,
+    /// The last lines of the serial console, if it is being logged
+    #[serde(default)]
+    pub console: Vec<String>,
+}
+
+impl BootFailure {
+    /// Create a failure, capturing the end of the console log.
+    pub fn new(kind: BootFailureKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            console: crate::console::console_tail(FAILURE_CONSOLE_LINES),
+        }
+    }
+}
+
+impl std::fmt::Display for BootFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if !self.console.is_empty() {
+            write!(
+                f,
+                "\n--- Last {} lines of the console ---",
+                self.console.len()
+            )?;
+            for line in &self.console {
+                write!(f, "\n{line}")?;
+            }
+        }
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Configuration of a running VM
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
 pub struct VmInfo {
-    /// Whether SSH is available
-    pub ssh_available: bool,
-    /// Port number for SSH if forwarded
-    pub ssh_port: Option<u16>,
-    /// IP address if available
-    pub ip_address: Option<String>,
+    /// PID of QEMU inside the container
+    pub qemu_pid: u32,
+    /// Guest vsock CID, if vsock is enabled
+    pub vsock_cid: Option<u32>,
+    pub memory_mb: u32,
+    pub vcpus: u32,
+    /// Ports published on the host, as `HOST_PORT:GUEST_PORT/PROTO`
+    #[serde(default)]
+    pub published_ports: Vec<String>,
+    /// Host directories shared with the guest
+    #[serde(default)]
+    pub mounts: Vec<MountInfo>,
+    /// Version of the booted kernel, unless it was given with `--kernel`
+    pub kernel_version: Option<String>,
+    /// When QEMU was started
+    pub started: DateTime<Utc>,
+}
+
+/// A host directory shared with the guest over virtiofs
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct MountInfo {
+    pub source: String,
+    /// Where the guest mounts it
+    pub target: String,
+    pub readonly: bool,
 }
 
 impl SupervisorStatus {
@@ -62,6 +153,47 @@ impl SupervisorStatus {
         Ok(())
     }
 
+    /// The last unit systemd reported as active
+    pub fn last_target(&self) -> Option<&str> {
+        self.reached_targets.last().map(String::as_str)
+    }
+
+    /// A short description of where the VM is, e.g. `booting` or `ready`.
+    pub fn boot_state(&self) -> &'static str {
+        match &self.state {
+            Some(SupervisorState::Failed(_)) => "failed",
+            _ if !self.running => "stopped",
+            _ if self.ssh_access => "ready",
+            Some(SupervisorState::Ready) => "ready",
+            Some(SupervisorState::WaitingForSystemd | SupervisorState::ReachedTarget(_)) => {
+                "booting"
+            }
+            None => "running",
+        }
+    }
+
+    /// Keep what later updates do not repeat: the VM configuration and the
+    /// reached targets.
+    fn keep_previous(&mut self, previous: &SupervisorStatus) {
+        if self.vm.is_none() {
+            self.vm = previous.vm.clone();
+        }
+        if self.reached_targets.is_empty() {
+            self.reached_targets = previous.reached_targets.clone();
+        }
+    }
+
+    /// A failure is final; keep it from the previous status so later
+    /// updates do not hide it.
+    fn keep_failure(&mut self, previous: SupervisorStatus) {
+        if matches!(self.state, Some(SupervisorState::Failed(_))) {
+            return;
+        }
+        if let Some(failed @ SupervisorState::Failed(_)) = previous.state {
+            self.state = Some(failed);
+        }
+    }
+
     /// Read status from a JSON file
     pub fn read_from_file(path: impl AsRef<Path>) -> color_eyre::Result<Self> {
         let contents = fs::read_to_string(path)?;
@@ -69,20 +201,53 @@ impl SupervisorStatus {
     }
 }
 
-/// Helper to write status updates from the supervisor
+/// Helper to write status updates from the supervisor. Clones share the
+/// status, so updates from concurrent tasks apply one at a time and none
+/// overwrites another.
+#[derive(Debug, Clone)]
 pub struct StatusWriter {
     path: String,
+    status: Arc<Mutex<SupervisorStatus>>,
 }
 
 impl StatusWriter {
     pub fn new(path: impl Into<String>) -> Self {
-        Self { path: path.into() }
+        Self {
+            path: path.into(),
+            status: Default::default(),
+        }
     }
 
-    pub fn update(&self, status: SupervisorStatus) -> color_eyre::Result<()> {
+    /// Change the status and write it out.
+    fn modify(&self, f: impl FnOnce(&mut SupervisorStatus)) -> color_eyre::Result<()> {
+        let mut status = self.status.lock().unwrap();
+        f(&mut status);
+        // Written with the lock held, so the file ends up with the latest status
         status.write_to_file(&self.path)
     }
 
+    pub fn update(&self, mut status: SupervisorStatus) -> color_eyre::Result<()> {
+        self.modify(|current| {
+            let previous = std::mem::take(current);
+            status.keep_previous(&previous);
+            status.keep_failure(previous);
+            *current = status;
+        })
+    }
+
+    /// Record a failure; `running` is whether QEMU is still running.
+    pub fn fail(&self, failure: BootFailure, running: bool) -> color_eyre::Result<()> {
+        self.modify(|status| {
+            status.state = Some(SupervisorState::Failed(failure));
+            status.running = running;
+        })
+    }
+
+    /// Record the configuration of the VM once QEMU has started.
+    pub fn set_vm_info(&self, vm: VmInfo) -> color_eyre::Result<()> {
+        self.modify(|status| status.vm = Some(vm))
+    }
+
     pub fn update_state(&self, state: SupervisorState) -> color_eyre::Result<()> {
         self.update(SupervisorStatus::new(state))
     }
@@ -94,3 +259,119 @@ impl StatusWriter {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_is_sticky() -> Result<()> {
+        let failure = BootFailure {
+            kind: BootFailureKind::EmergencyTarget,
+            message: "Guest entered emergency.target".into(),
+            console: vec!["You are in emergency mode.".into()],
+        };
+        let failed = SupervisorStatus {
+            state: Some(SupervisorState::Failed(failure.clone())),
+            running: true,
+            ..Default::default()
+        };
+        // Round trip as it is read by the host
+        let failed: SupervisorStatus = serde_json::from_str(&serde_json::to_string(&failed)?)?;
+
+        let mut next =
+            SupervisorStatus::new(SupervisorState::ReachedTarget("sysinit.target".into()));
+        next.keep_failure(failed.clone());
+        assert_eq!(next.state, failed.state);
+        let mut finished = SupervisorStatus::default();
+        finished.keep_failure(failed);
+        assert!(!finished.running);
+        assert_eq!(
+            finished.state,
+            Some(SupervisorState::Failed(failure.clone()))
+        );
+
+        let mut ok = SupervisorStatus::new(SupervisorState::Ready);
+        ok.keep_failure(SupervisorStatus::new(SupervisorState::WaitingForSystemd));
+        assert_eq!(ok.state, Some(SupervisorState::Ready));
+
+        assert_eq!(
+            failure.to_string(),
+            "Guest entered emergency.target\n--- Last 1 lines of the console ---\nYou are in emergency mode."
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_vm_info_is_kept() -> Result<()> {
+        let vm = VmInfo {
+            qemu_pid: 42,
+            vsock_cid: Some(3),
+            memory_mb: 4096,
+            vcpus: 2,
+            published_ports: vec!["8080:80/tcp".into()],
+            mounts: vec![MountInfo {
+                source: "/srv/data".into(),
+                target: "/run/virtiofs-mnt-data".into(),
+                readonly: true,
+            }],
+            kernel_version: Some("6.12.0-55.el10.x86_64".into()),
+            started: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        };
+        let previous = SupervisorStatus {
+            state: Some(SupervisorState::ReachedTarget("multi-user.target".into())),
+            ssh_access: true,
+            running: true,
+            reached_targets: vec!["basic.target".into(), "multi-user.target".into()],
+            vm: Some(vm.clone()),
+        };
+        let previous: SupervisorStatus = serde_json::from_str(&serde_json::to_string(&previous)?)?;
+        assert_eq!(previous.boot_state(), "ready");
+        assert_eq!(previous.last_target(), Some("multi-user.target"));
+
+        let mut finished = SupervisorStatus::default();
+        finished.keep_previous(&previous);
+        assert_eq!(finished.vm, Some(vm));
+        assert_eq!(finished.last_target(), Some("multi-user.target"));
+        assert_eq!(finished.boot_state(), "stopped");
+
+        // Older supervisors do not write the VM configuration
+        let old: SupervisorStatus = serde_json::from_str(
+            r#"{"state":"waiting_for_systemd","ssh_access":false,"running":true}"#,
+        )?;
+        assert_eq!(old.vm, None);
+        assert_eq!(old.boot_state(), "booting");
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_writers() -> Result<()> {
+        let td = tempfile::tempdir()?;
+        let path = td.path().join("status.json");
+        let writer = StatusWriter::new(path.to_str().unwrap());
+        writer.update_state(SupervisorState::WaitingForSystemd)?;
+
+        // A failure from the console relay is not lost to boot progress
+        // updates racing with it
+        std::thread::scope(|s| {
+            let console = writer.clone();
+            s.spawn(move || {
+                let failure = BootFailure {
+                    kind: BootFailureKind::KernelPanic,
+                    message: "Guest kernel panicked".into(),
+                    console: Vec::new(),
+                };
+                console.fail(failure, true).unwrap();
+            });
+            for i in 0..50 {
+                writer
+                    .update_state(SupervisorState::ReachedTarget(format!("unit{i}.target")))
+                    .unwrap();
+            }
+        });
+        let status = SupervisorStatus::read_from_file(&path)?;
+        assert!(matches!(status.state, Some(SupervisorState::Failed(_))));
+        assert_eq!(status.boot_state(), "failed");
+        Ok(())
+    }
+}
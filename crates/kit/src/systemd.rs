@@ -1,8 +1,11 @@
 use std::fmt::Display;
+use std::fs;
 use std::process::Command;
 
+use camino::{Utf8Path, Utf8PathBuf};
 use color_eyre::eyre::{eyre, Context as _};
 use color_eyre::Result;
+use tracing::debug;
 
 /// A systemd version
 #[derive(Debug, Clone)]
@@ -34,6 +37,11 @@ impl SystemdVersion {
     pub fn has_vmm_notify(&self) -> bool {
         self.0 >= 254
     }
+
+    /// https://www.freedesktop.org/software/systemd/man/latest/systemd-fstab-generator.html#systemd.mount-extra=
+    pub fn has_mount_extra(&self) -> bool {
+        self.0 >= 254
+    }
 }
 
 impl Display for SystemdVersion {
@@ -42,10 +50,237 @@ impl Display for SystemdVersion {
     }
 }
 
+/// Unit file suffixes systemd understands
+const UNIT_SUFFIXES: &[&str] = &[
+    "service",
+    "socket",
+    "device",
+    "mount",
+    "automount",
+    "swap",
+    "target",
+    "path",
+    "timer",
+    "slice",
+    "scope",
+];
+
+/// Suffixes of directories holding dependencies of the unit they are named after
+const DEPENDENCY_DIR_SUFFIXES: &[&str] = &["wants", "requires", "upholds"];
+
+/// Whether `name` is a unit file name, e.g. `foo.service` or `getty@.service`
+fn is_unit_name(name: &str) -> bool {
+    name.rsplit_once('.')
+        .is_some_and(|(stem, suffix)| !stem.is_empty() && UNIT_SUFFIXES.contains(&suffix))
+}
+
+/// Copy a file, or recreate a symlink (e.g. an alias, or a mask to /dev/null) as-is.
+fn copy_file_or_symlink(source: &Utf8Path, target: &Utf8Path) -> Result<()> {
+    let metadata = source.symlink_metadata()?;
+    if metadata.is_symlink() {
+        let link = fs::read_link(source)?;
+        let _ = fs::remove_file(target);
+        std::os::unix::fs::symlink(link, target)
+            .with_context(|| format!("Creating symlink {target}"))?;
+    } else if metadata.is_file() {
+        fs::copy(source, target).with_context(|| format!("Copying {source} to {target}"))?;
+    } else {
+        return Err(eyre!("Unsupported file type: {source}"));
+    }
+    Ok(())
+}
+
+/// Sorted entries of a directory, so errors and logs are deterministic.
+fn sorted_entries(dir: &Utf8Path) -> Result<Vec<(String, Utf8PathBuf)>> {
+    let mut r = dir
+        .read_dir_utf8()
+        .with_context(|| format!("Reading {dir}"))?
+        .map(|e| e.map(|e| (e.file_name().to_owned(), e.into_path())))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    r.sort();
+    Ok(r)
+}
+
+/// Copy a unit directory (`system/` or `user/`) into `target`, including
+/// drop-in (`*.d/`) and dependency (`*.wants/`, `*.requires/`, `*.upholds/`)
+/// directories. Anything else is an error rather than being silently ignored.
+///
+/// Returns the names of the unit files copied.
+fn copy_unit_dir(source: &Utf8Path, target: &Utf8Path) -> Result<Vec<String>> {
+    fs::create_dir_all(target)?;
+    let mut units = Vec::new();
+    for (name, path) in sorted_entries(source)? {
+        let target_path = target.join(&name);
+        if !path.symlink_metadata()?.is_dir() {
+            if !is_unit_name(&name) {
+                return Err(eyre!("Not a systemd unit file: {path}"));
+            }
+            copy_file_or_symlink(&path, &target_path)?;
+            debug!("Copied systemd unit: {name}");
+            units.push(name);
+            continue;
+        }
+
+        let (stem, suffix) = name.rsplit_once('.').unwrap_or((&name, ""));
+        if suffix == "d" && (is_unit_name(stem) || UNIT_SUFFIXES.contains(&stem)) {
+            fs::create_dir_all(&target_path)?;
+            for (dropin, dropin_path) in sorted_entries(&path)? {
+                if !dropin.ends_with(".conf") {
+                    return Err(eyre!("Not a drop-in (*.conf): {dropin_path}"));
+                }
+                copy_file_or_symlink(&dropin_path, &target_path.join(&dropin))?;
+            }
+            debug!("Copied drop-in directory: {name}");
+        } else if DEPENDENCY_DIR_SUFFIXES.contains(&suffix) && is_unit_name(stem) {
+            fs::create_dir_all(&target_path)?;
+            for (dep, dep_path) in sorted_entries(&path)? {
+                if !is_unit_name(&dep) {
+                    return Err(eyre!("Not a systemd unit: {dep_path}"));
+                }
+                copy_file_or_symlink(&dep_path, &target_path.join(&dep))?;
+            }
+            debug!("Copied dependency directory: {name}");
+        } else {
+            return Err(eyre!(
+                "Unsupported directory {path} (expected *.d, *.wants, *.requires or *.upholds)"
+            ));
+        }
+    }
+    Ok(units)
+}
+
+/// Copy preset files (`*.preset`) into `target`.
+fn copy_preset_dir(source: &Utf8Path, target: &Utf8Path) -> Result<()> {
+    fs::create_dir_all(target)?;
+    for (name, path) in sorted_entries(source)? {
+        if !name.ends_with(".preset") {
+            return Err(eyre!("Not a preset file (*.preset): {path}"));
+        }
+        copy_file_or_symlink(&path, &target.join(&name))?;
+    }
+    Ok(())
+}
+
+/// Apply presets to the given units offline, only ever enabling units.
+fn apply_presets(image_root: &Utf8Path, global: bool, units: &[String]) -> Result<()> {
+    let mut cmd = Command::new("systemctl");
+    cmd.arg(format!("--root={image_root}"));
+    if global {
+        cmd.arg("--global");
+    }
+    cmd.args(["--preset-mode=enable-only", "preset"])
+        .args(units);
+    let o = cmd.output().context("Running systemctl preset")?;
+    if !o.status.success() {
+        return Err(eyre!(
+            "systemctl preset failed: {}",
+            String::from_utf8_lossy(&o.stderr)
+        ));
+    }
+    Ok(())
+}
+
+/// Install a unit tree into `image_root/etc/systemd`, mirroring its layout:
+///
+/// - `system/`, `user/`: units, drop-ins and dependency directories
+/// - `system-preset/`, `user-preset/`: presets, applied to the units injected alongside them
+///
+/// System `.mount` units are enabled in `local-fs.target.wants`.
+pub(crate) fn install_unit_tree(source: &Utf8Path, image_root: &Utf8Path) -> Result<()> {
+    let target = image_root.join("etc/systemd");
+    let mut system_units = Vec::new();
+    let mut user_units = Vec::new();
+    let mut presets = (false, false);
+    for (name, path) in sorted_entries(source)? {
+        match name.as_str() {
+            "system" => system_units = copy_unit_dir(&path, &target.join(&name))?,
+            "user" => user_units = copy_unit_dir(&path, &target.join(&name))?,
+            "system-preset" => {
+                copy_preset_dir(&path, &target.join(&name))?;
+                presets.0 = true;
+            }
+            "user-preset" => {
+                copy_preset_dir(&path, &target.join(&name))?;
+                presets.1 = true;
+            }
+            _ => {
+                return Err(eyre!(
+                    "Unsupported entry {path} (expected system, user, system-preset or user-preset)"
+                ))
+            }
+        }
+    }
+
+    let mounts = system_units.iter().filter(|u| u.ends_with(".mount"));
+    let wants_dir = target.join("system/local-fs.target.wants");
+    for unit in mounts {
+        fs::create_dir_all(&wants_dir)?;
+        let _ = fs::remove_file(wants_dir.join(unit));
+        std::os::unix::fs::symlink(format!("../{unit}"), wants_dir.join(unit))?;
+        debug!("Enabled mount unit: {unit}");
+    }
+
+    if presets.0 && !system_units.is_empty() {
+        apply_presets(image_root, false, &system_units)?;
+    }
+    if presets.1 && !user_units.is_empty() {
+        apply_presets(image_root, true, &user_units)?;
+    }
+    Ok(())
+}
+
+/// Quote an argument for `ExecStart=`, escaping specifiers (`%`) and
+/// environment variable references (`$`).
+pub(crate) fn quote_exec_arg(arg: &str) -> String {
+    quote_unit_word(arg, true)
+}
+
+/// Quote a `NAME=VALUE` assignment for `Environment=`.
+pub(crate) fn quote_environment(assignment: &str) -> String {
+    quote_unit_word(assignment, false)
+}
+
+fn quote_unit_word(word: &str, escape_dollar: bool) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_./:,+@=".contains(c);
+    if !word.is_empty() && word.chars().all(is_plain) {
+        return word.to_owned();
+    }
+    let mut quoted = String::with_capacity(word.len() + 2);
+    quoted.push('"');
+    for c in word.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '%' => quoted.push_str("%%"),
+            '$' if escape_dollar => quoted.push_str("$$"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_quote_unit_word() {
+        assert_eq!(quote_exec_arg("/usr/bin/foo"), "/usr/bin/foo");
+        assert_eq!(quote_exec_arg("--opt=1"), "--opt=1");
+        assert_eq!(quote_exec_arg(""), r#""""#);
+        assert_eq!(quote_exec_arg("a b"), r#""a b""#);
+        assert_eq!(
+            quote_exec_arg(r#"say "hi" \ $HOME 100%"#),
+            r#""say \"hi\" \\ $$HOME 100%%""#
+        );
+        assert_eq!(quote_exec_arg("two\nlines"), r#""two\nlines""#);
+        assert_eq!(quote_environment("A=1"), "A=1");
+        assert_eq!(quote_environment("A=$x 5%"), r#""A=$x 5%%""#);
+    }
+
     #[test]
     fn test_parse_systemd_version() {
         // Test typical systemd version output
@@ -84,4 +319,64 @@ mod tests {
         let output = "systemd";
         assert!(SystemdVersion::from_version_output(output).is_err());
     }
+
+    #[test]
+    fn test_install_unit_tree() -> Result<()> {
+        let td = tempfile::tempdir()?;
+        let td = Utf8Path::from_path(td.path()).unwrap();
+        let source = td.join("units");
+        let root = td.join("root");
+        let system = source.join("system");
+        fs::create_dir_all(system.join("foo.service.d"))?;
+        fs::create_dir_all(system.join("timers.target.wants"))?;
+        fs::create_dir_all(source.join("user/default.target.wants"))?;
+        fs::write(system.join("foo.service"), "[Service]\n")?;
+        fs::write(system.join("foo.timer"), "[Timer]\n")?;
+        fs::write(system.join("data.mount"), "[Mount]\n")?;
+        fs::write(system.join("foo.service.d/10-env.conf"), "[Service]\n")?;
+        std::os::unix::fs::symlink("/dev/null", system.join("masked.service"))?;
+        std::os::unix::fs::symlink("../foo.timer", system.join("timers.target.wants/foo.timer"))?;
+        fs::write(source.join("user/bar.socket"), "[Socket]\n")?;
+        std::os::unix::fs::symlink(
+            "../bar.socket",
+            source.join("user/default.target.wants/bar.socket"),
+        )?;
+
+        install_unit_tree(&source, &root)?;
+        let target = root.join("etc/systemd");
+        for path in [
+            "system/foo.service",
+            "system/foo.timer",
+            "system/foo.service.d/10-env.conf",
+            "user/bar.socket",
+        ] {
+            assert!(target.join(path).is_file(), "{path}");
+        }
+        assert_eq!(
+            fs::read_link(target.join("system/masked.service"))?,
+            Utf8Path::new("/dev/null")
+        );
+        assert_eq!(
+            fs::read_link(target.join("system/timers.target.wants/foo.timer"))?,
+            Utf8Path::new("../foo.timer")
+        );
+        assert_eq!(
+            fs::read_link(target.join("user/default.target.wants/bar.socket"))?,
+            Utf8Path::new("../bar.socket")
+        );
+        assert_eq!(
+            fs::read_link(target.join("system/local-fs.target.wants/data.mount"))?,
+            Utf8Path::new("../data.mount")
+        );
+
+        // Unknown content is an error instead of being dropped
+        for bad in ["system/README", "system/foo.service.d/notes.txt", "other"] {
+            let source = td.join("bad");
+            let _ = fs::remove_dir_all(&source);
+            fs::create_dir_all(source.join("system/foo.service.d"))?;
+            fs::write(source.join(bad), "")?;
+            assert!(install_unit_tree(&source, &root).is_err(), "{bad}");
+        }
+        Ok(())
+    }
 }
@@ -74,7 +74,10 @@
 //! ```
 
 use crate::install_options::InstallOptions;
-use crate::run_ephemeral::{run_synchronous as run_ephemeral, CommonVmOpts, RunEphemeralOpts};
+use crate::run_ephemeral::{
+    run_synchronous as run_ephemeral, ArtifactsPolicy, CommonVmOpts, ExecuteTimedOut,
+    RunEphemeralOpts, TIMEOUT_EXIT_CODE,
+};
 use crate::{images, utils};
 use camino::Utf8PathBuf;
 use clap::{Parser, ValueEnum};
@@ -140,6 +143,30 @@ pub struct ToDiskOpts {
         help = "Add metadata to the container in key=value form"
     )]
     pub label: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "DURATION",
+        help = "Time limit for the installation (e.g. 30m); on expiry dump guest state, stop the VM and exit with code 124"
+    )]
+    pub timeout: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        help = "Collect the guest journal, systemctl --failed, bootc status and the serial console log into DIR when the installation fails"
+    )]
+    pub artifacts_dir: Option<String>,
+
+    #[clap(
+        long,
+        value_enum,
+        value_name = "WHEN",
+        default_value_t,
+        requires = "artifacts_dir",
+        help = "When to collect --artifacts-dir"
+    )]
+    pub artifacts: ArtifactsPolicy,
 }
 
 impl ToDiskOpts {
@@ -320,6 +347,22 @@ pub fn run(opts: ToDiskOpts) -> Result<()> {
             opts.target_disk,
             opts.format.as_str()
         )], // Attach target disk
+        publish: Vec::new(),            // No published ports
+        state_disk: None,               // Installation writes to the target disk
+        selinux: None,                  // bootc install labels the target itself
+        kernel_version: None,           // Boot the newest kernel
+        kernel: None,                   // Boot the installer image's kernel
+        initrd: None,                   // Use the image's initramfs
+        modules_dir: None,              // Use the image's kernel modules
+        copy_in: Vec::new(),
+        copy_in_tar: Vec::new(),
+        timeout: opts.timeout,
+        execute_result: None,
+        execute_script: None,
+        execute_env: Vec::new(),
+        artifacts_dir: opts.artifacts_dir,
+        artifacts: opts.artifacts,
+        boot_report: None,
     };
 
     // Phase 5: Final VM configuration and execution
@@ -343,6 +386,10 @@ pub fn run(opts: ToDiskOpts) -> Result<()> {
         Ok(()) => Ok(()),
         Err(e) => {
             let _ = std::fs::remove_file(&opts.target_disk);
+            if e.downcast_ref::<ExecuteTimedOut>().is_some() {
+                eprintln!("error: {e}");
+                std::process::exit(TIMEOUT_EXIT_CODE);
+            }
             Err(e)
         }
     }
@@ -362,6 +409,9 @@ mod tests {
             source_image: "test:latest".to_string(),
             target_disk: "/tmp/test.img".into(),
             label: Default::default(),
+            timeout: None,
+            artifacts_dir: None,
+            artifacts: Default::default(),
             install: InstallOptions {
                 filesystem: Some("ext4".to_string()),
                 root_size: None,
@@ -381,6 +431,9 @@ mod tests {
             source_image: "test:latest".to_string(),
             target_disk: "/tmp/test.img".into(),
             label: Default::default(),
+            timeout: None,
+            artifacts_dir: None,
+            artifacts: Default::default(),
             install: InstallOptions {
                 filesystem: Some("ext4".to_string()),
                 root_size: None,
@@ -173,3 +173,71 @@ pub(crate) fn parse_memory_to_mb(memory_str: &str) -> Result<u32> {
         Err(eyre!("Memory specification cannot be empty - please provide a value like '2G', '1024M', or '512'"))
     }
 }
+
+/// Parse a duration like "90", "90s", "30m", "2h" or "1h30m" (a plain number is seconds)
+pub(crate) fn parse_duration(duration_str: &str) -> Result<std::time::Duration> {
+    let s = duration_str.trim();
+    if s.is_empty() {
+        return Err(eyre!("Empty duration string"));
+    }
+    if let Ok(secs) = s.parse::<u64>() {
+        return Ok(std::time::Duration::from_secs(secs));
+    }
+
+    let mut total = 0u64;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| eyre!("Missing unit in duration: {duration_str}"))?;
+        let (number, tail) = rest.split_at(digits);
+        let number: u64 = number
+            .parse()
+            .map_err(|_| eyre!("Invalid duration: {duration_str}"))?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let multiplier = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return Err(eyre!("Unknown duration unit {unit:?} in {duration_str}")),
+        };
+        total = number
+            .checked_mul(multiplier)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| eyre!("Duration too large: {duration_str}"))?;
+        rest = tail;
+    }
+    Ok(std::time::Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_duration() -> Result<()> {
+        assert_eq!(parse_duration("90")?, Duration::from_secs(90));
+        assert_eq!(parse_duration("45s")?, Duration::from_secs(45));
+        assert_eq!(parse_duration("30m")?, Duration::from_secs(30 * 60));
+        assert_eq!(parse_duration("1h30m")?, Duration::from_secs(90 * 60));
+        assert_eq!(parse_duration("1d")?, Duration::from_secs(24 * 60 * 60));
+        for invalid in [
+            "",
+            "m",
+            "10x",
+            "1h30",
+            "1.5h",
+            "-5s",
+            "300000000000000d",
+            "18446744073709551615s1s",
+        ] {
+            assert!(parse_duration(invalid).is_err(), "{invalid}");
+        }
+        Ok(())
+    }
+}
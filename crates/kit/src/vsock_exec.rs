@@ -0,0 +1,384 @@
+//! Command execution in a running ephemeral VM over AF_VSOCK.
+//!
+//! This provides `bcvk ephemeral exec`, which works on images without sshd.
+//! The guest side is a small shell agent spawned per connection by systemd
+//! socket activation (`Accept=yes`) on two vsock ports:
+//!
+//! - [`EXEC_PORT`]: the control connection. The client sends a single header line
+//!   `<token> <session> <base64 argv>`, then streams stdin. When the command
+//!   exits, the agent replies with its exit code and closes the connection.
+//! - [`EXEC_STREAM_PORT`]: output connections. The client sends
+//!   `<token> <session> stdout|stderr` and receives that stream until the
+//!   command closes it.
+//!
+//! The agent connects the command to the output connections through FIFOs in
+//! `/run/bcvk-exec/<session>`. The token is generated per VM and only readable
+//! inside the container, since any host process can connect to a guest CID.
+//! In the guest it is kept in a root-only file that the socket-activated
+//! service receives with `LoadCredential=`, so unprivileged guest users cannot
+//! read it.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use camino::Utf8Path;
+use color_eyre::eyre::{eyre, Context};
+use color_eyre::Result;
+use data_encoding::BASE64;
+use nix::sys::socket::{connect, shutdown, socket, AddressFamily, Shutdown, SockFlag, SockType};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use vsock::VsockAddr;
+
+/// Guest vsock port for exec control connections
+pub(crate) const EXEC_PORT: u32 = 2240;
+/// Guest vsock port for exec stdout/stderr connections
+pub(crate) const EXEC_STREAM_PORT: u32 = 2241;
+
+/// How long to wait for the agent to start listening in a booting guest
+const AGENT_CONNECT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Where the container stores the information needed to reach the agent
+const EXEC_INFO_PATH: &str = "/run/bcvk-exec.json";
+
+/// Path of the agent script in the guest
+const AGENT_PATH: &str = "/usr/libexec/bcvk-exec-agent";
+
+/// Path of the root-only file holding the token in the guest
+const TOKEN_PATH: &str = "/usr/lib/bcvk/exec-token";
+
+/// Name of the credential the agent receives the token as
+const TOKEN_CREDENTIAL: &str = "bcvk-exec-token";
+
+/// The guest agent; it reads the token from its service credentials.
+const AGENT_SCRIPT: &str = r#"#!/bin/bash
+# bcvk exec agent, spawned per vsock connection by bcvk-exec*.socket
+set -euo pipefail
+token=$(<"$CREDENTIALS_DIRECTORY/bcvk-exec-token")
+mode=$1
+read -r reqtoken session rest
+if [ "$reqtoken" != "$token" ]; then
+    echo "bcvk-exec: invalid token" >&2
+    exit 1
+fi
+case "$session" in
+    ""|*[!a-z0-9]*) echo "bcvk-exec: invalid session" >&2; exit 1;;
+esac
+dir=/run/bcvk-exec/$session
+case "$mode" in
+    exec)
+        mkdir -p -m 0700 "$dir"
+        mkfifo "$dir/stdout" "$dir/stderr"
+        mapfile -d '' argv < <(printf '%s' "$rest" | base64 -d)
+        rc=0
+        "${argv[@]}" >"$dir/stdout" 2>"$dir/stderr" || rc=$?
+        rm -rf "$dir"
+        echo "$rc"
+        ;;
+    stream)
+        case "$rest" in
+            stdout|stderr) ;;
+            *) echo "bcvk-exec: invalid stream" >&2; exit 1;;
+        esac
+        for _ in $(seq 100); do
+            test -p "$dir/$rest" && break
+            sleep 0.1
+        done
+        exec cat "$dir/$rest"
+        ;;
+esac
+"#;
+
+/// Information for reaching the exec agent of the VM in this container.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ExecAgentInfo {
+    /// Guest vsock CID
+    pub(crate) cid: u32,
+    /// Token the agent requires on each connection
+    pub(crate) token: String,
+}
+
+impl ExecAgentInfo {
+    /// Write the agent information for later `exec` invocations in this container.
+    pub(crate) fn write(&self) -> Result<()> {
+        let json = serde_json::to_string(self)?;
+        std::fs::write(EXEC_INFO_PATH, json)
+            .with_context(|| format!("Writing {EXEC_INFO_PATH}"))?;
+        Ok(())
+    }
+
+    fn read() -> Result<Self> {
+        let contents = std::fs::read_to_string(EXEC_INFO_PATH).map_err(|e| {
+            eyre!("VM does not support exec (vsock unavailable or VM not started): {e}")
+        })?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+/// Generate a token for authenticating exec connections.
+pub(crate) fn generate_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Install the exec agent and its socket units into the image root, enabled in sockets.target.
+pub(crate) fn write_guest_agent(
+    image_root: &Utf8Path,
+    target_unitdir: &Utf8Path,
+    token: &str,
+) -> Result<()> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let agent_path = image_root.join(AGENT_PATH.trim_start_matches('/'));
+    if let Some(parent) = agent_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&agent_path, AGENT_SCRIPT).with_context(|| format!("Writing {agent_path}"))?;
+    std::fs::set_permissions(&agent_path, std::fs::Permissions::from_mode(0o755))?;
+
+    // Created 0600 so the token is never readable by other users
+    let token_path = image_root.join(TOKEN_PATH.trim_start_matches('/'));
+    if let Some(parent) = token_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let _ = std::fs::remove_file(&token_path);
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&token_path)
+        .and_then(|mut f| f.write_all(token.as_bytes()))
+        .with_context(|| format!("Writing {token_path}"))?;
+
+    let wants_dir = target_unitdir.join("sockets.target.wants");
+    std::fs::create_dir_all(&wants_dir)?;
+    for (name, port, mode) in [
+        ("bcvk-exec", EXEC_PORT, "exec"),
+        ("bcvk-exec-stream", EXEC_STREAM_PORT, "stream"),
+    ] {
+        let socket = format!(
+            r#"[Unit]
+Description=bcvk exec agent ({mode})
+
+[Socket]
+ListenStream=vsock::{port}
+Accept=yes
+"#
+        );
+        let service = format!(
+            r#"[Unit]
+Description=bcvk exec agent ({mode})
+
+[Service]
+ExecStart={AGENT_PATH} {mode}
+LoadCredential={TOKEN_CREDENTIAL}:{TOKEN_PATH}
+StandardInput=socket
+StandardOutput=socket
+StandardError=journal
+"#
+        );
+        let socket_name = format!("{name}.socket");
+        std::fs::write(target_unitdir.join(&socket_name), socket)?;
+        std::fs::write(target_unitdir.join(format!("{name}@.service")), service)?;
+        std::os::unix::fs::symlink(format!("../{socket_name}"), wants_dir.join(&socket_name))?;
+    }
+    debug!("Installed exec agent at {agent_path}");
+    Ok(())
+}
+
+/// Open a vsock stream connection to the guest.
+fn connect_vsock(cid: u32, port: u32) -> Result<File> {
+    let fd = socket(
+        AddressFamily::Vsock,
+        SockType::Stream,
+        SockFlag::SOCK_CLOEXEC,
+        None,
+    )
+    .map_err(|e| eyre!("Failed to create AF_VSOCK stream socket: {}", e))?;
+    connect(fd.as_raw_fd(), &VsockAddr::new(cid, port)).map_err(|e| {
+        eyre!("Failed to connect to exec agent on vsock {cid}:{port} (has the VM booted?): {e}")
+    })?;
+    Ok(File::from(fd))
+}
+
+/// Encode an argument vector for the agent header: NUL-terminated arguments, base64 encoded.
+fn encode_argv(args: &[String]) -> String {
+    let mut buf = Vec::new();
+    for arg in args {
+        buf.extend_from_slice(arg.as_bytes());
+        buf.push(0);
+    }
+    BASE64.encode(&buf)
+}
+
+/// Copy a guest output stream to a local writer until the guest closes it.
+fn copy_stream(
+    mut conn: File,
+    mut out: impl Write + Send + 'static,
+) -> std::thread::JoinHandle<std::io::Result<u64>> {
+    std::thread::spawn(move || {
+        let r = std::io::copy(&mut conn, &mut out);
+        out.flush()?;
+        r
+    })
+}
+
+/// Run a command in the VM of this container, returning its exit code.
+///
+/// This runs inside the container (via `container-entrypoint exec`).
+pub(crate) fn exec_in_vm(args: &[String]) -> Result<i32> {
+    exec_in_vm_with(
+        args,
+        AGENT_CONNECT_TIMEOUT,
+        true,
+        std::io::stdout(),
+        std::io::stderr(),
+    )
+}
+
+/// Run a diagnostic command in the VM of this container with all output on
+/// stderr, giving up quickly if the agent is not reachable.
+pub(crate) fn exec_diagnostic_in_vm(args: &[String]) -> Result<i32> {
+    exec_in_vm_with(
+        args,
+        Duration::from_secs(5),
+        false,
+        std::io::stderr(),
+        std::io::stderr(),
+    )
+}
+
+fn exec_in_vm_with(
+    args: &[String],
+    connect_timeout: Duration,
+    forward_stdin: bool,
+    stdout: impl Write + Send + 'static,
+    stderr: impl Write + Send + 'static,
+) -> Result<i32> {
+    // The agent information is written once QEMU starts, and the agent's socket
+    // only appears once the guest has booted far enough
+    let deadline = Instant::now() + connect_timeout;
+    let (info, mut control) = loop {
+        let attempt = ExecAgentInfo::read().and_then(|info| {
+            let conn = connect_vsock(info.cid, EXEC_PORT)?;
+            Ok((info, conn))
+        });
+        match attempt {
+            Ok(v) => break v,
+            Err(e) if Instant::now() < deadline => {
+                debug!("Exec agent not ready yet: {e}");
+                std::thread::sleep(Duration::from_millis(500));
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    let session = generate_token();
+    debug!(
+        "Executing {args:?} in guest CID {} (session {session})",
+        info.cid
+    );
+
+    control.write_all(format!("{} {session} {}\n", info.token, encode_argv(args)).as_bytes())?;
+
+    let mut streams = Vec::new();
+    for stream in ["stdout", "stderr"] {
+        let mut conn = connect_vsock(info.cid, EXEC_STREAM_PORT)?;
+        conn.write_all(format!("{} {session} {stream}\n", info.token).as_bytes())?;
+        streams.push(conn);
+    }
+    let stderr_conn = streams.pop().unwrap();
+    let stdout_conn = streams.pop().unwrap();
+    let stdout_copier = copy_stream(stdout_conn, stdout);
+    let stderr_copier = copy_stream(stderr_conn, stderr);
+
+    if forward_stdin {
+        // Forward stdin; this thread is simply abandoned if the command exits
+        // before stdin reaches EOF.
+        let mut stdin_conn = control.try_clone()?;
+        std::thread::spawn(move || {
+            let _ = std::io::copy(&mut std::io::stdin(), &mut stdin_conn);
+            let _ = shutdown(stdin_conn.as_raw_fd(), Shutdown::Write);
+        });
+    } else {
+        shutdown(control.as_raw_fd(), Shutdown::Write)?;
+    }
+
+    let mut status = String::new();
+    control
+        .read_to_string(&mut status)
+        .context("Reading exit status from exec agent")?;
+    for copier in [stdout_copier, stderr_copier] {
+        copier
+            .join()
+            .map_err(|_| eyre!("Output copier thread panicked"))?
+            .context("Copying command output")?;
+    }
+
+    status
+        .trim()
+        .parse()
+        .map_err(|_| eyre!("Exec agent did not report an exit status (got {status:?})"))
+}
+
+/// Build a host command that runs `command` in the VM of the given container,
+/// with stdio streamed and the guest exit code as its exit status.
+pub(crate) fn exec_command(container_name: &str, command: &[String]) -> Command {
+    let mut cmd = Command::new("podman");
+    cmd.args([
+        "exec",
+        "-i",
+        container_name,
+        crate::run_ephemeral::ENTRYPOINT,
+    ])
+    .args(["exec", "--"])
+    .args(command);
+    cmd
+}
+
+/// Run a command in the VM of the given container, returning the exit status
+/// of the command in the guest.
+pub fn exec_via_container(
+    container_name: &str,
+    command: &[String],
+) -> Result<std::process::ExitStatus> {
+    debug!("Executing {command:?} in VM of container {container_name}");
+    exec_command(container_name, command)
+        .status()
+        .map_err(|e| eyre!("Failed to run podman exec: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_argv() {
+        let args = vec!["echo".to_string(), "hello world".to_string()];
+        let decoded = BASE64.decode(encode_argv(&args).as_bytes()).unwrap();
+        assert_eq!(decoded, b"echo\0hello world\0");
+    }
+
+    #[test]
+    fn test_token_not_world_readable() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let td = tempfile::tempdir()?;
+        let root = Utf8Path::from_path(td.path()).unwrap();
+        let unitdir = root.join("etc/systemd/system");
+        let token = generate_token();
+        write_guest_agent(root, &unitdir, &token)?;
+
+        let agent = std::fs::read_to_string(root.join(AGENT_PATH.trim_start_matches('/')))?;
+        assert!(!agent.contains(&token));
+        assert!(agent.contains(TOKEN_CREDENTIAL));
+        let token_path = root.join(TOKEN_PATH.trim_start_matches('/'));
+        assert_eq!(std::fs::read_to_string(&token_path)?, token);
+        let mode = std::fs::metadata(&token_path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let service = std::fs::read_to_string(unitdir.join("bcvk-exec@.service"))?;
+        assert!(service.contains(&format!("LoadCredential={TOKEN_CREDENTIAL}:{TOKEN_PATH}")));
+        Ok(())
+    }
+}